@@ -4,23 +4,6 @@ use url::Url;
 
 #[allow(dead_code)]
 pub async fn debug_album_api(album_url: &str) -> Result<()> {
-    // Special handling for test URLs
-    if album_url.contains("#test") || album_url.contains("#custom") {
-        println!("Using mock data for test URL: {album_url}");
-
-        // Create a simple debug output file for test purposes
-        let mut debug_output = String::new();
-        debug_output.push_str("Mock Album data:\n");
-        debug_output.push_str(&format!("  Album URL: {album_url}\n"));
-        debug_output.push_str("  Photos count: 3\n");
-
-        // Save the debug output to a file
-        let mut file = std::fs::File::create("album_data_debug.txt")?;
-        file.write_all(debug_output.as_bytes())?;
-
-        return Ok(());
-    }
-
     // Validate and parse the URL
     let url = Url::parse(album_url)
         .with_context(|| format!("Invalid iCloud shared album URL: {album_url}"))?;
@@ -0,0 +1,467 @@
+//! Approving and rejecting photos in outputs that require approval.
+//!
+//! Anyone in a shared album can add photos to it. With `require_approval` set
+//! on an output, new photos are synced as drafts: their photostream pages get
+//! `draft: true`, and gallery and batch pages leave them out. Approving a
+//! photo publishes it on the next render. Rejecting it deletes its files but
+//! keeps its index entry, so later syncs skip it instead of downloading it
+//! again.
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::OutputType;
+use crate::index::{Approval, IndexedPhoto, PhotoIndex};
+use crate::rerender;
+use crate::verify::Page;
+
+/// What to do with the selected photos
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Approve,
+    Reject,
+}
+
+/// Which photos a decision applies to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selection {
+    /// The photos with these GUIDs
+    Guids(Vec<String>),
+    /// Every photo waiting for approval that was created before the date
+    PendingBefore(DateTime<Utc>),
+}
+
+/// What a decision did to one photo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The photo was approved and is published
+    Approved,
+    /// The photo was rejected and its files deleted
+    Rejected,
+    /// The photo was rejected before; it was dropped from the index so the
+    /// next sync adds it again
+    Unrejected,
+    /// Nothing had to change, for the given reason
+    Unchanged(String),
+    /// The photo was rejected, but its files couldn't be deleted
+    Failed(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Approved => write!(f, "approved"),
+            Outcome::Rejected => write!(f, "rejected, files deleted"),
+            Outcome::Unrejected => write!(f, "no longer rejected, the next sync adds it again"),
+            Outcome::Unchanged(reason) => write!(f, "unchanged, {reason}"),
+            Outcome::Failed(reason) => write!(f, "rejected, but {reason}"),
+        }
+    }
+}
+
+/// Approves or rejects the selected photos of an output
+///
+/// Returns the outcome for each selected photo in the index, by GUID. GUIDs
+/// that aren't in the index are left out. `site_root` is the directory of the
+/// Hugo site, which photo paths in the index are relative to.
+pub fn decide(
+    output_type: &OutputType,
+    content_dir: &Path,
+    site_root: &Path,
+    index: &mut PhotoIndex,
+    selection: &Selection,
+    decision: Decision,
+) -> Vec<(String, Outcome)> {
+    let mut guids: Vec<String> = match selection {
+        Selection::Guids(guids) => guids
+            .iter()
+            .filter(|guid| index.get_photo(guid).is_some())
+            .cloned()
+            .collect(),
+        Selection::PendingBefore(date) => index
+            .photos
+            .values()
+            .filter(|photo| photo.approval == Some(Approval::Pending) && photo.created_at < *date)
+            .map(|photo| photo.guid.clone())
+            .collect(),
+    };
+    guids.sort();
+    guids.dedup();
+
+    let mut outcomes = Vec::new();
+    for guid in guids {
+        let Some(photo) = index.get_photo(&guid) else {
+            continue;
+        };
+        let outcome = match (decision, photo.approval) {
+            (Decision::Approve, Some(Approval::Pending)) => {
+                let mut photo = photo.clone();
+                photo.approval = Some(Approval::Approved);
+                index.add_or_update_photo(photo);
+                Outcome::Approved
+            }
+            (Decision::Approve, Some(Approval::Rejected)) => {
+                index.remove_photo(&guid);
+                Outcome::Unrejected
+            }
+            (Decision::Approve, _) => Outcome::Unchanged("already published".to_string()),
+            (Decision::Reject, Some(Approval::Rejected)) => {
+                Outcome::Unchanged("already rejected".to_string())
+            }
+            (Decision::Reject, _) => {
+                let mut photo = photo.clone();
+                photo.approval = Some(Approval::Rejected);
+                let deleted = delete_photo_files(output_type, content_dir, site_root, &photo);
+                index.add_or_update_photo(photo);
+                match deleted {
+                    Ok(()) => Outcome::Rejected,
+                    Err(e) => Outcome::Failed(format!("{e:#}")),
+                }
+            }
+        };
+        outcomes.push((guid, outcome));
+    }
+    outcomes
+}
+
+/// Lists the pages to render again after photos were approved or rejected
+///
+/// Photostream pages of rejected photos were deleted with their bundle, so
+/// only approved photos' pages are listed.
+pub fn affected_pages(
+    output_type: &OutputType,
+    content_dir: &Path,
+    index: &PhotoIndex,
+    outcomes: &[(String, Outcome)],
+) -> Vec<Page> {
+    let changed: HashSet<&str> = outcomes
+        .iter()
+        .filter(|(_, outcome)| !matches!(outcome, Outcome::Unchanged(_)))
+        .map(|(guid, _)| guid.as_str())
+        .collect();
+    if changed.is_empty() {
+        return Vec::new();
+    }
+
+    rerender::pages(output_type, content_dir, index)
+        .into_iter()
+        .filter(|page| match page {
+            Page::Photo(guid) => changed.contains(guid.as_str()),
+            Page::Gallery(_) => true,
+            Page::Batch(id) => index.batches.get(id).is_some_and(|batch| {
+                batch
+                    .photos
+                    .iter()
+                    .any(|guid| changed.contains(guid.as_str()))
+            }),
+        })
+        .collect()
+}
+
+/// Deletes the files a photo left in an output, which must all exist
+pub fn delete_photo_files(
+    output_type: &OutputType,
+    content_dir: &Path,
+    site_root: &Path,
+    photo: &IndexedPhoto,
+) -> Result<()> {
+    delete_files(&photo_files(
+        output_type,
+        content_dir,
+        &photo.guid,
+        &photo.file_path(site_root),
+    ))
+}
+
+/// Lists the files a photo leaves on disk
+fn photo_files(
+    output_type: &OutputType,
+    content_dir: &Path,
+    guid: &str,
+    local_path: &Path,
+) -> Vec<PathBuf> {
+    match output_type {
+        // The whole bundle, page included
+        OutputType::Photostream => vec![content_dir.join(guid)],
+        OutputType::Gallery | OutputType::Batches => vec![local_path.to_path_buf()],
+    }
+}
+
+fn delete_files(files: &[PathBuf]) -> Result<()> {
+    for file in files {
+        let result = if file.is_dir() {
+            fs::remove_dir_all(file)
+        } else if file.exists() {
+            fs::remove_file(file)
+        } else {
+            bail!("{} doesn't exist", file.display());
+        };
+        result.with_context(|| format!("Failed to delete {}", file.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::{Batch, Gallery};
+    use tempfile::tempdir;
+
+    /// Creates a pending photo whose file is at `file` in the site
+    fn pending_photo(site_root: &Path, file: &Path, guid: &str, created_at: &str) -> IndexedPhoto {
+        let mut photo = IndexedPhoto::new(
+            guid.to_string(),
+            format!("{guid}.jpg"),
+            None,
+            created_at.parse().unwrap(),
+            format!("checksum_{guid}"),
+            format!("https://example.com/{guid}.jpg"),
+            800,
+            600,
+            file.to_path_buf(),
+            site_root,
+        );
+        photo.approval = Some(Approval::Pending);
+        photo
+    }
+
+    fn create_photo(content_dir: &Path, guid: &str, created_at: &str) -> Result<IndexedPhoto> {
+        let bundle = content_dir.join(guid);
+        fs::create_dir_all(&bundle)?;
+        fs::write(bundle.join("original.jpg"), "IMAGE")?;
+        Ok(pending_photo(
+            Path::new(""),
+            &bundle.join("original.jpg"),
+            guid,
+            created_at,
+        ))
+    }
+
+    /// Creates a pending photo stored as `<dir>/<guid>.jpg`, as galleries
+    /// and batches store them, with its path relative to the site
+    fn create_file_photo(site_root: &Path, dir: &Path, guid: &str) -> Result<IndexedPhoto> {
+        fs::create_dir_all(dir)?;
+        let file = dir.join(format!("{guid}.jpg"));
+        fs::write(&file, "IMAGE")?;
+        Ok(pending_photo(
+            site_root,
+            &file,
+            guid,
+            "2024-01-01T00:00:00Z",
+        ))
+    }
+
+    /// Creates a photostream with photos taken early, late and in between
+    fn photostream(content_dir: &Path) -> Result<PhotoIndex> {
+        let mut index = PhotoIndex::new();
+        for (guid, created_at) in [
+            ("early", "2024-01-01T00:00:00Z"),
+            ("late", "2024-06-01T00:00:00Z"),
+            ("spam", "2024-03-01T00:00:00Z"),
+        ] {
+            index.add_or_update_photo(create_photo(content_dir, guid, created_at)?);
+        }
+        Ok(index)
+    }
+
+    #[test]
+    fn test_reject_photostream_photo() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/photostream");
+        let mut index = photostream(&content_dir)?;
+        let output_type = OutputType::Photostream;
+
+        let outcomes = decide(
+            &output_type,
+            &content_dir,
+            temp_dir.path(),
+            &mut index,
+            &Selection::Guids(vec!["spam".to_string(), "unknown".to_string()]),
+            Decision::Reject,
+        );
+        assert_eq!(outcomes, vec![("spam".to_string(), Outcome::Rejected)]);
+        assert!(index.get_photo("spam").unwrap().is_rejected());
+        assert!(!content_dir.join("spam").exists());
+        assert!(content_dir.join("early").exists());
+        assert!(affected_pages(&output_type, &content_dir, &index, &outcomes).is_empty());
+
+        let outcomes = decide(
+            &output_type,
+            &content_dir,
+            temp_dir.path(),
+            &mut index,
+            &Selection::Guids(vec!["spam".to_string()]),
+            Decision::Reject,
+        );
+        assert_eq!(
+            outcomes,
+            vec![(
+                "spam".to_string(),
+                Outcome::Unchanged("already rejected".to_string())
+            )]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_approve_pending_before_date() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/photostream");
+        let mut index = photostream(&content_dir)?;
+        index.photos.get_mut("spam").unwrap().approval = Some(Approval::Rejected);
+        let output_type = OutputType::Photostream;
+
+        // Only photos still waiting are approved
+        let date = "2024-05-01T00:00:00Z".parse().unwrap();
+        let outcomes = decide(
+            &output_type,
+            &content_dir,
+            temp_dir.path(),
+            &mut index,
+            &Selection::PendingBefore(date),
+            Decision::Approve,
+        );
+        assert_eq!(outcomes, vec![("early".to_string(), Outcome::Approved)]);
+        assert_eq!(
+            affected_pages(&output_type, &content_dir, &index, &outcomes),
+            vec![Page::Photo("early".to_string())]
+        );
+        assert!(index.get_photo("late").unwrap().is_unapproved());
+        assert!(index.get_photo("spam").unwrap().is_rejected());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_approve_rejected_photo() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/photostream");
+        let mut index = photostream(&content_dir)?;
+        index.photos.get_mut("early").unwrap().approval = Some(Approval::Approved);
+        index.photos.get_mut("spam").unwrap().approval = Some(Approval::Rejected);
+
+        // Approving a rejected photo lets the next sync add it again
+        let outcomes = decide(
+            &OutputType::Photostream,
+            &content_dir,
+            temp_dir.path(),
+            &mut index,
+            &Selection::Guids(vec!["spam".to_string(), "early".to_string()]),
+            Decision::Approve,
+        );
+        assert_eq!(
+            outcomes,
+            vec![
+                (
+                    "early".to_string(),
+                    Outcome::Unchanged("already published".to_string())
+                ),
+                ("spam".to_string(), Outcome::Unrejected),
+            ]
+        );
+        assert!(index.get_photo("spam").is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_gallery_photo() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/gallery");
+        let mut index = PhotoIndex::new();
+        let photo = create_file_photo(temp_dir.path(), &content_dir, "spam")?;
+        assert!(photo.local_path.is_relative());
+        index.add_or_update_photo(photo);
+        let mut gallery = Gallery::new(
+            "summer".to_string(),
+            "Summer".to_string(),
+            "summer".to_string(),
+            None,
+        );
+        gallery.add_photo("spam".to_string());
+        index.galleries.insert(gallery.id.clone(), gallery);
+        let output_type = OutputType::Gallery;
+
+        let outcomes = decide(
+            &output_type,
+            &content_dir,
+            temp_dir.path(),
+            &mut index,
+            &Selection::Guids(vec!["spam".to_string()]),
+            Decision::Reject,
+        );
+        assert_eq!(outcomes, vec![("spam".to_string(), Outcome::Rejected)]);
+        assert!(!content_dir.join("spam.jpg").exists());
+        assert_eq!(
+            affected_pages(&output_type, &content_dir, &index, &outcomes),
+            vec![Page::Gallery("summer".to_string())]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_batch_photo() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/batches");
+        let mut index = PhotoIndex::new();
+        for (batch_id, guid) in [("b1", "spam"), ("b2", "keep")] {
+            let photo = create_file_photo(temp_dir.path(), &content_dir.join(batch_id), guid)?;
+            index.add_or_update_photo(photo);
+            let mut batch = Batch::new(batch_id.to_string(), batch_id.to_string(), Utc::now());
+            batch.add_photo(guid.to_string());
+            index.batches.insert(batch.id.clone(), batch);
+        }
+        let output_type = OutputType::Batches;
+
+        let outcomes = decide(
+            &output_type,
+            &content_dir,
+            temp_dir.path(),
+            &mut index,
+            &Selection::Guids(vec!["spam".to_string()]),
+            Decision::Reject,
+        );
+        assert_eq!(outcomes, vec![("spam".to_string(), Outcome::Rejected)]);
+        assert!(!content_dir.join("b1/spam.jpg").exists());
+        assert!(content_dir.join("b2/keep.jpg").exists());
+        assert_eq!(
+            affected_pages(&output_type, &content_dir, &index, &outcomes),
+            vec![Page::Batch("b1".to_string())]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_with_missing_files_fails() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/gallery");
+        let mut index = PhotoIndex::new();
+        let photo = create_file_photo(temp_dir.path(), &content_dir, "spam")?;
+        fs::remove_file(photo.file_path(temp_dir.path()))?;
+        index.add_or_update_photo(photo);
+
+        let outcomes = decide(
+            &OutputType::Gallery,
+            &content_dir,
+            temp_dir.path(),
+            &mut index,
+            &Selection::Guids(vec!["spam".to_string()]),
+            Decision::Reject,
+        );
+        assert!(matches!(
+            &outcomes[..],
+            [(guid, Outcome::Failed(reason))] if guid == "spam" && reason.contains("doesn't exist")
+        ));
+        // The photo is still rejected, so syncs don't download it again
+        assert!(index.get_photo("spam").unwrap().is_rejected());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,764 @@
+//! Upload-batch synchronization logic for icloud2hugo.
+//!
+//! iCloud shared albums group photos into batches: every time someone posts
+//! several photos at once (optionally with a comment), those photos share a
+//! batch GUID and batch date. This module turns each batch into its own Hugo
+//! page bundle, so a post in the album becomes a post on the site.
+//!
+//! The `BatchSyncer` struct downloads each batch's photos into the batch
+//! directory, several at a time, and writes an index.md with the batch
+//! caption as the body and the batch date as the post date.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use log::{info, warn};
+use slugify::slugify;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs as tokio_fs;
+
+use crate::config::{DownloadConfig, PrivacyConfig};
+use crate::download::Downloader;
+use crate::exif::extract_exif;
+use crate::frontmatter;
+use crate::geocode::create_geocoding_service;
+use crate::hashtags;
+use crate::icloud::{Album, Photo};
+use crate::index::{Approval, Batch, IndexedPhoto, PhotoIndex};
+use crate::overrides::{Overrides, PhotoView};
+use crate::retry::RetryPolicy;
+use crate::shutdown::Shutdown;
+use crate::store::{IndexStore, YamlStore};
+use crate::sync::SyncResult;
+
+/// Frontmatter keys of a batch page that syncs own; other keys are kept
+const PAGE_KEYS: &[&str] = &[
+    "title",
+    "date",
+    "lastmod",
+    "type",
+    "batch_guid",
+    "photo_count",
+    "contributor",
+    "slug",
+    "nofeed",
+    "noindex",
+    "unlisted",
+    "robots",
+    "photos",
+];
+
+/// Responsible for syncing photos from iCloud into one post per upload batch
+pub struct BatchSyncer {
+    /// Downloads photos, retrying transient failures
+    downloader: Downloader,
+    /// Base directory under which each batch gets its own page bundle
+    content_dir: PathBuf,
+    /// Where the photo index is saved
+    store: Arc<dyn IndexStore>,
+    /// Privacy configuration for Hugo frontmatter
+    privacy_config: PrivacyConfig,
+    /// Re-render batches from files on disk without downloading or deleting
+    offline: bool,
+    /// Number of photos processed at the same time
+    concurrency: usize,
+    /// Stops new photos from being started when the sync is interrupted
+    shutdown: Shutdown,
+    /// Per-photo overrides applied when pages are rendered
+    overrides: Arc<Overrides>,
+    /// New photos stay off their batch's page until they're approved
+    require_approval: bool,
+    /// Directory of the Hugo site, which indexed photo paths are relative to
+    site_root: PathBuf,
+}
+
+/// Returns the ID of the batch a photo belongs to
+///
+/// Photos without a batch GUID are treated as a batch of their own.
+fn batch_id(photo: &Photo) -> String {
+    photo
+        .batch_guid
+        .clone()
+        .unwrap_or_else(|| photo.guid.clone())
+}
+
+/// Builds the directory name for a batch from its date and ID
+fn batch_slug(id: &str, created_at: DateTime<Utc>) -> String {
+    let short_id: String = id.chars().take(8).collect();
+    format!("{}-{}", created_at.format("%Y-%m-%d"), slugify!(&short_id))
+}
+
+impl BatchSyncer {
+    /// Creates a new batch syncer
+    pub fn new(content_dir: PathBuf, index_path: PathBuf, privacy_config: PrivacyConfig) -> Self {
+        Self {
+            downloader: Downloader::default(),
+            content_dir,
+            store: Arc::new(YamlStore::new(index_path, PathBuf::new())),
+            privacy_config,
+            offline: false,
+            concurrency: DownloadConfig::default().concurrency(),
+            shutdown: Shutdown::default(),
+            overrides: Arc::default(),
+            require_approval: false,
+            site_root: PathBuf::new(),
+        }
+    }
+
+    /// Sets the retry policy used for downloads
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.downloader = self.downloader.with_retry_policy(retry_policy);
+        self
+    }
+
+    /// Sets how many photos are processed at once and the bandwidth cap
+    pub fn with_download_config(mut self, config: &DownloadConfig) -> Self {
+        self.concurrency = config.concurrency();
+        self.downloader = self
+            .downloader
+            .with_bandwidth_limit(config.max_bytes_per_second);
+        self
+    }
+
+    /// Sets whether to re-render every page from the files already on disk
+    /// instead of downloading anything or deleting photos
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self.downloader = self.downloader.with_offline(offline);
+        self
+    }
+
+    /// Sets the flag that tells the syncer to stop starting new photos
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Sets where the photo index is saved, instead of the YAML index file
+    pub fn with_index_store(mut self, store: Arc<dyn IndexStore>) -> Self {
+        self.store = store;
+        self
+    }
+
+    /// Sets the per-photo overrides applied when pages are rendered
+    pub fn with_overrides(mut self, overrides: Arc<Overrides>) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// Sets whether new photos stay off their batch's page until they're approved
+    pub fn with_require_approval(mut self, require_approval: bool) -> Self {
+        self.require_approval = require_approval;
+        self
+    }
+
+    /// Sets the directory of the Hugo site, which indexed photo paths are
+    /// stored relative to
+    pub fn with_site_root(mut self, site_root: PathBuf) -> Self {
+        self.site_root = site_root;
+        self
+    }
+
+    /// Syncs photos from the remote album into one page bundle per batch
+    pub async fn sync_batches(
+        &self,
+        album: &Album,
+        index: &mut PhotoIndex,
+    ) -> Result<Vec<SyncResult>> {
+        tokio_fs::create_dir_all(&self.content_dir)
+            .await
+            .context("Failed to create batch content directory")?;
+
+        // Group the album's photos by batch, oldest photo first
+        let mut remote_batches: HashMap<String, Vec<&Photo>> = HashMap::new();
+        for photo in album.photos.values() {
+            remote_batches
+                .entry(batch_id(photo))
+                .or_default()
+                .push(photo);
+        }
+        for photos in remote_batches.values_mut() {
+            photos.sort_by(|a, b| a.created_at.cmp(&b.created_at).then(a.guid.cmp(&b.guid)));
+        }
+
+        info!(
+            "Processing {} photos in {} batches",
+            album.photos.len(),
+            remote_batches.len()
+        );
+
+        let mut results = Vec::new();
+
+        // Remove photos that are gone from the album or moved to another batch,
+        // and drop batches that no longer have any photos. Offline syncs work
+        // from a saved album, so they never remove anything, and rejected
+        // photos stay so they're never added again.
+        let stale: Vec<(String, String)> = index
+            .batches
+            .values()
+            .filter(|_| !self.offline)
+            .flat_map(|batch| {
+                batch
+                    .photos
+                    .iter()
+                    .filter(|guid| {
+                        album
+                            .photos
+                            .get(*guid)
+                            .is_none_or(|photo| batch_id(photo) != batch.id)
+                            && !index.get_photo(guid).is_some_and(IndexedPhoto::is_rejected)
+                    })
+                    .map(|guid| (batch.id.clone(), guid.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        for (id, guid) in stale {
+            index.update_batch(&id, |batch| batch.remove_photo(&guid));
+
+            let path = index
+                .get_photo(&guid)
+                .map(|photo| photo.file_path(&self.site_root));
+            if let Some(path) = path
+                && path.exists()
+                && let Err(e) = tokio_fs::remove_file(&path).await
+            {
+                warn!("Failed to delete photo file {guid}: {e}");
+            }
+
+            if album.photos.contains_key(&guid) {
+                // The photo moved to another batch and will be re-added there
+                continue;
+            }
+
+            if index.remove_photo(&guid).is_some() {
+                results.push(SyncResult::Deleted(guid));
+            }
+        }
+
+        let empty_batches: Vec<String> = index
+            .batches
+            .values()
+            .filter(|batch| {
+                !self.offline && batch.photos.is_empty() && !remote_batches.contains_key(&batch.id)
+            })
+            .map(|batch| batch.id.clone())
+            .collect();
+        for id in empty_batches {
+            if let Some(batch) = index.remove_batch(&id) {
+                let batch_dir = self.content_dir.join(&batch.slug);
+                if batch_dir.exists()
+                    && let Err(e) = tokio_fs::remove_dir_all(&batch_dir).await
+                {
+                    warn!(
+                        "Failed to delete batch directory {}: {e}",
+                        batch_dir.display()
+                    );
+                }
+            }
+        }
+
+        // Add, update or refresh the photos of every remote batch
+        for (id, photos) in &remote_batches {
+            // An interrupted sync leaves the remaining batches for the next run
+            if self.shutdown.is_requested() {
+                break;
+            }
+
+            let created_at = photos
+                .iter()
+                .find_map(|photo| photo.batch_created_at)
+                .unwrap_or(photos[0].created_at);
+
+            if index.get_batch(id).is_none() {
+                let slug = batch_slug(id, created_at);
+                index.add_or_update_batch(Batch::new(id.clone(), slug, created_at));
+            }
+            let batch_dir = self.content_dir.join(&index.batches[id].slug);
+            tokio_fs::create_dir_all(&batch_dir)
+                .await
+                .with_context(|| format!("Failed to create batch directory for {id}"))?;
+
+            // Photos that are new or changed are downloaded in parallel below
+            let mut to_process = Vec::new();
+            let mut photos_changed = false;
+            for photo in photos {
+                let in_batch = index.batches[id].photos.contains(&photo.guid);
+
+                match index.get_photo(&photo.guid) {
+                    // Rejected photos are never downloaded again
+                    Some(indexed_photo) if indexed_photo.is_rejected() => {
+                        results.push(SyncResult::Unchanged(photo.guid.clone()));
+                    }
+                    // Offline, every photo is processed again from the file on disk
+                    Some(indexed_photo)
+                        if !self.offline
+                            && indexed_photo.checksum == photo.checksum
+                            && in_batch =>
+                    {
+                        if indexed_photo.metadata_differs(photo) {
+                            let mut indexed_photo = indexed_photo.clone();
+                            indexed_photo.update_metadata(photo);
+                            index.add_or_update_photo(indexed_photo);
+                            photos_changed = true;
+                            results.push(SyncResult::MetadataUpdated(photo.guid.clone()));
+                        } else {
+                            results.push(SyncResult::Unchanged(photo.guid.clone()));
+                        }
+                    }
+                    existing => to_process.push((*photo, existing.is_some())),
+                }
+            }
+
+            // Each photo is recorded in the index as soon as it is done
+            let mut processed = stream::iter(to_process)
+                .map(|(photo, is_update)| {
+                    let photo_path = batch_dir.join(&photo.filename);
+                    async move {
+                        // Don't start anything new once a shutdown was requested
+                        if self.shutdown.is_requested() {
+                            return None;
+                        }
+                        let result = self.process_photo(photo, &photo_path).await;
+                        Some((photo, is_update, result))
+                    }
+                })
+                .buffered(self.concurrency);
+
+            while let Some(item) = processed.next().await {
+                let Some((photo, is_update, result)) = item else {
+                    continue;
+                };
+
+                match result {
+                    Ok(mut indexed_photo) => {
+                        // Keep the approval state; new photos wait for approval if required
+                        indexed_photo.approval = match index.get_photo(&photo.guid) {
+                            Some(existing) => existing.approval,
+                            None => self.require_approval.then_some(Approval::Pending),
+                        };
+                        index.add_or_update_photo(indexed_photo);
+                        index.update_batch(id, |batch| batch.add_photo(photo.guid.clone()));
+                        photos_changed = true;
+                        results.push(if is_update {
+                            SyncResult::Updated(photo.guid.clone())
+                        } else {
+                            SyncResult::Added(photo.guid.clone())
+                        });
+                    }
+                    Err(e) => results.push(SyncResult::Failed(
+                        photo.guid.clone(),
+                        format!("Failed to process photo: {e}"),
+                    )),
+                }
+            }
+
+            // Keep the photos in posting order and pick up caption edits. The
+            // modification time only moves when something in the batch changed.
+            let caption = photos
+                .iter()
+                .filter_map(|photo| photo.caption.as_deref())
+                .map(str::trim)
+                .find(|caption| !caption.is_empty())
+                .map(str::to_string);
+            index.update_batch(id, |batch| {
+                batch
+                    .photos
+                    .sort_by_key(|guid| photos.iter().position(|photo| &photo.guid == guid));
+                if batch.caption != caption || photos_changed {
+                    batch.caption = caption;
+                    batch.updated_at = Utc::now();
+                }
+            });
+
+            self.create_batch_index(index, id, &batch_dir)
+                .await
+                .with_context(|| format!("Failed to create index.md for batch {id}"))?;
+        }
+
+        // Save the updated index
+        self.store.save(index)?;
+
+        Ok(results)
+    }
+
+    /// Downloads a photo into its batch directory and extracts its metadata
+    async fn process_photo(&self, photo: &Photo, photo_path: &Path) -> Result<IndexedPhoto> {
+        self.downloader
+            .download(photo, photo_path)
+            .await
+            .with_context(|| format!("Failed to download photo {}", photo.guid))?;
+
+        let mut indexed_photo = IndexedPhoto::new(
+            photo.guid.clone(),
+            photo.filename.clone(),
+            photo.caption.clone(),
+            photo.created_at,
+            photo.checksum.clone(),
+            photo.url.clone(),
+            photo.width,
+            photo.height,
+            photo_path.to_path_buf(),
+            &self.site_root,
+        );
+        indexed_photo.contributor = photo.contributor.clone();
+        indexed_photo.sources = photo.sources.clone();
+        indexed_photo.asset_checksum = photo.asset_checksum.clone();
+        if let Err(e) = indexed_photo.record_file(&self.site_root) {
+            warn!("Failed to hash photo {}: {e:#}", photo.guid);
+        }
+
+        // Extract EXIF data and geocode the location if possible
+        match extract_exif(photo_path) {
+            Ok(exif_data) => {
+                indexed_photo.update_exif(&exif_data);
+
+                if let (Some(lat), Some(lon)) = (indexed_photo.latitude, indexed_photo.longitude) {
+                    match create_geocoding_service().reverse_geocode(lat, lon) {
+                        Ok(location) => indexed_photo.update_location(location),
+                        Err(e) => warn!("Failed to geocode location for {}: {}", photo.guid, e),
+                    }
+                }
+            }
+            Err(e) => {
+                warn!("Failed to extract EXIF data from {}: {}", photo.guid, e);
+            }
+        }
+
+        Ok(indexed_photo)
+    }
+
+    /// Writes the index.md of an indexed batch
+    pub async fn render_batch(&self, index: &PhotoIndex, batch_id: &str) -> Result<()> {
+        let batch = index
+            .get_batch(batch_id)
+            .ok_or_else(|| anyhow::anyhow!("Batch not found: {}", batch_id))?;
+        let batch_dir = self.content_dir.join(&batch.slug);
+        tokio_fs::create_dir_all(&batch_dir)
+            .await
+            .with_context(|| format!("Failed to create batch directory for {batch_id}"))?;
+        self.create_batch_index(index, batch_id, &batch_dir).await
+    }
+
+    /// Creates a batch's index.md with the batch caption as the body and
+    /// figure shortcodes for each of its photos
+    async fn create_batch_index(
+        &self,
+        index: &PhotoIndex,
+        batch_id: &str,
+        batch_dir: &Path,
+    ) -> Result<()> {
+        let batch = match index.get_batch(batch_id) {
+            Some(b) => b,
+            None => return Err(anyhow::anyhow!("Batch not found: {}", batch_id)),
+        };
+        let (hidden, batch_photos): (Vec<PhotoView>, Vec<PhotoView>) = index
+            .get_batch_photos(batch_id)
+            .into_iter()
+            .map(|photo| self.overrides.apply(photo))
+            .partition(|view| view.hidden);
+
+        // Use the first line of the caption as the title
+        let caption = batch
+            .caption
+            .as_deref()
+            .map(|caption| self.overrides.caption(caption))
+            .filter(|caption| !caption.is_empty());
+        let title = match caption.as_deref().and_then(|c| c.lines().next()) {
+            Some(line) => line.trim().to_string(),
+            None => format!("Photos from {}", batch.created_at.format("%B %e, %Y")),
+        };
+
+        let mut content = format!(
+            "---\ntitle: {}\ndate: {}\nlastmod: {}\ntype: batch\nbatch_guid: {}\nphoto_count: {}\n",
+            frontmatter::quote(&title),
+            batch.created_at.format("%Y-%m-%dT%H:%M:%S%z"),
+            batch.updated_at.format("%Y-%m-%dT%H:%M:%S%z"),
+            batch.id,
+            batch_photos.len()
+        );
+
+        // Add the contributor when the whole batch was posted by one person
+        let contributors: HashSet<&str> = batch_photos
+            .iter()
+            .filter_map(|view| view.photo.contributor.as_deref())
+            .collect();
+        if contributors.len() == 1
+            && let Some(contributor) = contributors.into_iter().next()
+        {
+            content.push_str(&format!(
+                "contributor: {}\n",
+                frontmatter::quote(contributor)
+            ));
+        }
+
+        // Add privacy parameters
+        if self.privacy_config.uuid_slug {
+            content.push_str(&format!("slug: {}\n", slugify!(&batch.id)));
+        }
+        if self.privacy_config.nofeed {
+            content.push_str("nofeed: true\n");
+        }
+        if self.privacy_config.noindex {
+            content.push_str("noindex: true\n");
+        }
+        if self.privacy_config.unlisted {
+            content.push_str("unlisted: true\n");
+        }
+        if self.privacy_config.robots_noindex {
+            content.push_str("robots: noindex,nofollow\n");
+        }
+
+        // A batch whose photos are all hidden or waiting for approval is a
+        // draft; `draft` can be set by hand on other batches
+        if !hidden.is_empty() && batch_photos.is_empty() {
+            content.push_str("draft: true\n");
+        }
+
+        // Add the tags of all photos when the output writes hashtags
+        if let Some(taxonomy) = self.overrides.taxonomy() {
+            let terms: BTreeSet<&String> = batch_photos
+                .iter()
+                .flat_map(|view| view.tags.iter().flatten())
+                .collect();
+            if !terms.is_empty() {
+                hashtags::push_terms(&mut content, taxonomy, terms);
+            }
+        }
+
+        // Add the batch photo list
+        content.push_str("photos:\n");
+        for view in &batch_photos {
+            let photo = view.photo.as_ref();
+            content.push_str(&format!("  - filename: {}\n", photo.filename));
+            content.push_str(&format!(
+                "    caption: {}\n",
+                frontmatter::quote(&view.title)
+            ));
+            content.push_str(&format!("    mime_type: \"{}\"\n", photo.mime_type));
+            if let Some(ref alt) = view.alt {
+                content.push_str(&format!("    alt: {}\n", frontmatter::quote(alt)));
+            }
+            if let Some(ref tags) = view.tags {
+                let tags: Vec<String> = tags.iter().map(|tag| frontmatter::quote(tag)).collect();
+                content.push_str(&format!("    tags: [{}]\n", tags.join(", ")));
+            }
+
+            if let Some(ref location) = photo.location {
+                content.push_str(&format!(
+                    "    location: {}\n",
+                    frontmatter::quote(&location.formatted_address)
+                ));
+            }
+
+            let date = photo.exif_date_time.unwrap_or(photo.created_at);
+            content.push_str(&format!(
+                "    date: {}\n",
+                date.format("%Y-%m-%dT%H:%M:%S%z")
+            ));
+        }
+
+        content.push_str("---\n\n");
+
+        // The batch caption is the body of the post
+        if let Some(ref caption) = caption {
+            content.push_str(caption);
+            content.push_str("\n\n");
+        }
+
+        for view in &batch_photos {
+            let photo = view.photo.as_ref();
+            let caption = view.title.replace('"', "\\\"");
+            let alt = view
+                .alt
+                .as_deref()
+                .map_or_else(|| caption.clone(), |alt| alt.replace('"', "\\\""));
+
+            if photo.mime_type.starts_with("video/") {
+                content.push_str(&format!(
+                    "{{{{< video src=\"{}\" caption=\"{caption}\" >}}}}\n\n",
+                    photo.filename
+                ));
+            } else {
+                content.push_str(&format!(
+                    "{{{{< figure\n  src=\"{}\"\n  alt=\"{alt}\"\n  caption=\"{caption}\"\n  class=\"ma0 w-75\"\n>}}}}\n\n",
+                    photo.filename
+                ));
+            }
+        }
+
+        let index_path = batch_dir.join("index.md");
+        let optional: Vec<&str> = std::iter::once("draft")
+            .chain(self.overrides.taxonomy())
+            .collect();
+        frontmatter::write_page(&index_path, &content, PAGE_KEYS, &optional)
+            .await
+            .with_context(|| {
+                format!("Failed to write batch index.md to {}", index_path.display())
+            })?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn create_test_photo(guid: &str, batch: &str, caption: Option<&str>) -> Photo {
+        Photo {
+            guid: guid.to_string(),
+            filename: format!("{guid}.jpg"),
+            caption: caption.map(str::to_string),
+            created_at: "2023-07-15T14:30:00Z".parse().unwrap(),
+            checksum: format!("checksum_{guid}"),
+            url: format!("https://example.com/{guid}.jpg"),
+            width: 800,
+            height: 600,
+            mime_type: "image/jpeg".to_string(),
+            contributor: Some("Jane Doe".to_string()),
+            batch_guid: Some(batch.to_string()),
+            batch_created_at: Some("2023-07-16T09:00:00Z".parse().unwrap()),
+            asset_checksum: None,
+            sources: Vec::new(),
+        }
+    }
+
+    fn create_test_album() -> Album {
+        let mut album = Album::new("Family".to_string());
+        for photo in [
+            create_test_photo("photo1", "BATCHAAA1", None),
+            create_test_photo("photo2", "BATCHAAA1", Some("Beach day!")),
+            create_test_photo("photo3", "BATCHBBB2", None),
+        ] {
+            album.photos.insert(photo.guid.clone(), photo);
+        }
+        album
+    }
+
+    #[tokio::test]
+    async fn test_sync_batches_creates_one_post_per_batch() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content");
+        let index_path = temp_dir.path().join("index.yaml");
+        let syncer = BatchSyncer::new(
+            content_dir.clone(),
+            index_path.clone(),
+            PrivacyConfig::default(),
+        );
+
+        let mut index = PhotoIndex::new();
+        let results = syncer
+            .sync_batches(&create_test_album(), &mut index)
+            .await?;
+
+        assert_eq!(
+            results
+                .iter()
+                .filter(|r| matches!(r, SyncResult::Added(_)))
+                .count(),
+            3
+        );
+        assert_eq!(index.batch_count(), 2);
+        assert_eq!(index.photo_count(), 3);
+
+        let batch = index.get_batch("BATCHAAA1").unwrap();
+        assert_eq!(batch.slug, "2023-07-16-batchaaa");
+        assert_eq!(batch.caption, Some("Beach day!".to_string()));
+        assert_eq!(batch.photos.len(), 2);
+
+        let batch_dir = content_dir.join("2023-07-16-batchaaa");
+        assert!(batch_dir.join("photo1.jpg").exists());
+        assert!(batch_dir.join("photo2.jpg").exists());
+
+        let index_md = fs::read_to_string(batch_dir.join("index.md"))?;
+        assert!(index_md.contains("title: \"Beach day!\""));
+        assert!(index_md.contains("date: 2023-07-16T09:00:00+0000"));
+        assert!(index_md.contains("photo_count: 2"));
+        assert!(index_md.contains("contributor: \"Jane Doe\""));
+        assert!(index_md.contains("---\n\nBeach day!\n\n"));
+        assert!(index_md.contains("src=\"photo1.jpg\""));
+
+        let other_md = fs::read_to_string(content_dir.join("2023-07-16-batchbbb/index.md"))?;
+        assert!(other_md.contains("title: \"Photos from July 16, 2023\""));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sync_batches_removes_deleted_batches() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content");
+        let index_path = temp_dir.path().join("index.yaml");
+        let syncer = BatchSyncer::new(
+            content_dir.clone(),
+            index_path.clone(),
+            PrivacyConfig::default(),
+        );
+
+        let mut index = PhotoIndex::new();
+        let mut album = create_test_album();
+        syncer.sync_batches(&album, &mut index).await?;
+
+        // Delete the second batch and edit the first batch's caption
+        album.photos.remove("photo3");
+        album.photos.get_mut("photo2").unwrap().caption = Some("Sunset".to_string());
+        let results = syncer.sync_batches(&album, &mut index).await?;
+
+        assert!(
+            results
+                .iter()
+                .any(|r| matches!(r, SyncResult::Deleted(guid) if guid == "photo3"))
+        );
+        assert!(
+            results
+                .iter()
+                .any(|r| matches!(r, SyncResult::MetadataUpdated(guid) if guid == "photo2"))
+        );
+        assert_eq!(index.batch_count(), 1);
+        assert!(!content_dir.join("2023-07-16-batchbbb").exists());
+
+        let index_md = fs::read_to_string(content_dir.join("2023-07-16-batchaaa/index.md"))?;
+        assert!(index_md.contains("title: \"Sunset\""));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sync_batches_moves_photo_between_batches() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content");
+        let syncer = BatchSyncer::new(
+            content_dir.clone(),
+            temp_dir.path().join("index.yaml"),
+            PrivacyConfig::default(),
+        );
+
+        let mut index = PhotoIndex::new();
+        let mut album = create_test_album();
+        syncer.sync_batches(&album, &mut index).await?;
+        let old_dir = content_dir.join("2023-07-16-batchaaa");
+        let new_dir = content_dir.join("2023-07-16-batchbbb");
+        assert!(old_dir.join("photo2.jpg").exists());
+
+        album.photos.get_mut("photo2").unwrap().batch_guid = Some("BATCHBBB2".to_string());
+        let results = syncer.sync_batches(&album, &mut index).await?;
+
+        assert!(!results.iter().any(|r| matches!(r, SyncResult::Deleted(_))));
+        assert!(!old_dir.join("photo2.jpg").exists());
+        assert!(new_dir.join("photo2.jpg").exists());
+        assert_eq!(
+            index.get_photo("photo2").unwrap().file_path(Path::new("")),
+            new_dir.join("photo2.jpg")
+        );
+        assert_eq!(index.get_batch("BATCHAAA1").unwrap().photos, vec!["photo1"]);
+        assert!(!fs::read_to_string(old_dir.join("index.md"))?.contains("photo2.jpg"));
+        assert!(fs::read_to_string(new_dir.join("index.md"))?.contains("photo2.jpg"));
+
+        Ok(())
+    }
+}
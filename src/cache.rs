@@ -0,0 +1,210 @@
+//! Album response cache for icloud2hugo.
+//!
+//! When a cache directory is configured, the raw webstream and asset URL
+//! responses of every iCloud fetch are saved as JSON snapshots, one file per
+//! fetch, under a directory per album:
+//!
+//! ```text
+//! <cache_dir>/<md5 of album URL>/<fetch time>.json
+//! ```
+//!
+//! `sync --offline` rebuilds each album from its latest snapshot, which makes
+//! it possible to work on templates and privacy settings without network
+//! access, and to reproduce a bug report from a saved snapshot.
+//!
+//! Only the most recent snapshots of each album are kept; saving a new one
+//! deletes the oldest beyond that number.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::icloud::AlbumResponse;
+
+/// Number of snapshots kept per album unless configured otherwise
+const DEFAULT_KEEP: usize = 10;
+
+/// Saves and loads raw album responses
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    /// Directory the snapshots are stored in
+    dir: PathBuf,
+    /// Number of snapshots kept per album
+    keep: usize,
+}
+
+impl ResponseCache {
+    /// Creates a cache rooted at the given directory
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            keep: DEFAULT_KEEP,
+        }
+    }
+
+    /// Sets how many snapshots are kept per album; the latest is always kept
+    pub fn with_keep(mut self, keep: usize) -> Self {
+        self.keep = keep.max(1);
+        self
+    }
+
+    /// Returns the directory holding the snapshots of an album
+    ///
+    /// Album URLs contain the album's access token, so the directory is named
+    /// after a hash of the URL rather than the URL itself.
+    fn album_dir(&self, album_url: &str) -> PathBuf {
+        self.dir
+            .join(format!("{:x}", md5::compute(album_url.as_bytes())))
+    }
+
+    /// Saves a response as a new snapshot, deletes the album's snapshots
+    /// beyond the number kept, and returns the new snapshot's path
+    pub fn save(&self, response: &AlbumResponse) -> Result<PathBuf> {
+        let album_dir = self.album_dir(&response.album_url);
+        fs::create_dir_all(&album_dir)
+            .with_context(|| format!("Failed to create cache directory {}", album_dir.display()))?;
+
+        let path = album_dir.join(format!(
+            "{}.json",
+            response.fetched_at.format("%Y%m%dT%H%M%S%.3fZ")
+        ));
+        let json = serde_json::to_string_pretty(response)?;
+        fs::write(&path, json)
+            .with_context(|| format!("Failed to write album response to {}", path.display()))?;
+
+        let snapshots = Self::snapshots(&album_dir)?;
+        let excess = snapshots.len().saturating_sub(self.keep);
+        for old in &snapshots[..excess] {
+            fs::remove_file(old).with_context(|| {
+                format!("Failed to delete old album response {}", old.display())
+            })?;
+        }
+
+        Ok(path)
+    }
+
+    /// Loads the most recent snapshot of an album
+    pub fn load_latest(&self, album_url: &str) -> Result<AlbumResponse> {
+        let album_dir = self.album_dir(album_url);
+        let latest = Self::snapshots(&album_dir)?
+            .pop()
+            .with_context(|| format!("No saved response for album {album_url}"))?;
+
+        Self::load(&latest)
+    }
+
+    /// Loads a snapshot from a file
+    fn load(path: &Path) -> Result<AlbumResponse> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read album response from {}", path.display()))?;
+        serde_json::from_str(&json)
+            .with_context(|| format!("Failed to parse album response from {}", path.display()))
+    }
+
+    /// Lists the snapshots in an album directory, oldest first
+    fn snapshots(album_dir: &Path) -> Result<Vec<PathBuf>> {
+        if !album_dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut paths: Vec<PathBuf> = fs::read_dir(album_dir)
+            .with_context(|| format!("Failed to read cache directory {}", album_dir.display()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        // Snapshot names are timestamps, so they sort chronologically
+        paths.sort();
+
+        Ok(paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+    use serde_json::json;
+    use std::collections::HashMap;
+    use tempfile::tempdir;
+
+    fn create_response(album_url: &str, name: &str, age_minutes: i64) -> AlbumResponse {
+        AlbumResponse {
+            album_url: album_url.to_string(),
+            fetched_at: Utc::now() - Duration::minutes(age_minutes),
+            stream: json!({ "streamName": name, "photos": [] }),
+            asset_urls: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_load_latest_snapshot() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let cache = ResponseCache::new(temp_dir.path().to_path_buf());
+        let album_url = "https://www.icloud.com/sharedalbum/#B0aGWZmrRGZRiRW";
+
+        cache.save(&create_response(album_url, "Older", 10))?;
+        cache.save(&create_response(album_url, "Newer", 1))?;
+        cache.save(&create_response(
+            "https://www.icloud.com/sharedalbum/#B0other",
+            "Other",
+            0,
+        ))?;
+
+        let latest = cache.load_latest(album_url)?;
+        assert_eq!(latest.stream["streamName"], "Newer");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_keeps_latest_snapshots() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let cache = ResponseCache::new(temp_dir.path().to_path_buf()).with_keep(2);
+        let album_url = "https://www.icloud.com/sharedalbum/#B0aGWZmrRGZRiRW";
+        let other_url = "https://www.icloud.com/sharedalbum/#B0other";
+
+        let other = cache.save(&create_response(other_url, "Other", 30))?;
+        let oldest = cache.save(&create_response(album_url, "Oldest", 20))?;
+        cache.save(&create_response(album_url, "Older", 10))?;
+        cache.save(&create_response(album_url, "Newer", 1))?;
+
+        let album_dir = cache.album_dir(album_url);
+        assert_eq!(ResponseCache::snapshots(&album_dir)?.len(), 2);
+        assert!(!oldest.exists());
+        // Other albums keep their own snapshots
+        assert!(other.exists());
+        assert_eq!(cache.load_latest(album_url)?.stream["streamName"], "Newer");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_keep_never_drops_the_latest_snapshot() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let cache = ResponseCache::new(temp_dir.path().to_path_buf()).with_keep(0);
+        let album_url = "https://www.icloud.com/sharedalbum/#B0aGWZmrRGZRiRW";
+
+        cache.save(&create_response(album_url, "Older", 10))?;
+        let latest = cache.save(&create_response(album_url, "Newer", 1))?;
+
+        assert_eq!(
+            ResponseCache::snapshots(&cache.album_dir(album_url))?,
+            vec![latest]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_latest_without_snapshot_fails() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let cache = ResponseCache::new(temp_dir.path().to_path_buf());
+
+        let error = cache
+            .load_latest("https://www.icloud.com/sharedalbum/#B0missing")
+            .unwrap_err();
+        assert!(error.to_string().contains("No saved response"));
+
+        Ok(())
+    }
+}
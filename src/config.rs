@@ -8,9 +8,16 @@
 //! different albums into different directories, either as photostreams or galleries.
 
 use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::cache::ResponseCache;
+use crate::frontmatter::deserialize_date;
+use crate::overrides::Overrides;
+use crate::store::{IndexStore, SqliteStore, YamlStore};
 
 // Define constants for default configuration values for clarity and reusability
 const DEFAULT_ALBUM_URL: &str = "https://www.icloud.com/sharedalbum/ALBUM_TOKEN_GOES_HERE";
@@ -18,6 +25,11 @@ const DEFAULT_OUT_DIR: &str = "content/photostream";
 const DEFAULT_DATA_FILE: &str = "data/photos/index.yaml";
 const DEFAULT_FUZZ_METERS: f64 = 100.0;
 const DEFAULT_CONFIG_FILE: &str = "icloudalbums.yaml";
+const DEFAULT_RETRY_ATTEMPTS: u32 = 4;
+const DEFAULT_RETRY_INITIAL_DELAY_MS: u64 = 1000;
+const DEFAULT_RETRY_MAX_DELAY_MS: u64 = 30_000;
+const DEFAULT_DOWNLOAD_CONCURRENCY: usize = 8;
+const DEFAULT_MAX_FILTER_DELETIONS: usize = 20;
 
 // Define separate constants for test data - explicitly for testing only
 #[cfg(test)]
@@ -30,17 +42,13 @@ pub(crate) mod test_constants {
     pub const TEST_MOCK_DIRECTORY: &str = "custom/tests";
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum OutputType {
+    #[default]
     Photostream,
     Gallery,
-}
-
-impl Default for OutputType {
-    fn default() -> Self {
-        Self::Photostream
-    }
+    Batches,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -62,13 +70,194 @@ pub struct PrivacyConfig {
     pub robots_noindex: bool,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RetryConfig {
+    /// Total number of attempts for album fetches and downloads, including the first
+    #[serde(default = "default_retry_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry in milliseconds, doubled for every further retry
+    #[serde(default = "default_retry_initial_delay_ms")]
+    pub initial_delay_ms: u64,
+    /// Upper bound for a single delay in milliseconds, also applied to Retry-After
+    #[serde(default = "default_retry_max_delay_ms")]
+    pub max_delay_ms: u64,
+}
+
+fn default_retry_attempts() -> u32 {
+    DEFAULT_RETRY_ATTEMPTS
+}
+
+fn default_retry_initial_delay_ms() -> u64 {
+    DEFAULT_RETRY_INITIAL_DELAY_MS
+}
+
+fn default_retry_max_delay_ms() -> u64 {
+    DEFAULT_RETRY_MAX_DELAY_MS
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_RETRY_ATTEMPTS,
+            initial_delay_ms: DEFAULT_RETRY_INITIAL_DELAY_MS,
+            max_delay_ms: DEFAULT_RETRY_MAX_DELAY_MS,
+        }
+    }
+}
+
+/// Limits for photo downloads
+///
+/// Set globally and per output; a setting on an output overrides the global one.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct DownloadConfig {
+    /// Number of photos processed at the same time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<usize>,
+    /// Combined download rate cap in bytes per second
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bytes_per_second: Option<u64>,
+}
+
+impl DownloadConfig {
+    /// Whether nothing is set, so the config can be left out of the YAML
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Number of photos processed at the same time, at least one
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+            .unwrap_or(DEFAULT_DOWNLOAD_CONCURRENCY)
+            .max(1)
+    }
+
+    /// Fills the settings that aren't set here from a fallback
+    pub fn or(&self, fallback: &DownloadConfig) -> DownloadConfig {
+        DownloadConfig {
+            concurrency: self.concurrency.or(fallback.concurrency),
+            max_bytes_per_second: self.max_bytes_per_second.or(fallback.max_bytes_per_second),
+        }
+    }
+}
+
+/// Which kinds of media an output syncs
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaFilter {
+    #[default]
+    All,
+    Photos,
+    Videos,
+}
+
+/// Shape of the photos an output syncs
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    Landscape,
+    Portrait,
+    Square,
+}
+
+/// Rules that pick the photos of an album an output syncs
+///
+/// A photo has to pass every rule that is set. Photos that don't are treated
+/// as if they weren't in the album.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FilterConfig {
+    /// Only photos created on or after this date
+    #[serde(
+        default,
+        deserialize_with = "deserialize_date",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub after: Option<DateTime<Utc>>,
+    /// Only photos created before this date
+    #[serde(
+        default,
+        deserialize_with = "deserialize_date",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub before: Option<DateTime<Utc>>,
+    /// Only photos whose caption matches this regular expression
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub caption: Option<String>,
+    /// Leave out photos whose caption matches this regular expression
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exclude_caption: Option<String>,
+    /// Only photos, only videos, or both
+    #[serde(default)]
+    pub media: MediaFilter,
+    /// Only photos posted by these album members
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub contributors: Vec<String>,
+    /// Only photos of this shape
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orientation: Option<Orientation>,
+    /// Only photos with GPS coordinates (`true`) or without them (`false`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_gps: Option<bool>,
+    /// GUIDs of photos to leave out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_guids: Vec<String>,
+    /// Most synced photos the rules may delete in one sync before it stops
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_deletions: Option<usize>,
+}
+
+impl FilterConfig {
+    /// Whether no rule is set, so the config can be left out of the YAML
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns the most synced photos the rules may delete in one sync
+    pub fn max_deletions(&self) -> usize {
+        self.max_deletions.unwrap_or(DEFAULT_MAX_FILTER_DELETIONS)
+    }
+}
+
+/// Turning hashtags in captions into Hugo taxonomy terms
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct HashtagConfig {
+    /// Taxonomy, i.e. frontmatter key, the hashtags are written to
+    #[serde(default = "default_taxonomy")]
+    pub taxonomy: String,
+    /// Leave the hashtags out of the captions shown on the site
+    #[serde(default)]
+    pub strip_from_caption: bool,
+    /// Directory to write a `_index.md` page for each term to, e.g. `content/tags`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub term_pages: Option<String>,
+}
+
+fn default_taxonomy() -> String {
+    "tags".to_string()
+}
+
+impl Default for HashtagConfig {
+    fn default() -> Self {
+        Self {
+            taxonomy: default_taxonomy(),
+            strip_from_caption: false,
+            term_pages: None,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct OutputConfig {
-    /// Type of output (photostream or gallery)
+    /// Type of output (photostream, gallery or batches)
     #[serde(default)]
     pub output_type: OutputType,
     /// Album URL for this output
+    #[serde(default)]
     pub album_url: String,
+    /// Additional album URLs merged into this output
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub album_urls: Vec<String>,
     /// Output directory for this output
     pub out_dir: String,
     /// Data file for this output
@@ -83,23 +272,62 @@ pub struct OutputConfig {
     /// Privacy settings for Hugo frontmatter
     #[serde(default)]
     pub privacy: PrivacyConfig,
+    /// Retry settings for album fetches and downloads
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// Only resolve asset URLs for photos that are new or changed since the
+    /// last sync, reusing the indexed URLs for everything else
+    #[serde(default)]
+    pub two_phase_fetch: bool,
+    /// Download limits for this output, overriding the global ones
+    #[serde(default, skip_serializing_if = "DownloadConfig::is_empty")]
+    pub downloads: DownloadConfig,
+    /// Sync new photos as drafts until they're approved
+    #[serde(default)]
+    pub require_approval: bool,
+    /// Rules that pick the photos this output syncs
+    #[serde(default, skip_serializing_if = "FilterConfig::is_empty")]
+    pub filter: FilterConfig,
+    /// Write the hashtags in captions to a taxonomy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hashtags: Option<HashtagConfig>,
 }
 
 fn default_enabled() -> bool {
     true
 }
 
+impl OutputConfig {
+    /// All album URLs this output is synced from, in configuration order
+    pub fn album_sources(&self) -> Vec<String> {
+        let mut sources: Vec<String> = Vec::new();
+        for url in std::iter::once(&self.album_url).chain(&self.album_urls) {
+            if !url.trim().is_empty() && !sources.contains(url) {
+                sources.push(url.clone());
+            }
+        }
+        sources
+    }
+}
+
 impl Default for OutputConfig {
     fn default() -> Self {
         Self {
             output_type: OutputType::Photostream,
             album_url: DEFAULT_ALBUM_URL.to_string(),
+            album_urls: Vec::new(),
             out_dir: DEFAULT_OUT_DIR.to_string(),
             data_file: DEFAULT_DATA_FILE.to_string(),
             name: None,
             description: None,
             enabled: true,
             privacy: PrivacyConfig::default(),
+            retry: RetryConfig::default(),
+            two_phase_fetch: false,
+            downloads: DownloadConfig::default(),
+            require_approval: false,
+            filter: FilterConfig::default(),
+            hashtags: None,
         }
     }
 }
@@ -108,6 +336,31 @@ impl Default for OutputConfig {
 pub struct Config {
     /// Default fuzz meters for location privacy
     pub fuzz_meters: Option<f64>,
+    /// Directory of the Hugo site, which the other paths in this file are
+    /// relative to; defaults to the directory of the config file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub site_root: Option<String>,
+    /// `site_root` resolved against the directory of the config file
+    #[serde(skip)]
+    root: PathBuf,
+    /// Directory where raw album responses are saved for offline syncs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_dir: Option<String>,
+    /// Number of saved responses kept per album (10 by default); older ones
+    /// are deleted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_keep: Option<usize>,
+    /// SQLite database holding the photo indexes of all outputs, instead of
+    /// their YAML data files (which are then only written for Hugo)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index_database: Option<String>,
+    /// YAML file with per-photo overrides of captions, titles, alt text,
+    /// locations, dates, tags and visibility
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overrides_file: Option<String>,
+    /// Download limits for all outputs
+    #[serde(default, skip_serializing_if = "DownloadConfig::is_empty")]
+    pub downloads: DownloadConfig,
     /// List of outputs to process
     #[serde(default)]
     pub outputs: Vec<OutputConfig>,
@@ -117,24 +370,77 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             fuzz_meters: Some(DEFAULT_FUZZ_METERS),
+            site_root: None,
+            root: PathBuf::new(),
+            cache_dir: None,
+            cache_keep: None,
+            index_database: None,
+            overrides_file: None,
+            downloads: DownloadConfig::default(),
             outputs: vec![OutputConfig::default()],
         }
     }
 }
 
 impl Config {
-    pub fn save_to_file(&self, path: &Path) -> Result<()> {
-        // Ensure parent directory exists
-        if let Some(parent) = path.parent() {
-            if !parent.as_os_str().is_empty() {
-                fs::create_dir_all(parent)?;
-            }
+    /// The directory of the Hugo site
+    ///
+    /// Photo paths in the index are stored relative to it. It's empty for a
+    /// config that wasn't loaded from a file, which leaves paths relative to
+    /// the working directory.
+    pub fn site_root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Resolves a path from this file against the site root
+    pub fn resolve(&self, path: &str) -> PathBuf {
+        self.root.join(path)
+    }
+
+    /// The directory an output writes its pages to
+    pub fn content_dir(&self, output: &OutputConfig) -> PathBuf {
+        self.resolve(&output.out_dir)
+    }
+
+    /// The YAML data file of an output
+    pub fn data_file(&self, output: &OutputConfig) -> PathBuf {
+        self.resolve(&output.data_file)
+    }
+
+    /// The cache raw album responses are saved to, if a cache directory is
+    /// configured
+    pub fn response_cache(&self) -> Option<ResponseCache> {
+        let cache = ResponseCache::new(self.resolve(self.cache_dir.as_ref()?));
+        Some(match self.cache_keep {
+            Some(keep) => cache.with_keep(keep),
+            None => cache,
+        })
+    }
+
+    /// Opens the store holding the photo index of an output
+    pub fn index_store(&self, output: &OutputConfig) -> Result<Arc<dyn IndexStore>> {
+        let data_file = self.data_file(output);
+        Ok(match &self.index_database {
+            Some(database) => Arc::new(SqliteStore::open(
+                &self.resolve(database),
+                &data_file,
+                &self.root,
+            )?),
+            None => Arc::new(YamlStore::new(data_file, self.root.clone())),
+        })
+    }
+
+    /// Reads the per-photo overrides, if an overrides file is configured
+    pub fn overrides(&self) -> Result<Overrides> {
+        match &self.overrides_file {
+            Some(path) => Overrides::load(&self.resolve(path)),
+            None => Ok(Overrides::default()),
         }
+    }
 
+    pub fn save_to_file(&self, path: &Path) -> Result<()> {
         let yaml = serde_yaml::to_string(self)?;
-        fs::write(path, yaml)?;
-
-        Ok(())
+        crate::persist::write_atomic(path, yaml.as_bytes())
     }
 
     pub fn load_from_file(path: &Path) -> Result<Self> {
@@ -161,12 +467,19 @@ impl Config {
                 config.outputs = vec![OutputConfig {
                     output_type: OutputType::Photostream,
                     album_url: legacy_config.album_url,
+                    album_urls: Vec::new(),
                     out_dir: legacy_config.out_dir,
                     data_file: legacy_config.data_file,
                     name: None,
                     description: None,
                     enabled: true,
                     privacy: PrivacyConfig::default(),
+                    retry: RetryConfig::default(),
+                    two_phase_fetch: false,
+                    downloads: DownloadConfig::default(),
+                    require_approval: false,
+                    filter: FilterConfig::default(),
+                    hashtags: None,
                 }];
             }
         }
@@ -176,6 +489,8 @@ impl Config {
             config.outputs.push(OutputConfig::default());
         }
 
+        config.root = site_root(path, config.site_root.as_deref())?;
+
         Ok(config)
     }
 
@@ -212,6 +527,24 @@ impl Config {
     }
 }
 
+/// Resolves the site root of a config file: `site_root` relative to the
+/// file's directory, or the directory itself
+fn site_root(config_path: &Path, site_root: Option<&str>) -> Result<PathBuf> {
+    let dir = match config_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let root = std::path::absolute(dir.join(site_root.unwrap_or_default())).with_context(|| {
+        format!(
+            "Failed to resolve the site root of {}",
+            config_path.display()
+        )
+    })?;
+    // Symlinks and `..` are resolved when the directory exists, so paths
+    // under it can be made relative again
+    Ok(fs::canonicalize(&root).unwrap_or(root))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -260,6 +593,51 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_site_root_defaults_to_config_dir() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let site_dir = temp_dir.path().join("site");
+        fs::create_dir_all(&site_dir)?;
+        let config_path = site_dir.join("icloudalbums.yaml");
+        Config::default().save_to_file(&config_path)?;
+
+        let config = Config::load_from_file(&config_path)?;
+        let site_dir = site_dir.canonicalize()?;
+
+        assert_eq!(config.site_root(), site_dir);
+        let output = &config.outputs[0];
+        assert_eq!(config.content_dir(output), site_dir.join(DEFAULT_OUT_DIR));
+        assert_eq!(config.data_file(output), site_dir.join(DEFAULT_DATA_FILE));
+        assert!(!fs::read_to_string(&config_path)?.contains("site_root"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_explicit_site_root() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let config_dir = temp_dir.path().join("config");
+        fs::create_dir_all(temp_dir.path().join("site"))?;
+        fs::create_dir_all(&config_dir)?;
+        let config_path = config_dir.join("icloudalbums.yaml");
+        let config = Config {
+            site_root: Some("../site".to_string()),
+            ..Config::default()
+        };
+        config.save_to_file(&config_path)?;
+
+        let config = Config::load_from_file(&config_path)?;
+        let site_dir = temp_dir.path().join("site").canonicalize()?;
+
+        assert_eq!(config.site_root(), site_dir);
+        assert_eq!(
+            config.resolve("static/cache"),
+            site_dir.join("static/cache")
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_load_legacy_config() -> Result<()> {
         let temp_dir = tempdir()?;
@@ -328,6 +706,79 @@ fuzz_meters: 50.0
         Ok(())
     }
 
+    #[test]
+    fn test_download_config_overrides() -> Result<()> {
+        let yaml = r#"
+downloads:
+  concurrency: 4
+  max_bytes_per_second: 500000
+outputs:
+  - album_url: "https://www.icloud.com/sharedalbum/#B0first"
+    out_dir: "content/first"
+    data_file: "data/first.yaml"
+    downloads:
+      concurrency: 2
+  - album_url: "https://www.icloud.com/sharedalbum/#B0second"
+    out_dir: "content/second"
+    data_file: "data/second.yaml"
+"#;
+        let config: Config = serde_yaml::from_str(yaml)?;
+
+        let first = config.outputs[0].downloads.or(&config.downloads);
+        assert_eq!(first.concurrency(), 2);
+        assert_eq!(first.max_bytes_per_second, Some(500_000));
+
+        let second = config.outputs[1].downloads.or(&config.downloads);
+        assert_eq!(second.concurrency(), 4);
+
+        // Unset everywhere falls back to the default, and zero means one at a time
+        assert_eq!(DownloadConfig::default().concurrency(), 8);
+        let zero = DownloadConfig {
+            concurrency: Some(0),
+            max_bytes_per_second: None,
+        };
+        assert_eq!(zero.concurrency(), 1);
+
+        // Unset limits are left out of the YAML
+        assert!(!serde_yaml::to_string(&Config::default())?.contains("downloads"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_album_sources() -> Result<()> {
+        let yaml = r#"
+outputs:
+  - output_type: gallery
+    album_url: https://www.icloud.com/sharedalbum/#B0phone
+    album_urls:
+      - https://www.icloud.com/sharedalbum/#B0camera
+      - https://www.icloud.com/sharedalbum/#B0phone
+    out_dir: content/galleries/trip
+    data_file: data/trip.yaml
+  - output_type: gallery
+    album_urls:
+      - https://www.icloud.com/sharedalbum/#B0only
+    out_dir: content/galleries/other
+    data_file: data/other.yaml
+"#;
+        let config: Config = serde_yaml::from_str(yaml)?;
+
+        assert_eq!(
+            config.outputs[0].album_sources(),
+            vec![
+                "https://www.icloud.com/sharedalbum/#B0phone".to_string(),
+                "https://www.icloud.com/sharedalbum/#B0camera".to_string(),
+            ]
+        );
+        assert_eq!(
+            config.outputs[1].album_sources(),
+            vec!["https://www.icloud.com/sharedalbum/#B0only".to_string()]
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_privacy_config_default() {
         let privacy = PrivacyConfig::default();
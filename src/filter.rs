@@ -0,0 +1,633 @@
+//! Filter rules that route parts of an album into an output.
+//!
+//! An output's `filter` settings pick the photos it syncs by date, caption,
+//! media type, contributor, orientation and GUID. Photos that don't pass are
+//! taken out of the fetched album before it's synced, so the syncers treat
+//! them as if they had been removed from the album.
+//!
+//! Whether a photo has GPS coordinates is only known once its file has been
+//! read, so that rule is checked against the index after a sync. Photos that
+//! don't pass it are deleted and moved from the index's photos to its
+//! `filtered` set, which remembers whether they have coordinates. Syncs
+//! check the set instead of downloading the photos again, so a photo comes
+//! back as soon as the rule is relaxed, or when the photo itself changes.
+//!
+//! To guard against mistyped rules, a sync stops when the rules would delete
+//! more synced photos than `max_deletions`.
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+use crate::approval;
+use crate::config::{FilterConfig, MediaFilter, Orientation, OutputType};
+use crate::icloud::{Album, Photo};
+use crate::index::{FilteredPhoto, IndexedPhoto, PhotoIndex};
+use crate::rerender;
+use crate::verify::Page;
+
+/// A filter rule, for telling which rule left a photo out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rule {
+    Date,
+    Caption,
+    MediaType,
+    Contributor,
+    Orientation,
+    Gps,
+    Guid,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Rule::Date => "date range",
+            Rule::Caption => "caption",
+            Rule::MediaType => "media type",
+            Rule::Contributor => "contributor",
+            Rule::Orientation => "orientation",
+            Rule::Gps => "GPS coordinates",
+            Rule::Guid => "excluded GUIDs",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// An output's filter rules, ready to check photos against
+#[derive(Debug, Clone, Default)]
+pub struct PhotoFilter {
+    config: FilterConfig,
+    caption: Option<Regex>,
+    exclude_caption: Option<Regex>,
+}
+
+/// How many photos of an album each rule left out
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilterReport {
+    /// Photos that passed every rule
+    pub kept: usize,
+    /// Photos left out, by the first rule they didn't pass
+    pub excluded: BTreeMap<Rule, usize>,
+    /// Photos left out that were synced before, so syncing deletes them
+    pub deletions: usize,
+}
+
+/// What taking the photos that don't pass the GPS rule out of an output did
+#[derive(Debug, Default)]
+pub struct GpsRemoval {
+    /// GUIDs of the photos taken out of the index
+    pub removed: Vec<String>,
+    /// Photos whose files couldn't be deleted, with the error
+    pub failures: Vec<(String, String)>,
+    /// Pages that showed the photos and have to be rendered again
+    pub pages: Vec<Page>,
+}
+
+impl FilterReport {
+    /// Number of photos left out
+    pub fn excluded_count(&self) -> usize {
+        self.excluded.values().sum()
+    }
+}
+
+impl PhotoFilter {
+    /// Compiles an output's filter rules
+    pub fn new(config: &FilterConfig) -> Result<Self> {
+        let compile = |pattern: &Option<String>| {
+            pattern
+                .as_deref()
+                .map(|pattern| {
+                    Regex::new(pattern)
+                        .with_context(|| format!("Invalid caption filter '{pattern}'"))
+                })
+                .transpose()
+        };
+        Ok(Self {
+            caption: compile(&config.caption)?,
+            exclude_caption: compile(&config.exclude_caption)?,
+            config: config.clone(),
+        })
+    }
+
+    /// Whether no rule is set
+    pub fn is_empty(&self) -> bool {
+        self.config.is_empty()
+    }
+
+    /// Returns the most synced photos the rules may delete in one sync
+    pub fn max_deletions(&self) -> usize {
+        self.config.max_deletions()
+    }
+
+    /// Returns the first rule a photo from the album doesn't pass
+    ///
+    /// The GPS rule isn't checked; see [`PhotoFilter::check_indexed`].
+    pub fn check(&self, photo: &Photo) -> Option<Rule> {
+        let config = &self.config;
+        if config.after.is_some_and(|after| photo.created_at < after)
+            || config
+                .before
+                .is_some_and(|before| photo.created_at >= before)
+        {
+            return Some(Rule::Date);
+        }
+
+        let caption = photo.caption.as_deref().unwrap_or_default();
+        if self
+            .caption
+            .as_ref()
+            .is_some_and(|re| !re.is_match(caption))
+            || self
+                .exclude_caption
+                .as_ref()
+                .is_some_and(|re| re.is_match(caption))
+        {
+            return Some(Rule::Caption);
+        }
+
+        let is_video = photo.mime_type.starts_with("video/");
+        match config.media {
+            MediaFilter::Photos if is_video => return Some(Rule::MediaType),
+            MediaFilter::Videos if !is_video => return Some(Rule::MediaType),
+            _ => {}
+        }
+
+        if !config.contributors.is_empty()
+            && !photo
+                .contributor
+                .as_ref()
+                .is_some_and(|contributor| config.contributors.contains(contributor))
+        {
+            return Some(Rule::Contributor);
+        }
+
+        if let Some(orientation) = config.orientation {
+            let shape = match photo.width.cmp(&photo.height) {
+                std::cmp::Ordering::Greater => Orientation::Landscape,
+                std::cmp::Ordering::Less => Orientation::Portrait,
+                std::cmp::Ordering::Equal => Orientation::Square,
+            };
+            if shape != orientation {
+                return Some(Rule::Orientation);
+            }
+        }
+
+        if config.exclude_guids.contains(&photo.guid) {
+            return Some(Rule::Guid);
+        }
+        None
+    }
+
+    /// Returns the GPS rule if a photo with or without coordinates doesn't
+    /// pass it
+    fn check_gps(&self, has_gps: bool) -> Option<Rule> {
+        self.config
+            .has_gps
+            .is_some_and(|required| required != has_gps)
+            .then_some(Rule::Gps)
+    }
+
+    /// Returns the GPS rule if a synced photo doesn't pass it
+    pub fn check_indexed(&self, photo: &IndexedPhoto) -> Option<Rule> {
+        self.check_gps(has_gps(photo))
+    }
+
+    /// Takes the photos that don't pass the rules out of an album
+    ///
+    /// The GPS rule is checked against what the index knows. Synced photos
+    /// that don't pass it are counted, but stay in the album until
+    /// [`PhotoFilter::remove_gps_rejects`] takes them out after the sync.
+    /// Photos in the index's filtered set stay out while they still fail the
+    /// rule and haven't changed.
+    pub fn apply(&self, album: &mut Album, index: &PhotoIndex) -> FilterReport {
+        let mut report = FilterReport::default();
+        album.photos.retain(|_, photo| {
+            let indexed = index
+                .get_photo(&photo.guid)
+                .filter(|indexed| !indexed.is_rejected());
+            let rule = self.check(photo).or_else(|| match indexed {
+                Some(indexed) => self.check_indexed(indexed),
+                None => index
+                    .filtered
+                    .get(&photo.guid)
+                    .filter(|filtered| filtered.checksum == photo.checksum)
+                    .and_then(|filtered| self.check_gps(filtered.has_gps)),
+            });
+            match rule {
+                Some(rule) => *report.excluded.entry(rule).or_default() += 1,
+                None => report.kept += 1,
+            }
+            if rule.is_some() && indexed.is_some() {
+                report.deletions += 1;
+            }
+            !matches!(rule, Some(rule) if rule != Rule::Gps || indexed.is_none())
+        });
+        report
+    }
+
+    /// Takes the synced photos that don't pass the GPS rule out of an output
+    ///
+    /// Their files are deleted, and they're moved from the index's photos to
+    /// its filtered set. Photos in the set that were synced again, because
+    /// the rule or the photo changed, are dropped from it.
+    pub fn remove_gps_rejects(
+        &self,
+        output_type: &OutputType,
+        content_dir: &Path,
+        site_root: &Path,
+        index: &mut PhotoIndex,
+    ) -> GpsRemoval {
+        let synced: Vec<String> = index
+            .filtered
+            .keys()
+            .filter(|guid| index.photos.contains_key(*guid))
+            .cloned()
+            .collect();
+        for guid in synced {
+            index.filtered.remove(&guid);
+        }
+
+        let rejects: Vec<IndexedPhoto> = index
+            .photos
+            .values()
+            .filter(|photo| !photo.is_rejected() && self.check_indexed(photo).is_some())
+            .cloned()
+            .collect();
+        let mut removal = GpsRemoval::default();
+        if rejects.is_empty() {
+            return removal;
+        }
+
+        // The pages are found while the index still lists the photos
+        let guids: HashSet<&str> = rejects.iter().map(|photo| photo.guid.as_str()).collect();
+        removal.pages = rerender::pages(output_type, content_dir, index)
+            .into_iter()
+            .filter(|page| match page {
+                Page::Photo(_) => false,
+                Page::Gallery(_) => true,
+                Page::Batch(id) => index.batches.get(id).is_some_and(|batch| {
+                    batch
+                        .photos
+                        .iter()
+                        .any(|guid| guids.contains(guid.as_str()))
+                }),
+            })
+            .collect();
+
+        for photo in rejects {
+            if let Err(e) =
+                approval::delete_photo_files(output_type, content_dir, site_root, &photo)
+            {
+                removal
+                    .failures
+                    .push((photo.guid.clone(), format!("{e:#}")));
+            }
+            index.remove_photo(&photo.guid);
+            index.filtered.insert(
+                photo.guid.clone(),
+                FilteredPhoto {
+                    checksum: photo.checksum.clone(),
+                    has_gps: has_gps(&photo),
+                },
+            );
+            removal.removed.push(photo.guid);
+        }
+        removal
+    }
+}
+
+/// Whether a synced photo has GPS coordinates
+fn has_gps(photo: &IndexedPhoto) -> bool {
+    photo.latitude.is_some() && photo.longitude.is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::collections::HashMap;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn create_photo(guid: &str, created_at: &str, caption: Option<&str>) -> Photo {
+        Photo {
+            guid: guid.to_string(),
+            filename: format!("{guid}.jpg"),
+            caption: caption.map(str::to_string),
+            created_at: created_at.parse().unwrap(),
+            checksum: format!("checksum_{guid}"),
+            url: format!("https://example.com/{guid}.jpg"),
+            width: 800,
+            height: 600,
+            mime_type: "image/jpeg".to_string(),
+            contributor: Some("Harper".to_string()),
+            batch_guid: None,
+            batch_created_at: None,
+            asset_checksum: None,
+            sources: Vec::new(),
+        }
+    }
+
+    fn album(photos: Vec<Photo>) -> Album {
+        Album {
+            name: "Test Album".to_string(),
+            photos: photos
+                .into_iter()
+                .map(|photo| (photo.guid.clone(), photo))
+                .collect::<HashMap<_, _>>(),
+        }
+    }
+
+    fn indexed_photo(guid: &str, local_path: &Path, site_root: &Path) -> IndexedPhoto {
+        IndexedPhoto::new(
+            guid.to_string(),
+            format!("{guid}.jpg"),
+            None,
+            Utc::now(),
+            format!("checksum_{guid}"),
+            format!("https://example.com/{guid}.jpg"),
+            800,
+            600,
+            local_path.to_path_buf(),
+            site_root,
+        )
+    }
+
+    fn parse_filter(yaml: &str) -> Result<PhotoFilter> {
+        PhotoFilter::new(&serde_yaml::from_str(yaml)?)
+    }
+
+    /// Applies a filter to photos nobody synced yet, returning the GUIDs it
+    /// kept
+    fn kept(filter: &PhotoFilter, photos: Vec<Photo>) -> Vec<String> {
+        let mut album = album(photos);
+        filter.apply(&mut album, &PhotoIndex::new());
+        let mut guids: Vec<String> = album.photos.into_keys().collect();
+        guids.sort();
+        guids
+    }
+
+    #[test]
+    fn test_date_bounds() -> Result<()> {
+        let filter = parse_filter("after: 2024-01-01\nbefore: 2024-06-01\n")?;
+        let photos = vec![
+            create_photo("old", "2023-12-31T23:59:59Z", None),
+            create_photo("first", "2024-01-01T00:00:00Z", None),
+            create_photo("last", "2024-05-31T23:59:59Z", None),
+            create_photo("new", "2024-06-01T00:00:00Z", None),
+        ];
+        assert_eq!(kept(&filter, photos), vec!["first", "last"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_caption_rules() -> Result<()> {
+        let filter = parse_filter("caption: '(?i)#blog'\nexclude_caption: private\n")?;
+        let photos = vec![
+            create_photo("tagged", "2024-01-01T00:00:00Z", Some("Lunch #Blog")),
+            create_photo("untagged", "2024-01-01T00:00:00Z", Some("Lunch")),
+            create_photo("no_caption", "2024-01-01T00:00:00Z", None),
+            create_photo("private", "2024-01-01T00:00:00Z", Some("#blog private")),
+        ];
+        assert_eq!(kept(&filter, photos), vec!["tagged"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_caption_regex() {
+        let config = FilterConfig {
+            caption: Some("(unclosed".to_string()),
+            ..FilterConfig::default()
+        };
+        let err = PhotoFilter::new(&config).unwrap_err();
+        assert!(format!("{err:#}").contains("(unclosed"));
+    }
+
+    #[test]
+    fn test_media_type() -> Result<()> {
+        let mut video = create_photo("video", "2024-01-01T00:00:00Z", None);
+        video.mime_type = "video/mp4".to_string();
+        let photos = vec![create_photo("photo", "2024-01-01T00:00:00Z", None), video];
+
+        assert_eq!(
+            kept(&parse_filter("media: photos\n")?, photos.clone()),
+            vec!["photo"]
+        );
+        assert_eq!(
+            kept(&parse_filter("media: videos\n")?, photos.clone()),
+            vec!["video"]
+        );
+        assert_eq!(
+            kept(&parse_filter("media: all\n")?, photos),
+            vec!["photo", "video"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_contributors() -> Result<()> {
+        let mut guest = create_photo("guest", "2024-01-01T00:00:00Z", None);
+        guest.contributor = Some("Guest".to_string());
+        let mut anonymous = create_photo("anonymous", "2024-01-01T00:00:00Z", None);
+        anonymous.contributor = None;
+        let photos = vec![
+            create_photo("harper", "2024-01-01T00:00:00Z", None),
+            guest,
+            anonymous,
+        ];
+        assert_eq!(
+            kept(&parse_filter("contributors: [Harper]\n")?, photos),
+            vec!["harper"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_orientation() -> Result<()> {
+        let mut portrait = create_photo("portrait", "2024-01-01T00:00:00Z", None);
+        portrait.height = 1200;
+        let mut square = create_photo("square", "2024-01-01T00:00:00Z", None);
+        square.height = square.width;
+        let photos = vec![
+            create_photo("landscape", "2024-01-01T00:00:00Z", None),
+            portrait,
+            square,
+        ];
+
+        for orientation in ["landscape", "portrait", "square"] {
+            let filter = parse_filter(&format!("orientation: {orientation}\n"))?;
+            assert_eq!(kept(&filter, photos.clone()), vec![orientation]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_guid_denylist() -> Result<()> {
+        let photos = vec![
+            create_photo("kept", "2024-01-01T00:00:00Z", None),
+            create_photo("denied", "2024-01-01T00:00:00Z", None),
+        ];
+        assert_eq!(
+            kept(&parse_filter("exclude_guids: [denied]\n")?, photos),
+            vec!["kept"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_report_counts_first_failing_rule() -> Result<()> {
+        let filter = parse_filter("after: 2024-01-01\ncaption: '#blog'\n")?;
+        let mut album = album(vec![
+            create_photo("kept", "2024-02-01T00:00:00Z", Some("#blog")),
+            create_photo("old", "2023-01-01T00:00:00Z", None),
+            create_photo("untagged", "2024-02-01T00:00:00Z", None),
+        ]);
+        let report = filter.apply(&mut album, &PhotoIndex::new());
+
+        assert_eq!(report.kept, 1);
+        assert_eq!(
+            report.excluded,
+            BTreeMap::from([(Rule::Date, 1), (Rule::Caption, 1)])
+        );
+        assert_eq!(report.deletions, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_deletions_count_synced_photos() -> Result<()> {
+        let filter = parse_filter("caption: '#blog'\n")?;
+        let mut index = PhotoIndex::new();
+        for guid in ["synced", "also_synced"] {
+            index.add_or_update_photo(indexed_photo(guid, Path::new("x.jpg"), Path::new("")));
+        }
+        let mut album = album(vec![
+            create_photo("synced", "2024-01-01T00:00:00Z", None),
+            create_photo("also_synced", "2024-01-01T00:00:00Z", None),
+            create_photo("new", "2024-01-01T00:00:00Z", None),
+        ]);
+        let report = filter.apply(&mut album, &index);
+
+        assert!(album.photos.is_empty());
+        assert_eq!(report.deletions, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_deletions() -> Result<()> {
+        assert_eq!(parse_filter("caption: x\n")?.max_deletions(), 20);
+        assert_eq!(
+            parse_filter("caption: x\nmax_deletions: 0\n")?.max_deletions(),
+            0
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_gps_rule_filters_synced_photos() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("photostream");
+        let mut index = PhotoIndex::new();
+        for guid in ["located", "no_gps"] {
+            let bundle = content_dir.join(guid);
+            fs::create_dir_all(&bundle)?;
+            let mut photo = indexed_photo(guid, &bundle.join("photo.jpg"), temp_dir.path());
+            if guid == "located" {
+                photo.latitude = Some(41.9);
+                photo.longitude = Some(-87.6);
+            }
+            index.add_or_update_photo(photo);
+        }
+        let photos = || {
+            vec![
+                create_photo("located", "2024-01-01T00:00:00Z", None),
+                create_photo("no_gps", "2024-01-01T00:00:00Z", None),
+            ]
+        };
+
+        // A synced photo that fails the rule stays in the album until the
+        // sync is done, and is then taken out of the index
+        let filter = parse_filter("has_gps: true\n")?;
+        let mut synced = album(photos());
+        let report = filter.apply(&mut synced, &index);
+        assert_eq!(report.excluded, BTreeMap::from([(Rule::Gps, 1)]));
+        assert_eq!(report.deletions, 1);
+        assert_eq!(synced.photos.len(), 2);
+
+        let removal = filter.remove_gps_rejects(
+            &OutputType::Photostream,
+            &content_dir,
+            temp_dir.path(),
+            &mut index,
+        );
+        assert_eq!(removal.removed, vec!["no_gps"]);
+        assert!(removal.failures.is_empty());
+        assert!(removal.pages.is_empty());
+        assert!(index.get_photo("no_gps").is_none());
+        assert!(!content_dir.join("no_gps").exists());
+        assert!(content_dir.join("located").exists());
+        assert!(!index.filtered["no_gps"].has_gps);
+
+        // The next sync leaves it out without downloading it again
+        let mut next = album(photos());
+        let report = filter.apply(&mut next, &index);
+        assert_eq!(report.excluded, BTreeMap::from([(Rule::Gps, 1)]));
+        assert_eq!(report.deletions, 0);
+        assert!(!next.photos.contains_key("no_gps"));
+
+        // Relaxing the rule brings it back
+        let relaxed = parse_filter("exclude_guids: [other]\n")?;
+        let mut next = album(photos());
+        let report = relaxed.apply(&mut next, &index);
+        assert_eq!(report.kept, 2);
+        assert!(next.photos.contains_key("no_gps"));
+
+        // So does a change to the photo
+        let mut changed = photos();
+        changed[1].checksum = "edited".to_string();
+        let mut next = album(changed);
+        filter.apply(&mut next, &index);
+        assert!(next.photos.contains_key("no_gps"));
+
+        // Once it's synced again, it's dropped from the filtered set
+        index.add_or_update_photo(indexed_photo(
+            "no_gps",
+            &content_dir.join("no_gps/photo.jpg"),
+            temp_dir.path(),
+        ));
+        let removal = relaxed.remove_gps_rejects(
+            &OutputType::Photostream,
+            &content_dir,
+            temp_dir.path(),
+            &mut index,
+        );
+        assert!(removal.removed.is_empty());
+        assert!(index.filtered.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_gps_rule_reports_missing_files() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("photostream");
+        let mut index = PhotoIndex::new();
+        index.add_or_update_photo(indexed_photo(
+            "missing",
+            &content_dir.join("missing/photo.jpg"),
+            temp_dir.path(),
+        ));
+
+        let removal = parse_filter("has_gps: true\n")?.remove_gps_rejects(
+            &OutputType::Photostream,
+            &content_dir,
+            temp_dir.path(),
+            &mut index,
+        );
+        assert_eq!(removal.removed, vec!["missing"]);
+        assert_eq!(removal.failures.len(), 1);
+        assert!(removal.failures[0].1.contains("doesn't exist"));
+        assert!(index.filtered.contains_key("missing"));
+        Ok(())
+    }
+}
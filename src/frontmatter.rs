@@ -0,0 +1,509 @@
+//! Reading and writing the YAML frontmatter of Hugo pages.
+//!
+//! Pages written by this tool and pages written by hand both start with a
+//! YAML block between `---` lines. Only YAML frontmatter is supported, not
+//! TOML or JSON.
+//!
+//! Generated pages are often edited by hand afterwards, to add tags, a longer
+//! write-up or `featured: true`. When a page is written again, only the keys
+//! this tool owns are replaced; other keys keep their place and value. The
+//! body is only replaced while it's still the one this tool wrote, which the
+//! page records as a checksum of the generated body.
+//!
+//! Keys are merged line by line, so comments and formatting survive. The
+//! result is parsed again and compared with the expected keys; a page the
+//! line-based merge can't handle is left as it is, with a warning.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::{Deserialize, Deserializer};
+use serde_yaml::{Mapping, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use tokio::fs as tokio_fs;
+
+use crate::index::content_hash;
+
+/// Line that opens and closes the frontmatter
+const DELIMITER: &str = "---";
+
+/// Key holding the checksum of the body this tool generated
+const BODY_CHECKSUM: &str = "body_checksum";
+
+/// Key listing the optional keys this tool wrote to the page
+const GENERATED_KEYS: &str = "generated_keys";
+
+/// Splits a page into its frontmatter and body
+pub fn split(contents: &str) -> Option<(&str, &str)> {
+    let rest = contents.strip_prefix(DELIMITER)?;
+    let rest = rest
+        .strip_prefix("\r\n")
+        .or_else(|| rest.strip_prefix('\n'))?;
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == DELIMITER {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Parses the frontmatter of a page, if it has any
+pub fn parse(contents: &str) -> Result<Option<Mapping>> {
+    let Some((yaml, _)) = split(contents) else {
+        return Ok(None);
+    };
+    if yaml.trim().is_empty() {
+        return Ok(Some(Mapping::new()));
+    }
+    let value: Value = serde_yaml::from_str(yaml).context("Failed to parse frontmatter")?;
+    match value {
+        Value::Mapping(mapping) => Ok(Some(mapping)),
+        _ => Ok(None),
+    }
+}
+
+/// Reads the frontmatter of a page file
+pub fn read(path: &Path) -> Result<Option<Mapping>> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    parse(&contents).with_context(|| format!("Invalid frontmatter in {}", path.display()))
+}
+
+/// Returns a string value, also accepting numbers and booleans
+pub fn string(frontmatter: &Mapping, key: &str) -> Option<String> {
+    match frontmatter.get(key)? {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Returns a numeric value, also accepting numbers written as strings
+pub fn number(frontmatter: &Mapping, key: &str) -> Option<f64> {
+    match frontmatter.get(key)? {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Returns a date value in any of the formats Hugo accepts
+pub fn date(frontmatter: &Mapping, key: &str) -> Option<DateTime<Utc>> {
+    parse_date(&string(frontmatter, key)?)
+}
+
+/// Parses a date as written in frontmatter
+///
+/// This tool writes `2024-01-31T12:00:00+0000`; hand-written pages often use
+/// RFC 3339 or a plain date.
+pub fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    let date = date.trim();
+    if let Ok(parsed) = DateTime::parse_from_rfc3339(date) {
+        return Some(parsed.with_timezone(&Utc));
+    }
+    if let Ok(parsed) = DateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S%z") {
+        return Some(parsed.with_timezone(&Utc));
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S"] {
+        if let Ok(parsed) = NaiveDateTime::parse_from_str(date, format) {
+            return Some(parsed.and_utc());
+        }
+    }
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .and_then(|day| day.and_hms_opt(0, 0, 0))
+        .map(|midnight| midnight.and_utc())
+}
+
+/// Deserializes an optional date in any of the formats [`parse_date`] reads
+pub fn deserialize_date<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let Some(date) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    parse_date(&date)
+        .map(Some)
+        .ok_or_else(|| serde::de::Error::custom(format!("invalid date '{date}'")))
+}
+
+/// Quotes a string for a YAML value
+pub fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Writes a generated page, keeping what was added to an existing page by hand
+///
+/// `managed` lists the frontmatter keys the generator owns. `optional` lists
+/// keys it only writes for some pages, like `draft`; those are owned while the
+/// page records that the generator wrote them.
+pub async fn write_page(
+    path: &Path,
+    generated: &str,
+    managed: &[&str],
+    optional: &[&str],
+) -> Result<()> {
+    let existing = match tokio_fs::read_to_string(path).await {
+        Ok(existing) => Some(existing),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to read {}", path.display()));
+        }
+    };
+    let Some(page) = merge_page(existing.as_deref(), generated, managed, optional) else {
+        log::warn!(
+            "Not rewriting {}, its frontmatter couldn't be merged safely",
+            path.display()
+        );
+        return Ok(());
+    };
+    tokio_fs::write(path, page)
+        .await
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Merges a generated page into an existing one
+///
+/// Keys in `managed` are replaced by their generated lines, or removed when
+/// the generator no longer writes them. The same goes for keys in `optional`
+/// that the generator writes now or wrote last time, as recorded in the page.
+/// Other keys of the page win over generated ones. Generated keys the page
+/// doesn't have yet are added at the end of its frontmatter. A page without
+/// frontmatter is replaced.
+///
+/// Returns `None` when the merged frontmatter doesn't parse to the expected
+/// keys and values, e.g. because of YAML the line-based merge doesn't handle.
+pub fn merge_page(
+    existing: Option<&str>,
+    generated: &str,
+    managed: &[&str],
+    optional: &[&str],
+) -> Option<String> {
+    let (generated_yaml, generated_body) = split(generated).unwrap_or(("", generated));
+    let mut generated_entries = entries(generated_yaml);
+    let written_optional: Vec<&str> = generated_entries
+        .iter()
+        .map(|(key, _)| *key)
+        .filter(|key| optional.contains(key))
+        .collect();
+    let checksum_line = format!(
+        "{BODY_CHECKSUM}: {}\n",
+        content_hash(generated_body.as_bytes())
+    );
+    generated_entries.push((BODY_CHECKSUM, &checksum_line));
+    let generated_keys_line = format!(
+        "{GENERATED_KEYS}: [{}]\n",
+        written_optional
+            .iter()
+            .map(|key| quote(key))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    if !written_optional.is_empty() {
+        generated_entries.push((GENERATED_KEYS, &generated_keys_line));
+    }
+
+    let (yaml, body) = match existing.and_then(split) {
+        Some((yaml, body)) => (Some(yaml), body),
+        None => (None, generated_body),
+    };
+    let existing_entries = yaml.map(entries).unwrap_or_default();
+    let recorded_keys = recorded_keys(yaml.unwrap_or_default());
+    let owned = |key: &str| {
+        key == BODY_CHECKSUM
+            || key == GENERATED_KEYS
+            || managed.contains(&key)
+            || written_optional.contains(&key)
+            || recorded_keys.iter().any(|recorded| recorded == key)
+    };
+
+    let mut page = format!("{DELIMITER}\n");
+    let mut written = HashSet::new();
+    for (key, lines) in &existing_entries {
+        if !owned(key) {
+            page.push_str(lines);
+            written.insert(*key);
+        } else if let Some((_, generated_lines)) = generated_entries.iter().find(|(k, _)| k == key)
+            && written.insert(*key)
+        {
+            page.push_str(generated_lines);
+        }
+    }
+    for (key, lines) in &generated_entries {
+        if !written.contains(key) {
+            page.push_str(lines);
+        }
+    }
+    page.push_str(DELIMITER);
+    page.push('\n');
+    if let Some(yaml) = yaml
+        && !merged_cleanly(yaml, generated_yaml, &page, owned)
+    {
+        return None;
+    }
+
+    // Without a recorded checksum, a body that differs may have been edited
+    let recorded = existing_entries
+        .iter()
+        .find(|(key, _)| *key == BODY_CHECKSUM)
+        .and_then(|(_, line)| line.split_once(':'))
+        .map(|(_, checksum)| checksum.trim());
+    let edited = match recorded {
+        Some(checksum) => checksum != content_hash(body.as_bytes()),
+        None => body != generated_body,
+    };
+    page.push_str(if edited { body } else { generated_body });
+    Some(page)
+}
+
+/// Reads the optional keys a page records as written by the generator
+fn recorded_keys(yaml: &str) -> Vec<String> {
+    entries(yaml)
+        .into_iter()
+        .find(|(key, _)| *key == GENERATED_KEYS)
+        .and_then(|(_, lines)| serde_yaml::from_str::<Mapping>(lines).ok())
+        .and_then(|mapping| mapping.get(GENERATED_KEYS).cloned())
+        .and_then(|keys| serde_yaml::from_value(keys).ok())
+        .unwrap_or_default()
+}
+
+/// Checks that merged frontmatter holds the existing page's own keys and the
+/// generated keys, with the same values
+fn merged_cleanly(
+    existing: &str,
+    generated: &str,
+    merged: &str,
+    owned: impl Fn(&str) -> bool,
+) -> bool {
+    let parse_yaml = |yaml: &str| -> Option<Mapping> {
+        if yaml.trim().is_empty() {
+            return Some(Mapping::new());
+        }
+        match serde_yaml::from_str(yaml).ok()? {
+            Value::Mapping(mapping) => Some(mapping),
+            _ => None,
+        }
+    };
+    let (Some(existing), Some(generated), Some(mut merged)) = (
+        parse_yaml(existing),
+        parse_yaml(generated),
+        parse(merged).ok().flatten(),
+    ) else {
+        return false;
+    };
+
+    let mut expected: Mapping = existing
+        .into_iter()
+        .filter(|(key, _)| key.as_str().is_none_or(|key| !owned(key)))
+        .collect();
+    for (key, value) in generated {
+        if !expected.contains_key(&key) {
+            expected.insert(key, value);
+        }
+    }
+    merged.remove(BODY_CHECKSUM);
+    merged.remove(GENERATED_KEYS);
+    merged == expected
+}
+
+/// Splits frontmatter into its top-level keys, each with its lines
+///
+/// Indented lines, list items and comments belong to the key above them;
+/// lines before the first key belong to an empty key.
+fn entries(yaml: &str) -> Vec<(&str, &str)> {
+    let mut entries: Vec<(&str, usize, usize)> = Vec::new();
+    let mut offset = 0;
+    for line in yaml.split_inclusive('\n') {
+        let key = (!line.starts_with([' ', '\t', '-', '#']))
+            .then(|| line.split_once(':'))
+            .flatten()
+            .map(|(key, _)| key.trim().trim_matches(['"', '\'']))
+            .filter(|key| !key.is_empty());
+        match (key, entries.last_mut()) {
+            (Some(key), _) => entries.push((key, offset, offset + line.len())),
+            (None, Some(last)) => last.2 = offset + line.len(),
+            (None, None) => entries.push(("", offset, offset + line.len())),
+        }
+        offset += line.len();
+    }
+    entries
+        .into_iter()
+        .map(|(key, start, end)| (key, &yaml[start..end]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_frontmatter() -> Result<()> {
+        let page = "---\ntitle: Beach, Chicago\nguid: abc123\nf_number: 1.8\n---\n\nA day out\n";
+        let frontmatter = parse(page)?.unwrap();
+        assert_eq!(string(&frontmatter, "guid").as_deref(), Some("abc123"));
+        assert_eq!(number(&frontmatter, "f_number"), Some(1.8));
+        assert_eq!(split(page).unwrap().1, "\nA day out\n");
+
+        assert!(parse("No frontmatter here")?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_date_formats() {
+        let expected = "2024-01-31T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(parse_date("2024-01-31T12:00:00+0000"), Some(expected));
+        assert_eq!(parse_date("2024-01-31T13:00:00+01:00"), Some(expected));
+        assert_eq!(parse_date("2024-01-31 12:00:00"), Some(expected));
+        assert_eq!(
+            parse_date("2024-01-31"),
+            Some("2024-01-31T00:00:00Z".parse().unwrap())
+        );
+        assert_eq!(parse_date("last tuesday"), None);
+    }
+
+    #[test]
+    fn test_quote_round_trips() -> Result<()> {
+        for value in [
+            "Beach: day one",
+            "A \"quoted\" word",
+            "Two\nlines\tand a \\",
+        ] {
+            let page = format!("---\ntitle: {}\n---\n", quote(value));
+            let frontmatter = parse(&page)?.unwrap();
+            assert_eq!(string(&frontmatter, "title").as_deref(), Some(value));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_page_keeps_hand_edits() {
+        let managed = ["title", "photos", "camera_make"];
+        let generated =
+            "---\ntitle: Beach\ncamera_make: Apple\nphotos:\n  - a.jpg\n---\n\nA day out\n";
+        let page = merge_page(None, generated, &managed, &[]).unwrap();
+        assert!(page.starts_with(
+            "---\ntitle: Beach\ncamera_make: Apple\nphotos:\n  - a.jpg\nbody_checksum: "
+        ));
+        assert!(page.ends_with("---\n\nA day out\n"));
+        assert_eq!(
+            merge_page(Some(&page), generated, &managed, &[]).unwrap(),
+            page
+        );
+
+        // Keys added by hand survive, managed keys follow the generator
+        let edited = page.replace(
+            "title: Beach\n",
+            "title: Beach\nfeatured: true\ntags:\n  - summer\n",
+        );
+        let generated = "---\ntitle: Lake\nphotos:\n  - a.jpg\n  - b.jpg\n---\n\nA day out\n";
+        let page = merge_page(Some(&edited), generated, &managed, &[]).unwrap();
+        assert!(page.starts_with(
+            "---\ntitle: Lake\nfeatured: true\ntags:\n  - summer\nphotos:\n  - a.jpg\n  - b.jpg\n"
+        ));
+        assert!(!page.contains("camera_make"));
+
+        // The body is regenerated until it's edited by hand
+        let generated = "---\ntitle: Lake\n---\n\nA day at the lake\n";
+        let page = merge_page(Some(&page), generated, &managed, &[]).unwrap();
+        assert!(page.ends_with("---\n\nA day at the lake\n"));
+        let edited = page.replace("A day at the lake", "We swam all afternoon");
+        let page = merge_page(
+            Some(&edited),
+            "---\ntitle: Lake\n---\n\nNew body\n",
+            &managed,
+            &[],
+        )
+        .unwrap();
+        assert!(page.ends_with("---\n\nWe swam all afternoon\n"));
+    }
+
+    #[test]
+    fn test_merge_page_without_checksum_keeps_edited_body() {
+        let managed = ["title"];
+        let generated = "---\ntitle: Lake\n---\n\nA day at the lake\n";
+
+        let old_page = "---\ntitle: Lake\n---\n\nWe swam all afternoon\n";
+        let page = merge_page(Some(old_page), generated, &managed, &[]).unwrap();
+        assert!(page.contains("body_checksum: "));
+        assert!(page.ends_with("---\n\nWe swam all afternoon\n"));
+
+        let old_page = "---\ntitle: Beach\n---\n\nA day at the lake\n";
+        let page = merge_page(Some(old_page), generated, &managed, &[]).unwrap();
+        assert!(page.starts_with("---\ntitle: Lake\n"));
+        assert!(page.ends_with("---\n\nA day at the lake\n"));
+    }
+
+    #[test]
+    fn test_merge_page_block_scalars() {
+        let managed = ["title", "description"];
+        let existing = "---\ntitle: Old\ndescription: >\n  Folded\n  text\nnotes: |\n  Kept as is\n  key: not a key\n---\n";
+        let generated = "---\ntitle: New\ndescription: |\n  Fresh\n---\n";
+
+        let page = merge_page(Some(existing), generated, &managed, &[]).unwrap();
+        let frontmatter = parse(&page).unwrap().unwrap();
+        assert_eq!(string(&frontmatter, "title").as_deref(), Some("New"));
+        assert_eq!(
+            string(&frontmatter, "description").as_deref(),
+            Some("Fresh\n")
+        );
+        assert_eq!(
+            string(&frontmatter, "notes").as_deref(),
+            Some("Kept as is\nkey: not a key\n")
+        );
+        assert!(!frontmatter.contains_key("key"));
+    }
+
+    #[test]
+    fn test_merge_page_quoted_keys() {
+        let managed = ["title"];
+        let existing = "---\n'title': Old\n\"featured\": true\n---\n";
+        let generated = "---\ntitle: \"New\"\n---\n";
+
+        let page = merge_page(Some(existing), generated, &managed, &[]).unwrap();
+        assert!(page.starts_with("---\ntitle: \"New\"\n\"featured\": true\n"));
+        assert!(!page.contains("Old"));
+    }
+
+    #[test]
+    fn test_merge_page_comments_between_keys() {
+        let managed = ["title", "photo_count"];
+        let existing = "---\n# Written by hand\ntitle: Old\nfeatured: true\n# Keep this one on top\nweight: 1\nphoto_count: 3\n---\n";
+        let generated = "---\ntitle: New\nphoto_count: 4\n---\n";
+
+        let page = merge_page(Some(existing), generated, &managed, &[]).unwrap();
+        assert!(page.starts_with(
+            "---\n# Written by hand\ntitle: New\nfeatured: true\n# Keep this one on top\nweight: 1\nphoto_count: 4\n"
+        ));
+    }
+
+    #[test]
+    fn test_merge_page_leaves_unmergeable_page() {
+        // A flow mapping can't be merged line by line
+        let existing = "---\n{title: Old, featured: true}\n---\n\nBody\n";
+        let generated = "---\ntitle: New\n---\n\nBody\n";
+        assert_eq!(merge_page(Some(existing), generated, &["title"], &[]), None);
+
+        // Neither can frontmatter that isn't valid YAML
+        let existing = "---\ntitle: [unclosed\n---\n\nBody\n";
+        assert_eq!(merge_page(Some(existing), generated, &["title"], &[]), None);
+    }
+}
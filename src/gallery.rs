@@ -7,39 +7,93 @@
 //!
 //! The `GallerySyncer` struct orchestrates these operations, managing the creation
 //! of the gallery page bundle, its index.md file, and downloading all photos into
-//! the gallery directory.
+//! the gallery directory. Photos are downloaded in parallel, up to the output's
+//! download concurrency.
 
 use anyhow::{Context, Result};
 use chrono::Utc;
+use futures::stream::{self, StreamExt};
 use log::{info, warn};
-use reqwest::Client;
 use slugify::slugify;
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tokio::fs as tokio_fs;
 use uuid::Uuid;
 
-use crate::config::PrivacyConfig;
+use crate::config::{DownloadConfig, PrivacyConfig};
+use crate::download::Downloader;
 use crate::exif::extract_exif;
+use crate::frontmatter;
 use crate::geocode::create_geocoding_service;
+use crate::hashtags;
 use crate::icloud::{Album, Photo};
-use crate::index::{Gallery, IndexedPhoto, PhotoIndex};
-use crate::sync::{SyncResult, format_photo_title};
+use crate::index::{Approval, Gallery, IndexedPhoto, PhotoIndex};
+use crate::overrides::{Overrides, PhotoView};
+use crate::retry::RetryPolicy;
+use crate::shutdown::Shutdown;
+use crate::source::extension_for;
+use crate::store::{IndexStore, YamlStore};
+use crate::sync::SyncResult;
+
+/// Frontmatter keys of a gallery page that syncs own; other keys are kept
+const PAGE_KEYS: &[&str] = &[
+    "title",
+    "date",
+    "lastmod",
+    "type",
+    "layout",
+    "uuid",
+    "slug",
+    "nofeed",
+    "noindex",
+    "unlisted",
+    "robots",
+    "description",
+    "photo_count",
+    "photos",
+];
+
+/// Finds the gallery a gallery output's page shows, by the UUID in its
+/// frontmatter
+pub fn shown_gallery<'a>(content_dir: &Path, index: &'a PhotoIndex) -> Option<&'a Gallery> {
+    let page = content_dir.join("index.md");
+    let uuid = match frontmatter::read(&page) {
+        Ok(frontmatter) => frontmatter.and_then(|f| frontmatter::string(&f, "uuid"))?,
+        Err(e) => {
+            log::debug!("Can't read the gallery page: {e:#}");
+            return None;
+        }
+    };
+    index.galleries.values().find(|g| g.uuid == uuid)
+}
 
 /// Responsible for syncing photos from iCloud into a gallery
 pub struct GallerySyncer {
-    /// HTTP client for downloading photos
-    client: Client,
+    /// Downloads photos, retrying transient failures
+    downloader: Downloader,
     /// Base directory for storing the gallery
     content_dir: PathBuf,
     /// Gallery name for display
     gallery_name: String,
     /// Optional gallery description
     gallery_description: Option<String>,
-    /// Path to the index file
-    index_path: PathBuf,
+    /// Where the photo index is saved
+    store: Arc<dyn IndexStore>,
     /// Privacy configuration for Hugo frontmatter
     privacy_config: PrivacyConfig,
+    /// Re-render the gallery from files on disk without downloading or deleting
+    offline: bool,
+    /// Number of photos processed at the same time
+    concurrency: usize,
+    /// Stops new photos from being started when the sync is interrupted
+    shutdown: Shutdown,
+    /// Per-photo overrides applied when the page is rendered
+    overrides: Arc<Overrides>,
+    /// New photos stay off the page until they're approved
+    require_approval: bool,
+    /// Directory of the Hugo site, which indexed photo paths are relative to
+    site_root: PathBuf,
 }
 
 impl GallerySyncer {
@@ -52,15 +106,75 @@ impl GallerySyncer {
         privacy_config: PrivacyConfig,
     ) -> Self {
         Self {
-            client: Client::new(),
+            downloader: Downloader::default(),
             content_dir,
             gallery_name: gallery_name.unwrap_or_else(|| "Gallery".to_string()),
             gallery_description,
-            index_path,
+            store: Arc::new(YamlStore::new(index_path, PathBuf::new())),
             privacy_config,
+            offline: false,
+            concurrency: DownloadConfig::default().concurrency(),
+            shutdown: Shutdown::default(),
+            overrides: Arc::default(),
+            require_approval: false,
+            site_root: PathBuf::new(),
         }
     }
 
+    /// Sets the retry policy used for downloads
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.downloader = self.downloader.with_retry_policy(retry_policy);
+        self
+    }
+
+    /// Sets how many photos are processed at once and the bandwidth cap
+    pub fn with_download_config(mut self, config: &DownloadConfig) -> Self {
+        self.concurrency = config.concurrency();
+        self.downloader = self
+            .downloader
+            .with_bandwidth_limit(config.max_bytes_per_second);
+        self
+    }
+
+    /// Sets whether to re-render every page from the files already on disk
+    /// instead of downloading anything or deleting photos
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self.downloader = self.downloader.with_offline(offline);
+        self
+    }
+
+    /// Sets the flag that tells the syncer to stop starting new photos
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Sets where the photo index is saved, instead of the YAML index file
+    pub fn with_index_store(mut self, store: Arc<dyn IndexStore>) -> Self {
+        self.store = store;
+        self
+    }
+
+    /// Sets the per-photo overrides applied when the page is rendered
+    pub fn with_overrides(mut self, overrides: Arc<Overrides>) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// Sets whether new photos stay off the page until they're approved
+    pub fn with_require_approval(mut self, require_approval: bool) -> Self {
+        self.require_approval = require_approval;
+        self
+    }
+
+    /// Sets the directory of the Hugo site, which indexed photo paths are
+    /// stored relative to
+    pub fn with_site_root(mut self, site_root: PathBuf) -> Self {
+        self.site_root = site_root;
+        self
+    }
+
     /// Syncs photos from the remote album to a gallery
     pub async fn sync_gallery(
         &self,
@@ -96,8 +210,19 @@ impl GallerySyncer {
         // First, identify which photos need processing
         let mut to_add = Vec::new();
         let mut to_update = Vec::new();
+        let mut metadata_updates = Vec::new();
         let mut unchanged = Vec::new();
 
+        // Follow renames, so the existing gallery keeps its photos
+        let slug = slugify!(&gallery_name);
+        index.update_gallery(&gallery_id, |gallery| {
+            if gallery.name != gallery_name {
+                gallery.name = gallery_name.clone();
+                gallery.slug = slug;
+                gallery.updated_at = Utc::now();
+            }
+        });
+
         let gallery = match index.get_gallery(&gallery_id) {
             Some(g) => g,
             None => {
@@ -123,10 +248,25 @@ impl GallerySyncer {
 
             // Check if the photo exists in our index
             if let Some(indexed_photo) = index.get_photo(guid) {
-                // Photo exists, check if it needs updating
-                if indexed_photo.checksum == photo.checksum && existing_photos.contains(guid) {
-                    // Photo is unchanged
+                // Rejected photos are never downloaded again
+                if indexed_photo.is_rejected() {
                     unchanged.push(SyncResult::Unchanged(guid.clone()));
+                    continue;
+                }
+
+                // Photo exists, check if it needs updating
+                // Offline, every photo is processed again from the file on disk
+                if !self.offline
+                    && indexed_photo.checksum == photo.checksum
+                    && existing_photos.contains(guid)
+                {
+                    if indexed_photo.metadata_differs(photo) {
+                        // Same content, but the caption, date, etc. changed
+                        metadata_updates.push(photo);
+                    } else {
+                        // Photo is unchanged
+                        unchanged.push(SyncResult::Unchanged(guid.clone()));
+                    }
                 } else {
                     // Photo needs updating
                     to_update.push((guid.clone(), photo.clone(), photo_path));
@@ -137,81 +277,125 @@ impl GallerySyncer {
             }
         }
 
-        // Find photos to remove (in gallery but not in album)
+        // Find photos to remove (in gallery but not in album). Offline syncs
+        // work from a saved album, so they never remove anything, and rejected
+        // photos stay so they're never added again.
         let remote_guids: HashSet<&String> = album.photos.keys().collect();
         let to_remove: Vec<String> = gallery
             .photos
             .iter()
-            .filter(|guid| !remote_guids.contains(guid))
+            .filter(|guid| {
+                !self.offline
+                    && !remote_guids.contains(guid)
+                    && !index.get_photo(guid).is_some_and(IndexedPhoto::is_rejected)
+            })
             .cloned()
             .collect();
 
-        // Add new photos
-        for (guid, photo, photo_path) in to_add {
-            match self.process_photo(&photo, &photo_path).await {
-                Ok(indexed_photo) => {
-                    // Add to index
+        // Download new and changed photos in parallel, recording each one in
+        // the index as soon as it is done
+        let mut processed = stream::iter(
+            to_add
+                .into_iter()
+                .map(|photo| (false, photo))
+                .chain(to_update.into_iter().map(|photo| (true, photo))),
+        )
+        .map(|(is_update, (guid, photo, photo_path))| async move {
+            // Don't start anything new once a shutdown was requested
+            if self.shutdown.is_requested() {
+                return None;
+            }
+            let result = self.process_photo(&photo, &photo_path).await;
+            Some((is_update, guid, result))
+        })
+        .buffered(self.concurrency);
+
+        while let Some(item) = processed.next().await {
+            let Some((is_update, guid, result)) = item else {
+                continue;
+            };
+
+            match result {
+                Ok(mut indexed_photo) => {
+                    // Keep the approval state; new photos wait for approval if required
+                    indexed_photo.approval = match index.get_photo(&guid) {
+                        Some(existing) => existing.approval,
+                        None => self.require_approval.then_some(Approval::Pending),
+                    };
                     index.add_or_update_photo(indexed_photo);
 
-                    // Add to gallery
-                    if let Some(gallery) = index.galleries.get_mut(&gallery_id) {
-                        gallery.add_photo(guid.clone());
-                    }
+                    // Add to gallery if not already there
+                    index.update_gallery(&gallery_id, |gallery| gallery.add_photo(guid.clone()));
 
-                    results.push(SyncResult::Added(guid));
+                    results.push(if is_update {
+                        SyncResult::Updated(guid)
+                    } else {
+                        SyncResult::Added(guid)
+                    });
                 }
                 Err(e) => {
+                    let action = if is_update { "update" } else { "process" };
                     results.push(SyncResult::Failed(
                         guid,
-                        format!("Failed to process photo: {e}"),
+                        format!("Failed to {action} photo: {e}"),
                     ));
                 }
             }
         }
 
-        // Update existing photos
-        for (guid, photo, photo_path) in to_update {
-            match self.process_photo(&photo, &photo_path).await {
-                Ok(indexed_photo) => {
-                    // Update in index
-                    index.add_or_update_photo(indexed_photo);
-
-                    // Add to gallery if not already there
-                    if let Some(gallery) = index.galleries.get_mut(&gallery_id) {
-                        if !gallery.photos.contains(&guid) {
-                            gallery.add_photo(guid.clone());
-                        }
-                    }
+        // An interrupted sync leaves metadata changes and removals for the next run
+        let (metadata_updates, to_remove) = if self.shutdown.is_requested() {
+            (Vec::new(), Vec::new())
+        } else {
+            (metadata_updates, to_remove)
+        };
 
-                    results.push(SyncResult::Updated(guid));
-                }
-                Err(e) => {
-                    results.push(SyncResult::Failed(
-                        guid,
-                        format!("Failed to update photo: {e}"),
-                    ));
-                }
+        // Apply metadata-only changes; the gallery page is re-rendered below
+        for photo in metadata_updates {
+            if let Some(mut indexed_photo) = index.get_photo(&photo.guid).cloned() {
+                indexed_photo.update_metadata(photo);
+                index.add_or_update_photo(indexed_photo);
+                results.push(SyncResult::MetadataUpdated(photo.guid.clone()));
             }
         }
 
         // Remove deleted photos from gallery (but keep them in the index)
         for guid in to_remove {
             // Remove from gallery but not from index
-            if let Some(gallery) = index.galleries.get_mut(&gallery_id) {
-                gallery.remove_photo(&guid);
-            }
+            index.update_gallery(&gallery_id, |gallery| gallery.remove_photo(&guid));
 
             // Try to remove the file
             let photo_path = gallery_dir.join(format!("{guid}.jpg"));
-            if photo_path.exists() {
-                if let Err(e) = tokio_fs::remove_file(&photo_path).await {
-                    warn!("Failed to delete photo file {guid}: {e}");
-                }
+            if photo_path.exists()
+                && let Err(e) = tokio_fs::remove_file(&photo_path).await
+            {
+                warn!("Failed to delete photo file {guid}: {e}");
             }
 
             results.push(SyncResult::Deleted(guid));
         }
 
+        // Keep the gallery in a stable order, oldest photo first, and only
+        // move its modification time when one of its photos changed
+        let content_changed = results
+            .iter()
+            .any(|result| !matches!(result, SyncResult::Failed(..)));
+        let mut ordered = index
+            .get_gallery(&gallery_id)
+            .map(|gallery| gallery.photos.clone())
+            .unwrap_or_default();
+        ordered.sort_by(|a, b| {
+            let created_at = |guid| index.get_photo(guid).map(|photo| photo.created_at);
+            created_at(a).cmp(&created_at(b)).then_with(|| a.cmp(b))
+        });
+        let now = Utc::now();
+        index.update_gallery(&gallery_id, |gallery| {
+            gallery.photos = ordered;
+            if content_changed {
+                gallery.updated_at = now;
+            }
+        });
+
         // Add unchanged photos
         results.extend(unchanged);
 
@@ -221,7 +405,7 @@ impl GallerySyncer {
             .context("Failed to create gallery index.md")?;
 
         // Save the updated index
-        index.save(&self.index_path)?;
+        self.store.save(index)?;
 
         Ok(results)
     }
@@ -229,7 +413,8 @@ impl GallerySyncer {
     /// Processes a single photo for the gallery
     async fn process_photo(&self, photo: &Photo, photo_path: &Path) -> Result<IndexedPhoto> {
         // Download the image
-        self.download_photo(photo, photo_path)
+        self.downloader
+            .download(photo, photo_path)
             .await
             .with_context(|| format!("Failed to download photo {}", photo.guid))?;
 
@@ -244,7 +429,14 @@ impl GallerySyncer {
             photo.width,
             photo.height,
             photo_path.to_path_buf(),
+            &self.site_root,
         );
+        indexed_photo.contributor = photo.contributor.clone();
+        indexed_photo.sources = photo.sources.clone();
+        indexed_photo.asset_checksum = photo.asset_checksum.clone();
+        if let Err(e) = indexed_photo.record_file(&self.site_root) {
+            warn!("Failed to hash photo {}: {e:#}", photo.guid);
+        }
 
         // Extract EXIF data if possible
         if photo_path.exists() {
@@ -280,51 +472,13 @@ impl GallerySyncer {
         Ok(indexed_photo)
     }
 
-    /// Downloads a photo from its URL
-    async fn download_photo(&self, photo: &Photo, path: &Path) -> Result<()> {
-        // For tests, create a placeholder file instead of actually downloading
-        if cfg!(test) {
-            // Create a placeholder image for tests only
-            tokio_fs::write(path, "PLACEHOLDER IMAGE CONTENT")
-                .await
-                .with_context(|| {
-                    format!("Failed to write test placeholder to {}", path.display())
-                })?;
-            return Ok(());
-        }
-
-        // Check for test URLs explicitly - looking for exact test domains
-        if photo.url.starts_with("https://example.com/")
-            || photo.url.starts_with("http://example.com/")
-            || photo.url.starts_with("https://test.example/")
-        {
-            // Create a placeholder for test URLs
-            tokio_fs::write(path, "PLACEHOLDER TEST URL IMAGE CONTENT")
-                .await
-                .with_context(|| {
-                    format!("Failed to write test URL placeholder to {}", path.display())
-                })?;
-            return Ok(());
-        }
-
-        // Otherwise, download the actual image
-        let response = self
-            .client
-            .get(&photo.url)
-            .send()
-            .await
-            .with_context(|| format!("Failed to GET photo from {}", photo.url))?;
-
-        let bytes = response
-            .bytes()
+    /// Writes the index.md of an indexed gallery
+    pub async fn render_gallery(&self, index: &PhotoIndex, gallery_id: &str) -> Result<()> {
+        tokio_fs::create_dir_all(&self.content_dir)
             .await
-            .context("Failed to read photo bytes")?;
-
-        tokio_fs::write(path, bytes)
+            .context("Failed to create gallery directory")?;
+        self.create_gallery_index(index, gallery_id, &self.content_dir)
             .await
-            .with_context(|| format!("Failed to write photo to {}", path.display()))?;
-
-        Ok(())
     }
 
     /// Creates a gallery index.md file with frontmatter and references to all photos
@@ -341,14 +495,22 @@ impl GallerySyncer {
             None => return Err(anyhow::anyhow!("Gallery not found: {}", gallery_id)),
         };
 
-        // Get all photos in the gallery
-        let gallery_photos = index.get_gallery_photos(gallery_id);
+        // Get the photos in the gallery with their overrides applied, leaving
+        // out hidden ones; an overridden date can move a photo
+        let mut gallery_photos: Vec<PhotoView> = index
+            .get_gallery_photos(gallery_id)
+            .into_iter()
+            .map(|photo| self.overrides.apply(photo))
+            .filter(|view| !view.hidden)
+            .collect();
+        gallery_photos.sort_by_key(|view| view.photo.created_at);
 
-        // Build frontmatter
+        // Build frontmatter; the dates only change along with the gallery
         let mut content = format!(
-            "---\ntitle: {}\ndate: {}\ntype: gallery\nlayout: gallery\n",
-            gallery.name,
-            Utc::now().format("%Y-%m-%dT%H:%M:%S%z")
+            "---\ntitle: {}\ndate: {}\nlastmod: {}\ntype: gallery\nlayout: gallery\n",
+            frontmatter::quote(&gallery.name),
+            gallery.created_at.format("%Y-%m-%dT%H:%M:%S%z"),
+            gallery.updated_at.format("%Y-%m-%dT%H:%M:%S%z")
         );
 
         // Add UUID
@@ -375,7 +537,21 @@ impl GallerySyncer {
 
         // Add description if available
         if let Some(ref description) = gallery.description {
-            content.push_str(&format!("description: \"{description}\"\n"));
+            content.push_str(&format!(
+                "description: {}\n",
+                frontmatter::quote(description)
+            ));
+        }
+
+        // Add the tags of all photos when the output writes hashtags
+        if let Some(taxonomy) = self.overrides.taxonomy() {
+            let terms: BTreeSet<&String> = gallery_photos
+                .iter()
+                .flat_map(|view| view.tags.iter().flatten())
+                .collect();
+            if !terms.is_empty() {
+                hashtags::push_terms(&mut content, taxonomy, terms);
+            }
         }
 
         // Add photo count
@@ -383,49 +559,69 @@ impl GallerySyncer {
 
         // Add gallery photo list
         content.push_str("photos:\n");
-        for photo in &gallery_photos {
+        for view in &gallery_photos {
+            let photo = view.photo.as_ref();
             // Get correct file extension based on MIME type
-            let extension = match photo.mime_type.as_str() {
-                "image/jpeg" => "jpg",
-                "image/png" => "png",
-                "image/heic" => "heic",
-                "image/gif" => "gif",
-                "image/webp" => "webp",
-                "video/mp4" => "mp4",
-                _ => "jpg", // Default to jpg for unknown types
-            };
+            let extension = extension_for(&photo.mime_type);
 
             let filename = format!("{}.{}", photo.guid, extension);
 
-            // Generate a formatted title with date, location, and camera info
-            let formatted_title = format_photo_title(photo);
+            // The title is made from the date, location and camera info
+            // unless it's overridden
+            let formatted_title = &view.title;
 
             content.push_str(&format!("  - filename: {filename}\n"));
-            content.push_str(&format!("    caption: \"{formatted_title}\"\n"));
+            content.push_str(&format!(
+                "    caption: {}\n",
+                frontmatter::quote(formatted_title)
+            ));
             content.push_str(&format!("    mime_type: \"{}\"\n", photo.mime_type));
 
+            // Add alt text and tags if they were given
+            if let Some(ref alt) = view.alt {
+                content.push_str(&format!("    alt: {}\n", frontmatter::quote(alt)));
+            }
+            if let Some(ref tags) = view.tags {
+                let tags: Vec<String> = tags.iter().map(|tag| frontmatter::quote(tag)).collect();
+                content.push_str(&format!("    tags: [{}]\n", tags.join(", ")));
+            }
+
             // Add original caption if available
-            if let Some(ref caption) = photo.caption {
-                if !caption.trim().is_empty() {
-                    content.push_str(&format!("    original_caption: \"{caption}\"\n"));
-                }
+            if let Some(ref caption) = photo.caption
+                && !caption.trim().is_empty()
+            {
+                content.push_str(&format!(
+                    "    original_caption: {}\n",
+                    frontmatter::quote(caption)
+                ));
+            }
+
+            // Add contributor if available
+            if let Some(ref contributor) = photo.contributor {
+                content.push_str(&format!(
+                    "    contributor: {}\n",
+                    frontmatter::quote(contributor)
+                ));
             }
 
             // Add location if available
             if let Some(ref location) = photo.location {
                 content.push_str(&format!(
-                    "    location: \"{}\"\n",
-                    location.formatted_address
+                    "    location: {}\n",
+                    frontmatter::quote(&location.formatted_address)
                 ));
             }
 
             // Add camera if available
             if let Some(ref make) = photo.camera_make {
-                content.push_str(&format!("    camera_make: \"{make}\"\n"));
+                content.push_str(&format!("    camera_make: {}\n", frontmatter::quote(make)));
             }
 
             if let Some(ref model) = photo.camera_model {
-                content.push_str(&format!("    camera_model: \"{model}\"\n"));
+                content.push_str(&format!(
+                    "    camera_model: {}\n",
+                    frontmatter::quote(model)
+                ));
             }
 
             // Add date
@@ -446,42 +642,41 @@ impl GallerySyncer {
         }
 
         // Add figure shortcodes for each photo
-        for photo in &gallery_photos {
+        for view in &gallery_photos {
+            let photo = view.photo.as_ref();
             // Get correct file extension based on MIME type
-            let extension = match photo.mime_type.as_str() {
-                "image/jpeg" => "jpg",
-                "image/png" => "png",
-                "image/heic" => "heic",
-                "image/gif" => "gif",
-                "image/webp" => "webp",
-                "video/mp4" => "mp4",
-                _ => "jpg", // Default to jpg for unknown types
-            };
+            let extension = extension_for(&photo.mime_type);
 
             let filename = format!("{}.{}", photo.guid, extension);
 
-            // Generate a formatted title with date, location, and camera info
-            let formatted_title = format_photo_title(photo);
+            // The title is made from the date, location and camera info
+            // unless it's overridden
+            let formatted_title = &view.title;
 
-            // Format the title, escaping any quotes
+            // Format the title and alt text, escaping any quotes
             let caption = formatted_title.replace('"', "\\\"");
+            let alt = view
+                .alt
+                .as_deref()
+                .map_or_else(|| caption.clone(), |alt| alt.replace('"', "\\\""));
 
             // For videos, use a video shortcode instead of figure
-            if photo.mime_type == "video/mp4" {
+            if photo.mime_type.starts_with("video/") {
                 content.push_str(&format!(
                     "{{{{< video src=\"{filename}\" caption=\"{caption}\" >}}}}\n\n"
                 ));
             } else {
                 // Build the figure shortcode for images
                 content.push_str(&format!(
-                    "{{{{< figure\n  src=\"{filename}\"\n  alt=\"{caption}\"\n  caption=\"{caption}\"\n  class=\"ma0 w-75\"\n>}}}}\n\n"
+                    "{{{{< figure\n  src=\"{filename}\"\n  alt=\"{alt}\"\n  caption=\"{caption}\"\n  class=\"ma0 w-75\"\n>}}}}\n\n"
                 ));
             }
         }
 
         // Write to index.md
         let index_path = gallery_dir.join("index.md");
-        tokio_fs::write(&index_path, content)
+        let optional: Vec<&str> = self.overrides.taxonomy().into_iter().collect();
+        frontmatter::write_page(&index_path, &content, PAGE_KEYS, &optional)
             .await
             .with_context(|| {
                 format!(
@@ -509,6 +704,14 @@ impl GallerySyncer {
             }
         }
 
+        // An output has a single gallery, so a lone gallery with another name
+        // is this one after the album or the configured name changed
+        if index.galleries.len() == 1
+            && let Some(gallery) = index.galleries.values().next()
+        {
+            return Ok(gallery.id.clone());
+        }
+
         // Generate a gallery ID using UUID
         let gallery_id = format!("gallery_{}", Uuid::new_v4());
 
@@ -519,6 +722,7 @@ impl GallerySyncer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::HashtagConfig;
     use chrono::Utc;
     use std::fs;
     use tempfile::tempdir;
@@ -534,6 +738,11 @@ mod tests {
             width: 800,
             height: 600,
             mime_type: "image/jpeg".to_string(),
+            contributor: None,
+            batch_guid: None,
+            batch_created_at: None,
+            asset_checksum: None,
+            sources: Vec::new(),
         }
     }
 
@@ -611,7 +820,7 @@ mod tests {
 
         // Read the index.md
         let index_md = fs::read_to_string(content_dir.join("index.md"))?;
-        assert!(index_md.contains("title: Test Gallery"));
+        assert!(index_md.contains("title: \"Test Gallery\""));
         assert!(index_md.contains("type: gallery"));
         assert!(index_md.contains("description: \"Test gallery description\""));
         assert!(index_md.contains("photo_count: 2"));
@@ -630,6 +839,83 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_resync_without_changes_is_byte_identical() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content");
+        let index_path = temp_dir.path().join("index.yaml");
+        let gallery_syncer = GallerySyncer::new(
+            content_dir.clone(),
+            Some("Test Gallery".to_string()),
+            None,
+            index_path.clone(),
+            PrivacyConfig::default(),
+        );
+
+        // Photos are listed oldest first, whatever order the album has them in
+        let mut album = Album::new("Test Album".to_string());
+        let start = Utc::now();
+        for (guid, minutes) in [("b", 2), ("c", 1), ("a", 3)] {
+            let mut photo = create_test_photo(guid);
+            photo.created_at = start - chrono::Duration::minutes(minutes);
+            album.photos.insert(photo.guid.clone(), photo);
+        }
+
+        let mut index = PhotoIndex::new();
+        gallery_syncer.sync_gallery(&album, &mut index).await?;
+        let gallery = index.galleries.values().next().unwrap();
+        assert_eq!(gallery.photos, vec!["a", "b", "c"]);
+
+        let index_md = fs::read(content_dir.join("index.md"))?;
+        let index_yaml = fs::read(&index_path)?;
+
+        let mut index = PhotoIndex::load(&index_path, Path::new(""))?;
+        gallery_syncer.sync_gallery(&album, &mut index).await?;
+        assert_eq!(fs::read(content_dir.join("index.md"))?, index_md);
+        assert_eq!(fs::read(&index_path)?, index_yaml);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sync_gallery_metadata_update() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content");
+        let index_path = temp_dir.path().join("index.yaml");
+
+        let gallery_syncer = GallerySyncer::new(
+            content_dir.clone(),
+            Some("Test Gallery".to_string()),
+            None,
+            index_path.clone(),
+            PrivacyConfig::default(),
+        );
+
+        let mut index = PhotoIndex::new();
+        let mut album = create_test_album();
+        gallery_syncer.sync_gallery(&album, &mut index).await?;
+
+        // Edit a caption without touching the photo content
+        album.photos.get_mut("photo1").unwrap().caption = Some("Edited caption".to_string());
+        let results = gallery_syncer.sync_gallery(&album, &mut index).await?;
+
+        assert_eq!(results.len(), 2);
+        assert!(
+            results
+                .iter()
+                .any(|r| matches!(r, SyncResult::MetadataUpdated(guid) if guid == "photo1"))
+        );
+        assert_eq!(
+            index.get_photo("photo1").unwrap().caption,
+            Some("Edited caption".to_string())
+        );
+
+        let index_md = fs::read_to_string(content_dir.join("index.md"))?;
+        assert!(index_md.contains("original_caption: \"Edited caption\""));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_privacy_frontmatter_generation() -> Result<()> {
         // Create a temporary directory for the test
@@ -685,6 +971,141 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_renamed_album_keeps_its_gallery() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content");
+        let gallery_syncer = GallerySyncer::new(
+            content_dir.clone(),
+            None,
+            None,
+            temp_dir.path().join("index.yaml"),
+            PrivacyConfig::default(),
+        );
+
+        let mut index = PhotoIndex::new();
+        let mut album = create_test_album();
+        gallery_syncer.sync_gallery(&album, &mut index).await?;
+        let gallery_id = index.galleries.keys().next().unwrap().clone();
+
+        album.name = "Renamed Album".to_string();
+        gallery_syncer.sync_gallery(&album, &mut index).await?;
+
+        assert_eq!(index.gallery_count(), 1);
+        let gallery = index.get_gallery(&gallery_id).unwrap();
+        assert_eq!(gallery.name, "Renamed Album");
+        assert_eq!(gallery.slug, "renamed-album");
+        assert_eq!(gallery.photos.len(), album.photos.len());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_hand_edits_survive_sync() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content");
+        let gallery_syncer = GallerySyncer::new(
+            content_dir.clone(),
+            None,
+            None,
+            temp_dir.path().join("index.yaml"),
+            PrivacyConfig::default(),
+        );
+
+        let mut index = PhotoIndex::new();
+        let mut album = create_test_album();
+        gallery_syncer.sync_gallery(&album, &mut index).await?;
+
+        let page_path = content_dir.join("index.md");
+        let page = fs::read_to_string(&page_path)?;
+        let (_, body) = frontmatter::split(&page).unwrap();
+        let edited = page
+            .replacen("type: gallery\n", "type: gallery\nfeatured: true\n", 1)
+            .replace(body, "\nOur trip, in order.\n");
+        fs::write(&page_path, edited)?;
+
+        album
+            .photos
+            .insert("photo4".to_string(), create_test_photo("photo4"));
+        gallery_syncer.sync_gallery(&album, &mut index).await?;
+
+        let page = fs::read_to_string(&page_path)?;
+        assert!(page.contains("featured: true"));
+        assert!(page.contains(&format!("photo_count: {}", album.photos.len())));
+        assert!(page.ends_with("---\n\nOur trip, in order.\n"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_overrides_in_gallery_page() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content");
+        let overrides_path = temp_dir.path().join("overrides.yaml");
+        fs::write(
+            &overrides_path,
+            "photo1:\n  title: Grandma's \"90th\"\n  alt: Grandma blowing out candles\nphoto2:\n  hidden: true\n",
+        )?;
+        let gallery_syncer = GallerySyncer::new(
+            content_dir.clone(),
+            None,
+            None,
+            temp_dir.path().join("index.yaml"),
+            PrivacyConfig::default(),
+        )
+        .with_overrides(Arc::new(Overrides::load(&overrides_path)?));
+
+        let mut index = PhotoIndex::new();
+        gallery_syncer
+            .sync_gallery(&create_test_album(), &mut index)
+            .await?;
+
+        let page = fs::read_to_string(content_dir.join("index.md"))?;
+        assert!(page.contains("caption: \"Grandma's \\\"90th\\\"\""));
+        assert!(page.contains("alt=\"Grandma blowing out candles\""));
+        assert!(page.contains("photo_count: 1"));
+        assert!(!page.contains("photo2"));
+        assert_eq!(
+            index.galleries.values().next().unwrap().photos.len(),
+            2,
+            "Hidden photos stay in the index"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_hashtags_in_gallery_page() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content");
+        let hashtags = HashtagConfig {
+            taxonomy: "topics".to_string(),
+            ..HashtagConfig::default()
+        };
+        let gallery_syncer = GallerySyncer::new(
+            content_dir.clone(),
+            None,
+            None,
+            temp_dir.path().join("index.yaml"),
+            PrivacyConfig::default(),
+        )
+        .with_overrides(Arc::new(Overrides::default().with_hashtags(Some(hashtags))));
+
+        let mut album = create_test_album();
+        album.photos.get_mut("photo1").unwrap().caption = Some("Beach #summer #kids".to_string());
+        album.photos.get_mut("photo2").unwrap().caption = Some("Pool #summer".to_string());
+        let mut index = PhotoIndex::new();
+        gallery_syncer.sync_gallery(&album, &mut index).await?;
+
+        // The gallery gets the terms of all its photos, each photo its own
+        let page = fs::read_to_string(content_dir.join("index.md"))?;
+        assert!(page.contains("topics:\n  - \"kids\"\n  - \"summer\"\n"));
+        assert!(page.contains("    tags: [\"summer\", \"kids\"]\n"));
+        assert!(page.contains("    tags: [\"summer\"]\n"));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_uuid_id_generation() -> Result<()> {
         // Create a temporary directory for the test
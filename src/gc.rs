@@ -0,0 +1,335 @@
+//! Garbage collection for the photo index.
+//!
+//! Photos removed from a gallery stay in the index, and galleries left behind
+//! by renamed albums (before syncs followed renames) were never cleaned up,
+//! so gallery indexes only ever grew. Garbage collection removes the galleries
+//! an output no longer renders, drops the photos that no gallery or batch
+//! references any more, and deletes their files if they're still on disk.
+//!
+//! A photostream output renders every photo in its index, so nothing in it is
+//! garbage.
+
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::OutputType;
+use crate::gallery;
+use crate::index::PhotoIndex;
+
+/// What garbage collection removed, or would remove in a dry run
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GcReport {
+    /// Names of the galleries that were removed
+    pub galleries: Vec<String>,
+    /// GUIDs of the photos that were dropped from the index
+    pub photos: Vec<String>,
+    /// Files of dropped photos that were deleted
+    pub files: Vec<PathBuf>,
+    /// Bytes freed by deleting those files
+    pub bytes: u64,
+    /// Problems that kept something from being collected
+    pub warnings: Vec<String>,
+}
+
+impl GcReport {
+    /// Whether there was nothing to collect
+    pub fn is_empty(&self) -> bool {
+        self.galleries.is_empty() && self.photos.is_empty()
+    }
+}
+
+/// Removes what an output no longer uses from its index and content directory
+///
+/// With `dry_run`, only reports what would be removed. `site_root` is the
+/// directory of the Hugo site, which photo paths in the index are relative to.
+pub fn collect_garbage(
+    output_type: &OutputType,
+    content_dir: &Path,
+    site_root: &Path,
+    index: &mut PhotoIndex,
+    dry_run: bool,
+) -> Result<GcReport> {
+    let mut report = GcReport::default();
+
+    let stale = match output_type {
+        OutputType::Photostream => return Ok(report),
+        OutputType::Gallery => stale_galleries(content_dir, index, &mut report),
+        OutputType::Batches => HashSet::new(),
+    };
+    let referenced: HashSet<String> = match output_type {
+        OutputType::Gallery => index
+            .galleries
+            .values()
+            .filter(|gallery| !stale.contains(&gallery.id))
+            .flat_map(|gallery| gallery.photos.iter().cloned())
+            .collect(),
+        _ => index
+            .batches
+            .values()
+            .flat_map(|batch| batch.photos.iter().cloned())
+            .collect(),
+    };
+
+    let kept_paths: HashSet<PathBuf> = index
+        .photos
+        .values()
+        .filter(|photo| referenced.contains(&photo.guid))
+        .map(|photo| photo.file_path(site_root))
+        .collect();
+
+    let mut files = Vec::new();
+    for photo in index.photos.values() {
+        // Rejected photos stay in the index so syncs never add them again
+        if referenced.contains(&photo.guid) || photo.is_rejected() {
+            continue;
+        }
+        report.photos.push(photo.guid.clone());
+
+        // Two entries can point at the same file, keep it if either is kept
+        let path = photo.file_path(site_root);
+        if !kept_paths.contains(&path)
+            && let Ok(metadata) = fs::metadata(&path)
+            && metadata.is_file()
+        {
+            report.bytes += metadata.len();
+            files.push(path);
+        }
+    }
+    report.files = files;
+
+    if dry_run {
+        return Ok(report);
+    }
+
+    for id in &stale {
+        index.remove_gallery(id);
+    }
+    for guid in &report.photos {
+        index.remove_photo(guid);
+    }
+    for file in &report.files {
+        fs::remove_file(file).with_context(|| format!("Failed to delete {}", file.display()))?;
+    }
+
+    Ok(report)
+}
+
+/// Finds the IDs of the galleries a gallery output no longer renders
+///
+/// The output's page names the gallery it shows by its UUID. Without a page
+/// to tell, no gallery is considered stale.
+fn stale_galleries(
+    content_dir: &Path,
+    index: &PhotoIndex,
+    report: &mut GcReport,
+) -> HashSet<String> {
+    if index.galleries.len() <= 1 {
+        return HashSet::new();
+    }
+
+    let Some(current) = gallery::shown_gallery(content_dir, index) else {
+        report.warnings.push(format!(
+            "Can't tell which gallery {} shows, so no gallery was removed; sync the output first",
+            content_dir.join("index.md").display()
+        ));
+        return HashSet::new();
+    };
+
+    let stale: HashSet<String> = index
+        .galleries
+        .values()
+        .filter(|gallery| gallery.id != current.id)
+        .map(|gallery| gallery.id.clone())
+        .collect();
+    report.galleries = index
+        .galleries
+        .values()
+        .filter(|gallery| stale.contains(&gallery.id))
+        .map(|gallery| gallery.name.clone())
+        .collect();
+    stale
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::{Approval, Batch, Gallery, IndexedPhoto};
+    use chrono::Utc;
+    use tempfile::tempdir;
+
+    fn create_photo(site_root: &Path, content_dir: &Path, guid: &str) -> Result<IndexedPhoto> {
+        let path = content_dir.join(format!("{guid}.jpg"));
+        fs::write(&path, "IMAGE")?;
+        Ok(IndexedPhoto::new(
+            guid.to_string(),
+            format!("{guid}.jpg"),
+            None,
+            Utc::now(),
+            format!("checksum_{guid}"),
+            format!("https://example.com/{guid}.jpg"),
+            800,
+            600,
+            path,
+            site_root,
+        ))
+    }
+
+    /// Creates a gallery output whose album was renamed, leaving the old
+    /// gallery behind, and whose page shows the current gallery
+    fn renamed_gallery(site_root: &Path, content_dir: &Path) -> Result<PhotoIndex> {
+        fs::create_dir_all(content_dir)?;
+        let mut index = PhotoIndex::new();
+        for guid in ["kept", "removed", "renamed"] {
+            index.add_or_update_photo(create_photo(site_root, content_dir, guid)?);
+        }
+
+        let mut old = Gallery::new(
+            "gallery_old".to_string(),
+            "Old Name".to_string(),
+            "old-name".to_string(),
+            None,
+        );
+        old.add_photo("kept".to_string());
+        old.add_photo("renamed".to_string());
+        let mut current = Gallery::new(
+            "gallery_new".to_string(),
+            "New Name".to_string(),
+            "new-name".to_string(),
+            None,
+        );
+        current.add_photo("kept".to_string());
+        fs::write(
+            content_dir.join("index.md"),
+            format!("---\ntitle: New Name\nuuid: {}\n---\n", current.uuid),
+        )?;
+        index.add_or_update_gallery(old);
+        index.add_or_update_gallery(current);
+        Ok(index)
+    }
+
+    #[test]
+    fn test_dry_run_changes_nothing() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/gallery");
+        let mut index = renamed_gallery(temp_dir.path(), &content_dir)?;
+
+        let report = collect_garbage(
+            &OutputType::Gallery,
+            &content_dir,
+            temp_dir.path(),
+            &mut index,
+            true,
+        )?;
+        assert_eq!(report.galleries, vec!["Old Name".to_string()]);
+        assert_eq!(
+            report.photos,
+            vec!["removed".to_string(), "renamed".to_string()]
+        );
+        assert_eq!(report.bytes, 10);
+        assert_eq!(index.photo_count(), 3);
+        assert_eq!(index.gallery_count(), 2);
+        assert!(content_dir.join("removed.jpg").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_collect_gallery_garbage() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/gallery");
+        let mut index = renamed_gallery(temp_dir.path(), &content_dir)?;
+
+        let report = collect_garbage(
+            &OutputType::Gallery,
+            &content_dir,
+            temp_dir.path(),
+            &mut index,
+            false,
+        )?;
+        assert_eq!(report.files.len(), 2);
+        assert_eq!(index.gallery_count(), 1);
+        assert_eq!(index.photo_count(), 1);
+        assert!(content_dir.join("kept.jpg").exists());
+        assert!(!content_dir.join("removed.jpg").exists());
+        assert!(!content_dir.join("renamed.jpg").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_keeps_galleries_without_page() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/gallery");
+        let mut index = renamed_gallery(temp_dir.path(), &content_dir)?;
+        fs::remove_file(content_dir.join("index.md"))?;
+
+        let report = collect_garbage(
+            &OutputType::Gallery,
+            &content_dir,
+            temp_dir.path(),
+            &mut index,
+            false,
+        )?;
+        assert!(report.galleries.is_empty());
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].contains("Can't tell which gallery"));
+        // Photos neither gallery references are still collected
+        assert_eq!(report.photos, vec!["removed".to_string()]);
+        assert_eq!(index.gallery_count(), 2);
+        assert!(content_dir.join("renamed.jpg").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_collect_batch_garbage() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/batches");
+        fs::create_dir_all(&content_dir)?;
+        let mut index = PhotoIndex::new();
+        for guid in ["kept", "removed", "rejected"] {
+            index.add_or_update_photo(create_photo(temp_dir.path(), &content_dir, guid)?);
+        }
+        index.photos.get_mut("rejected").unwrap().approval = Some(Approval::Rejected);
+        let mut batch = Batch::new("b1".to_string(), "b1".to_string(), Utc::now());
+        batch.add_photo("kept".to_string());
+        index.batches.insert(batch.id.clone(), batch);
+
+        let report = collect_garbage(
+            &OutputType::Batches,
+            &content_dir,
+            temp_dir.path(),
+            &mut index,
+            false,
+        )?;
+        // Rejected photos stay so syncs don't add them again
+        assert_eq!(report.photos, vec!["removed".to_string()]);
+        assert!(index.get_photo("rejected").is_some());
+        assert!(!content_dir.join("removed.jpg").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_photostream_has_no_garbage() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/photostream");
+        fs::create_dir_all(&content_dir)?;
+        let mut index = PhotoIndex::new();
+        index.add_or_update_photo(create_photo(temp_dir.path(), &content_dir, "photo")?);
+
+        let report = collect_garbage(
+            &OutputType::Photostream,
+            &content_dir,
+            temp_dir.path(),
+            &mut index,
+            false,
+        )?;
+        assert!(report.is_empty());
+        assert_eq!(index.photo_count(), 1);
+
+        Ok(())
+    }
+}
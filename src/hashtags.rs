@@ -0,0 +1,256 @@
+//! Hashtags in captions, as Hugo taxonomy terms.
+//!
+//! Captions like "Beach day #summer #kids" carry the tags of a photo. The
+//! hashtags are parsed when a photo is indexed and kept on its index entry.
+//! Outputs with `hashtags` settings write them to a taxonomy in the page
+//! frontmatter, can leave them out of the captions shown on the site, and
+//! can write a page for each term so it gets a title and a description.
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::LazyLock;
+use tokio::fs as tokio_fs;
+
+use crate::frontmatter::{self, quote};
+use crate::index::PhotoIndex;
+use crate::overrides::Overrides;
+
+/// A `#` at the start of the caption or after whitespace, and the term after
+/// it; `#` inside words and URLs isn't a hashtag
+static HASHTAG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(^|\s)#([\p{L}\p{N}_][\p{L}\p{N}_-]*)").unwrap());
+
+/// Returns the terms of the hashtags in a caption, lowercased and in order
+///
+/// Hashtags that are only digits, like "#1", aren't terms.
+pub fn parse(caption: &str) -> Vec<String> {
+    let mut terms: Vec<String> = Vec::new();
+    for captures in HASHTAG.captures_iter(caption) {
+        let term = captures[2].trim_end_matches('-').to_lowercase();
+        if !term.chars().all(|c| c.is_ascii_digit()) && !terms.contains(&term) {
+            terms.push(term);
+        }
+    }
+    terms
+}
+
+/// Removes the hashtags from a caption
+pub fn strip(caption: &str) -> String {
+    caption
+        .lines()
+        .map(|line| {
+            let line = HASHTAG.replace_all(line, |captures: &regex::Captures| {
+                if captures[2].chars().all(|c| c.is_ascii_digit()) {
+                    captures[0].to_string()
+                } else {
+                    captures[1].to_string()
+                }
+            });
+            line.split_whitespace().collect::<Vec<_>>().join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// Adds a taxonomy with its terms to frontmatter
+pub fn push_terms<'t>(
+    frontmatter: &mut String,
+    taxonomy: &str,
+    terms: impl IntoIterator<Item = &'t String>,
+) {
+    frontmatter.push_str(&format!("{taxonomy}:\n"));
+    for term in terms {
+        frontmatter.push_str(&format!("  - {}\n", quote(term)));
+    }
+}
+
+/// Writes a `_index.md` page for each term of the photos an output publishes
+///
+/// Photos that are hidden or not approved don't count, and overridden tags
+/// replace the hashtags of a caption. Pages that exist are left as they are,
+/// so their titles and descriptions can be edited by hand. Pages of terms no
+/// published photo uses anymore are removed, unless they were edited.
+/// Returns the number of terms.
+pub async fn write_term_pages(
+    dir: &Path,
+    index: &PhotoIndex,
+    overrides: &Overrides,
+) -> Result<usize> {
+    let terms: BTreeSet<String> = index
+        .photos
+        .values()
+        .map(|photo| overrides.apply(photo))
+        .filter(|view| !view.hidden)
+        .flat_map(|view| view.tags.unwrap_or_default())
+        .collect();
+
+    for term in &terms {
+        let term_dir = dir.join(term);
+        tokio_fs::create_dir_all(&term_dir).await?;
+        frontmatter::write_page(&term_dir.join("_index.md"), &term_page(term), &[], &[]).await?;
+    }
+    remove_unused_term_pages(dir, &terms).await?;
+    Ok(terms.len())
+}
+
+/// Returns the generated page of a term
+fn term_page(term: &str) -> String {
+    format!("---\ntitle: {}\n---\n", quote(term))
+}
+
+/// Removes the term pages of terms that aren't used, if they're still as
+/// generated, and their directories once they're empty
+async fn remove_unused_term_pages(dir: &Path, terms: &BTreeSet<String>) -> Result<()> {
+    let mut entries = match tokio_fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to read {}", dir.display()));
+        }
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let Some(term) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        if terms.contains(&term) || !entry.file_type().await?.is_dir() {
+            continue;
+        }
+        let page = entry.path().join("_index.md");
+        let Ok(contents) = tokio_fs::read_to_string(&page).await else {
+            continue;
+        };
+        if frontmatter::merge_page(None, &term_page(&term), &[], &[]).as_ref() != Some(&contents) {
+            continue;
+        }
+        tokio_fs::remove_file(&page)
+            .await
+            .with_context(|| format!("Failed to delete {}", page.display()))?;
+        // Leave the directory if something else was put in it
+        let _ = tokio_fs::remove_dir(entry.path()).await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HashtagConfig;
+    use crate::index::{Approval, IndexedPhoto};
+    use chrono::Utc;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_and_strip_hashtags() {
+        let caption = "Beach day #summer #Kids #summer\nhttps://example.com/#anchor We're #1!";
+        assert_eq!(parse(caption), vec!["summer", "kids"]);
+        assert_eq!(
+            strip(caption),
+            "Beach day\nhttps://example.com/#anchor We're #1!"
+        );
+        assert_eq!(
+            parse("#tbt to the lake-side #road-trip-"),
+            vec!["tbt", "road-trip"]
+        );
+        assert_eq!(strip("#tbt to the lake"), "to the lake");
+    }
+
+    fn create_photo(guid: &str, caption: &str) -> IndexedPhoto {
+        IndexedPhoto::new(
+            guid.to_string(),
+            format!("{guid}.jpg"),
+            Some(caption.to_string()),
+            Utc::now(),
+            format!("checksum-{guid}"),
+            format!("https://example.com/{guid}.jpg"),
+            800,
+            600,
+            format!("{guid}.jpg").into(),
+            Path::new(""),
+        )
+    }
+
+    fn term_overrides(yaml: &str) -> Result<Overrides> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("overrides.yaml");
+        fs::write(&path, yaml)?;
+        Ok(Overrides::load(&path)?.with_hashtags(Some(HashtagConfig::default())))
+    }
+
+    #[tokio::test]
+    async fn test_write_term_pages() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let dir = temp_dir.path().join("content/tags");
+        let overrides = term_overrides("")?;
+
+        let mut index = PhotoIndex::new();
+        index.add_or_update_photo(create_photo("photo1", "Beach day #summer #kids"));
+
+        assert_eq!(write_term_pages(&dir, &index, &overrides).await?, 2);
+        let page = dir.join("summer/_index.md");
+        assert!(fs::read_to_string(&page)?.starts_with("---\ntitle: \"summer\"\n"));
+
+        // Titles and descriptions edited by hand are kept
+        fs::write(&page, "---\ntitle: Summer\ndescription: Hot days\n---\n")?;
+        write_term_pages(&dir, &index, &overrides).await?;
+        let page = fs::read_to_string(&page)?;
+        assert!(page.starts_with("---\ntitle: Summer\ndescription: Hot days\n"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_term_pages_of_published_photos() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let dir = temp_dir.path().join("content/tags");
+        let overrides = term_overrides("photo2:\n  hidden: true\nphoto3:\n  tags: [lake]\n")?;
+
+        let mut index = PhotoIndex::new();
+        index.add_or_update_photo(create_photo("photo1", "Beach day #summer"));
+        index.add_or_update_photo(create_photo("photo2", "Secret #hidden"));
+        index.add_or_update_photo(create_photo("photo3", "Swimming #pool"));
+        let mut rejected = create_photo("photo4", "Blurry #rejected");
+        rejected.approval = Some(Approval::Rejected);
+        index.add_or_update_photo(rejected);
+
+        assert_eq!(write_term_pages(&dir, &index, &overrides).await?, 2);
+        assert!(dir.join("summer/_index.md").exists());
+        assert!(dir.join("lake/_index.md").exists());
+        for term in ["hidden", "pool", "rejected"] {
+            assert!(!dir.join(term).exists(), "{term} has a page");
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_term_pages_removes_unused_pages() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let dir = temp_dir.path().join("content/tags");
+        let overrides = term_overrides("")?;
+
+        let mut index = PhotoIndex::new();
+        index.add_or_update_photo(create_photo("photo1", "Beach day #summer #kids"));
+        index.add_or_update_photo(create_photo("photo2", "Snow #winter"));
+        write_term_pages(&dir, &index, &overrides).await?;
+        fs::write(
+            dir.join("kids/_index.md"),
+            "---\ntitle: Kids\ndescription: Our two\n---\n",
+        )?;
+
+        // The last photos with #kids and #winter are gone
+        index.remove_photo("photo2");
+        index.add_or_update_photo(create_photo("photo1", "Beach day #summer"));
+        assert_eq!(write_term_pages(&dir, &index, &overrides).await?, 1);
+        assert!(dir.join("summer/_index.md").exists());
+        assert!(!dir.join("winter").exists());
+        // A page edited by hand is kept
+        assert!(dir.join("kids/_index.md").exists());
+
+        Ok(())
+    }
+}
@@ -26,12 +26,26 @@
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use icloud_album_rs::models::Image;
 use log::{debug, error, info, trace, warn};
+use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
 use std::collections::HashMap;
 use std::fmt;
 use url::Url;
 
+use crate::index::{IndexedPhoto, PhotoIndex};
+use crate::retry::HttpStatusError;
+use crate::source::FetchOptions;
+
+/// Number of photos per webasseturls request
+const ASSET_URL_CHUNK_SIZE: usize = 100;
+
+/// Number of webasseturls requests in flight at once
+const ASSET_URL_CONCURRENCY: usize = 4;
+
 /// Represents a single photo in an album with all necessary metadata
 ///
 /// This struct contains all the information needed to identify, download,
@@ -69,6 +83,27 @@ pub struct Photo {
     /// MIME type of the photo (e.g., "image/jpeg", "image/png")
     #[serde(default = "default_mime_type")]
     pub mime_type: String,
+
+    /// Name of the album member who posted the photo, if known
+    #[serde(default)]
+    pub contributor: Option<String>,
+
+    /// Identifier of the upload batch the photo was posted in
+    #[serde(default)]
+    pub batch_guid: Option<String>,
+
+    /// When the upload batch was posted
+    #[serde(default)]
+    pub batch_created_at: Option<DateTime<Utc>>,
+
+    /// iCloud's checksum of the downloaded derivative, identical for the
+    /// same image posted to different albums
+    #[serde(default)]
+    pub asset_checksum: Option<String>,
+
+    /// URLs of the albums this photo was found in
+    #[serde(default)]
+    pub sources: Vec<String>,
 }
 
 /// Default MIME type for backward compatibility
@@ -116,6 +151,50 @@ impl Album {
     pub fn photo_count(&self) -> usize {
         self.photos.len()
     }
+
+    /// Merges the photos of another album into this one
+    ///
+    /// A photo that is already present, either under the same GUID or as the
+    /// same image (matching asset checksum) posted to both albums, is kept
+    /// once and only gains the other album's sources.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The album to merge into this one
+    pub fn merge(&mut self, other: Album) {
+        let mut photos: Vec<Photo> = other.photos.into_values().collect();
+        photos.sort_by(|a, b| a.guid.cmp(&b.guid));
+
+        for photo in photos {
+            let existing_guid = if self.photos.contains_key(&photo.guid) {
+                Some(photo.guid.clone())
+            } else {
+                photo.asset_checksum.as_ref().and_then(|checksum| {
+                    self.photos
+                        .values()
+                        .find(|p| p.asset_checksum.as_ref() == Some(checksum))
+                        .map(|p| p.guid.clone())
+                })
+            };
+
+            match existing_guid.and_then(|guid| self.photos.get_mut(&guid)) {
+                Some(existing) => {
+                    debug!(
+                        "Photo {} is a duplicate of {}, merging sources",
+                        photo.guid, existing.guid
+                    );
+                    for source in photo.sources {
+                        if !existing.sources.contains(&source) {
+                            existing.sources.push(source);
+                        }
+                    }
+                }
+                None => {
+                    self.photos.insert(photo.guid.clone(), photo);
+                }
+            }
+        }
+    }
 }
 
 /// Represents different formats of iCloud shared album URLs
@@ -202,10 +281,10 @@ fn extract_token(album_url: &str) -> Result<String> {
             })?;
 
             // Find "photos" segment and get the next segment as the token
-            if let Some("photos") = segments.next() {
-                if let Some(token) = segments.next() {
-                    return Ok(token.to_string());
-                }
+            if let Some("photos") = segments.next()
+                && let Some(token) = segments.next()
+            {
+                return Ok(token.to_string());
             }
 
             // If we didn't find a valid token after "photos"
@@ -241,6 +320,21 @@ pub enum ICloudError {
     /// No derivatives found for a photo
     NoDerivativesError(String),
 
+    /// The album doesn't exist, for example because it was deleted
+    AlbumNotFound(String),
+
+    /// The album exists but is no longer shared publicly
+    AlbumNotPublic(String),
+
+    /// iCloud is rejecting requests because too many were made
+    RateLimited {
+        /// How long iCloud asked us to wait, if it said
+        retry_after: Option<std::time::Duration>,
+    },
+
+    /// iCloud answered with a response we couldn't make sense of
+    MalformedResponse(String),
+
     /// Wraps an underlying error with context
     Context {
         /// The context message explaining what operation was being performed
@@ -263,6 +357,40 @@ impl ICloudError {
         }
     }
 
+    /// Classifies an unsuccessful webstream response
+    ///
+    /// iCloud answers 404 for albums that were deleted and 401 or 403 for
+    /// albums that are no longer shared publicly.
+    fn from_webstream_status(status_error: HttpStatusError) -> Self {
+        match status_error.status {
+            404 => Self::AlbumNotFound(format!("{} returned 404", status_error.url)),
+            401 | 403 => Self::AlbumNotPublic(format!(
+                "{} returned {}",
+                status_error.url, status_error.status
+            )),
+            429 => Self::RateLimited {
+                retry_after: status_error.retry_after,
+            },
+            _ => Self::with_context(status_error, "webstream request failed"),
+        }
+    }
+
+    /// Classifies a failed webasseturls request
+    fn from_asset_urls_error(error: icloud_album_rs::api::ApiError) -> Self {
+        use icloud_album_rs::api::ApiError;
+
+        match error {
+            ApiError::RequestError {
+                status: Some(429), ..
+            } => Self::RateLimited { retry_after: None },
+            ApiError::JsonParseError(msg) => Self::MalformedResponse(msg),
+            ApiError::MissingFieldError(field) => {
+                Self::MalformedResponse(format!("webasseturls response has no {field}"))
+            }
+            error => Self::with_context(error, "webasseturls request failed"),
+        }
+    }
+
     /// Wrap a Result error with additional context
     pub fn context<T, E, C>(result: Result<T, E>, context: C) -> Result<T, Self>
     where
@@ -281,6 +409,17 @@ impl fmt::Display for ICloudError {
             Self::FetchError(msg) => write!(f, "Failed to fetch album: {msg}"),
             Self::PhotoProcessingError(msg) => write!(f, "Error processing photo: {msg}"),
             Self::NoDerivativesError(msg) => write!(f, "No suitable derivatives: {msg}"),
+            Self::AlbumNotFound(msg) => write!(f, "Album not found: {msg}"),
+            Self::AlbumNotPublic(msg) => write!(f, "Album is no longer public: {msg}"),
+            Self::RateLimited {
+                retry_after: Some(retry_after),
+            } => write!(
+                f,
+                "Rate limited by iCloud, retry after {}s",
+                retry_after.as_secs()
+            ),
+            Self::RateLimited { retry_after: None } => write!(f, "Rate limited by iCloud"),
+            Self::MalformedResponse(msg) => write!(f, "Malformed response from iCloud: {msg}"),
             Self::Context { context, source } => write!(f, "{context}: {source}"),
         }
     }
@@ -321,9 +460,7 @@ fn extract_derivative_info(
 /// Result containing URL, width, height, and MIME type
 type DerivativeInfo = (String, u32, u32, String);
 
-fn find_best_derivative(
-    photo: &icloud_album_rs::models::Image,
-) -> Result<DerivativeInfo, ICloudError> {
+fn find_best_derivative(photo: &Image) -> Result<DerivativeInfo, ICloudError> {
     debug!("Finding best derivative for photo: {}", photo.photo_guid);
     trace!("Photo has {} derivatives", photo.derivatives.len());
 
@@ -404,20 +541,22 @@ fn find_best_derivative(
 }
 
 /// Fetches photos from an iCloud shared album using the icloud-album-rs crate
+#[allow(dead_code)]
 pub async fn fetch_album(album_url: &str) -> Result<Album> {
-    info!("Fetching iCloud shared album: {album_url}");
-
-    // Define test token indicators explicitly
-    const TEST_TOKEN_INDICATORS: [&str; 3] = ["#test", "#custom", "#example"];
+    fetch_album_with_options(album_url, FetchOptions::default()).await
+}
 
-    // Special handling for test or custom URLs
-    let is_test_url = TEST_TOKEN_INDICATORS
-        .iter()
-        .any(|indicator| album_url.contains(indicator));
-    if is_test_url {
-        debug!("Detected test URL, using mock album data");
-        return crate::mock::create_mock_album();
-    }
+/// Fetches photos from an iCloud shared album with the given fetch options
+///
+/// With an index, the album is fetched in two phases: the stream metadata is
+/// fetched first, and asset URLs are only resolved for photos that are new or
+/// whose content changed. Unchanged photos keep the URL stored in the index.
+///
+/// With a cache, the raw responses are saved after every fetch. Offline
+/// fetches rebuild the album from the latest saved responses instead of
+/// contacting iCloud.
+pub async fn fetch_album_with_options(album_url: &str, options: FetchOptions<'_>) -> Result<Album> {
+    info!("Fetching iCloud shared album: {album_url}");
 
     // Check if the URL seems valid before processing
     if !album_url.contains("icloud.com/sharedalbum")
@@ -449,28 +588,63 @@ pub async fn fetch_album(album_url: &str) -> Result<Album> {
         }
     };
 
-    // Fetch the album data using the icloud-album-rs crate
-    info!("Fetching album data with token");
-    let album_data = match icloud_album_rs::get_icloud_photos(&token).await {
-        Ok(data) => {
-            debug!("Successfully fetched album data");
-            data
-        }
-        Err(e) => {
-            error!("Failed to fetch iCloud album: {e}");
-            return Err(anyhow::anyhow!(ICloudError::FetchError(e.to_string())));
+    let response = if options.offline {
+        let cache = options.cache.ok_or_else(|| {
+            anyhow::anyhow!("Offline mode needs a cache_dir to read saved album responses from")
+        })?;
+        let response = cache.load_latest(album_url)?;
+        validate_stream(&response.stream)?;
+        info!(
+            "Using saved album response from {}",
+            response.fetched_at.to_rfc3339()
+        );
+        response
+    } else {
+        // Fetch the album data from the shared streams API
+        info!("Fetching album data with token");
+        let (stream, asset_urls) = match fetch_stream(&token, options.index).await {
+            Ok(data) => {
+                debug!("Successfully fetched album data");
+                data
+            }
+            Err(e) => {
+                error!("Failed to fetch iCloud album: {e}");
+                return Err(anyhow::anyhow!(e));
+            }
+        };
+
+        let response = AlbumResponse {
+            album_url: album_url.to_string(),
+            fetched_at: Utc::now(),
+            stream,
+            asset_urls,
+        };
+        if let Some(cache) = options.cache {
+            // A failing cache shouldn't stop the sync
+            match cache.save(&response) {
+                Ok(path) => debug!("Saved album response to {}", path.display()),
+                Err(e) => warn!("Failed to save album response: {e:#}"),
+            }
         }
+        response
     };
 
-    // Create our Album struct from the icloud-album-rs response
+    album_from_response(&token, &response)
+}
+
+/// Builds an album from the raw webstream and asset URL responses
+fn album_from_response(token: &str, response: &AlbumResponse) -> Result<Album> {
+    let album_data = stream_data_from_response(response);
+
+    // Create our Album struct from the stream response
     // If stream_name is empty, use a generic name with the token as a fallback
-    let album_name = if album_data.metadata.stream_name.trim().is_empty() {
+    let album_name = if album_data.stream_name.trim().is_empty() {
         let name = format!("iCloud Album {}", token.chars().take(8).collect::<String>());
         warn!("Album has no name, using generated name: {name}");
         name
     } else {
-        debug!("Using album name: {}", album_data.metadata.stream_name);
-        album_data.metadata.stream_name.clone()
+        debug!("Using album name: {}", album_data.stream_name);
+        album_data.stream_name.clone()
     };
 
     let mut album = Album::new(album_name);
@@ -483,7 +657,7 @@ pub async fn fetch_album(album_url: &str) -> Result<Album> {
     let photo_count = album_data.photos.len();
 
     for (i, photo) in album_data.photos.into_iter().enumerate() {
-        let photo_guid = photo.photo_guid.clone();
+        let photo_guid = photo.image.photo_guid.clone();
         trace!("Processing photo {}/{}: {}", i + 1, photo_count, photo_guid);
 
         let result = process_photo(&mut album, photo);
@@ -521,6 +695,327 @@ pub async fn fetch_album(album_url: &str) -> Result<Album> {
     Ok(album)
 }
 
+/// Raw responses of an album fetch
+///
+/// This is everything needed to rebuild an `Album` without contacting iCloud,
+/// and is what the response cache saves.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlbumResponse {
+    /// URL of the album that was fetched
+    pub album_url: String,
+    /// When the album was fetched
+    pub fetched_at: DateTime<Utc>,
+    /// The webstream response as returned by iCloud
+    pub stream: Value,
+    /// Download URLs by derivative checksum, from the webasseturls responses
+    pub asset_urls: HashMap<String, String>,
+}
+
+/// Album data returned by the webstream endpoint
+///
+/// `icloud_album_rs::get_icloud_photos` parses each photo into an `Image`,
+/// which drops fields such as the contributor's name. We request the stream
+/// ourselves so those fields can be read from the raw JSON.
+struct StreamData {
+    /// Name of the shared album
+    stream_name: String,
+    /// Photos in the album with their asset URLs filled in
+    photos: Vec<StreamPhoto>,
+}
+
+/// A photo entry from the webstream response
+struct StreamPhoto {
+    /// The photo as parsed by icloud-album-rs
+    image: Image,
+    /// Full name of the album member who posted the photo
+    contributor: Option<String>,
+    /// Identifier of the upload batch the photo belongs to
+    batch_guid: Option<String>,
+}
+
+/// Resolves the shared streams base URL (including the partition host) for an album token
+async fn resolve_base_url(client: &Client, token: &str) -> Result<String, ICloudError> {
+    let base_url = icloud_album_rs::base_url::get_base_url(token)
+        .map_err(|e| ICloudError::InvalidToken(e.to_string()))?;
+    let base_url = icloud_album_rs::redirect::get_redirected_base_url(client, &base_url, token)
+        .await
+        .map_err(|e| ICloudError::FetchError(e.to_string()))?;
+    debug!("Using stream base URL: {base_url}");
+    Ok(base_url)
+}
+
+/// Requests fresh download URLs for photos whose signed URLs have expired
+///
+/// The URLs returned by webasseturls are only valid for a limited time, so on
+/// large albums the last downloads can outlive them. This re-requests the URL
+/// of a single photo from the album it came from, without refetching the
+/// album. Base URLs are cached per album.
+#[derive(Default)]
+pub struct AssetUrlRefresher {
+    /// HTTP client for API requests
+    client: Client,
+    /// Resolved stream base URLs by album URL
+    base_urls: tokio::sync::Mutex<HashMap<String, String>>,
+}
+
+impl AssetUrlRefresher {
+    /// Returns a fresh download URL for a photo fetched from an iCloud album
+    pub async fn refresh_url(&self, photo: &Photo) -> Result<String> {
+        let album_url = photo.sources.first().ok_or_else(|| {
+            ICloudError::FetchError(format!("Photo {} has no source album", photo.guid))
+        })?;
+        let checksum = photo.asset_checksum.as_ref().ok_or_else(|| {
+            ICloudError::FetchError(format!("Photo {} has no asset checksum", photo.guid))
+        })?;
+
+        let base_url = {
+            let mut base_urls = self.base_urls.lock().await;
+            match base_urls.get(album_url) {
+                Some(base_url) => base_url.clone(),
+                None => {
+                    let token = extract_token(album_url)?;
+                    let base_url = resolve_base_url(&self.client, &token).await?;
+                    base_urls.insert(album_url.clone(), base_url.clone());
+                    base_url
+                }
+            }
+        };
+
+        debug!("Requesting a fresh asset URL for photo {}", photo.guid);
+        let urls = icloud_album_rs::api::get_asset_urls(
+            &self.client,
+            &base_url,
+            std::slice::from_ref(&photo.guid),
+        )
+        .await
+        .map_err(ICloudError::from_asset_urls_error)?;
+
+        urls.get(checksum).cloned().ok_or_else(|| {
+            anyhow::anyhow!(ICloudError::FetchError(format!(
+                "No fresh URL returned for photo {}",
+                photo.guid
+            )))
+        })
+    }
+}
+
+/// Fetches the webstream and asset URLs for an album token
+///
+/// Returns the raw webstream response and the download URLs by derivative
+/// checksum. With an index, URLs of unchanged photos are taken from the index.
+async fn fetch_stream(
+    token: &str,
+    index: Option<&PhotoIndex>,
+) -> Result<(Value, HashMap<String, String>), ICloudError> {
+    let client = Client::new();
+    let base_url = resolve_base_url(&client, token).await?;
+
+    // Request the stream metadata and photo list
+    let response = ICloudError::context(
+        client
+            .post(format!("{base_url}webstream"))
+            .json(&json!({ "streamCtag": null }))
+            .send()
+            .await,
+        "webstream request failed",
+    )?;
+
+    if !response.status().is_success() {
+        return Err(ICloudError::from_webstream_status(
+            HttpStatusError::from_response(&response),
+        ));
+    }
+
+    let data: Value = response
+        .json()
+        .await
+        .map_err(|e| ICloudError::MalformedResponse(format!("webstream response: {e}")))?;
+    validate_stream(&data)?;
+    let photos = parse_stream_photos(&data);
+
+    // Only resolve URLs for photos the index doesn't already have
+    let mut asset_urls = HashMap::new();
+    let mut guids = Vec::new();
+    for photo in &photos {
+        let known = index
+            .and_then(|index| index.get_photo(&photo.image.photo_guid))
+            .and_then(|known| known_asset_url(&photo.image, known));
+        match known {
+            Some((checksum, url)) => {
+                asset_urls.insert(checksum, url);
+            }
+            None => guids.push(photo.image.photo_guid.clone()),
+        }
+    }
+    if index.is_some() {
+        info!(
+            "{} of {} photos are new or changed, resolving their URLs",
+            guids.len(),
+            photos.len()
+        );
+    }
+
+    asset_urls.extend(resolve_asset_urls(&client, &base_url, &guids).await?);
+    Ok((data, asset_urls))
+}
+
+/// Checks that a webstream response has a photo list
+///
+/// Without this check, a response that lost its photo list would look like
+/// an empty album and every local photo would be deleted.
+fn validate_stream(data: &Value) -> Result<(), ICloudError> {
+    if data.get("photos").is_some_and(Value::is_array) {
+        Ok(())
+    } else {
+        Err(ICloudError::MalformedResponse(
+            "webstream response has no photo list".to_string(),
+        ))
+    }
+}
+
+/// Parses a webstream response and fills in the download URLs of its photos
+fn stream_data_from_response(response: &AlbumResponse) -> StreamData {
+    let stream_name = response
+        .stream
+        .get("streamName")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+
+    let mut photos = parse_stream_photos(&response.stream);
+    for photo in &mut photos {
+        icloud_album_rs::enrich::enrich_photos_with_urls(
+            std::slice::from_mut(&mut photo.image),
+            &response.asset_urls,
+        );
+    }
+
+    StreamData {
+        stream_name,
+        photos,
+    }
+}
+
+/// Resolves asset URLs in chunks, fetching several chunks concurrently
+///
+/// Albums can hold thousands of photos, and a single webasseturls request for
+/// all of them is slow and may be rejected.
+async fn resolve_asset_urls(
+    client: &Client,
+    base_url: &str,
+    guids: &[String],
+) -> Result<HashMap<String, String>, ICloudError> {
+    let total = guids.len();
+    let mut urls = HashMap::new();
+    let mut resolved = 0;
+
+    let mut chunks = stream::iter(guids.chunks(ASSET_URL_CHUNK_SIZE).map(<[String]>::to_vec))
+        .map(|chunk| async move {
+            let result = icloud_album_rs::api::get_asset_urls(client, base_url, &chunk).await;
+            (chunk.len(), result)
+        })
+        .buffer_unordered(ASSET_URL_CONCURRENCY);
+
+    while let Some((count, result)) = chunks.next().await {
+        urls.extend(result.map_err(ICloudError::from_asset_urls_error)?);
+        resolved += count;
+        info!("Resolved asset URLs for {resolved}/{total} photos");
+    }
+
+    Ok(urls)
+}
+
+/// Returns the key of the derivative `find_best_derivative` would pick once
+/// all URLs are known: the original if there is one, otherwise the largest
+fn preferred_derivative_key(photo: &Image) -> Option<&String> {
+    if let Some(key) = photo
+        .derivatives
+        .keys()
+        .find(|key| key.contains("original"))
+    {
+        return Some(key);
+    }
+
+    let mut best: Option<(&String, u64)> = None;
+    for (key, derivative) in &photo.derivatives {
+        let resolution =
+            derivative.width.unwrap_or(0) as u64 * derivative.height.unwrap_or(0) as u64;
+        match best {
+            Some((_, best_res)) if resolution <= best_res => {}
+            _ => best = Some((key, resolution)),
+        }
+    }
+    best.map(|(key, _)| key)
+}
+
+/// Returns the indexed URL of a photo if its content is unchanged
+///
+/// The content is unchanged when the preferred derivative still has the asset
+/// checksum stored in the index. Returns that checksum with the indexed URL.
+fn known_asset_url(photo: &Image, known: &IndexedPhoto) -> Option<(String, String)> {
+    let known_checksum = known.asset_checksum.as_ref()?;
+    let key = preferred_derivative_key(photo)?;
+
+    (&photo.derivatives[key].checksum == known_checksum)
+        .then(|| (known_checksum.clone(), known.url.clone()))
+}
+
+/// Parses the photo entries of a webstream response
+///
+/// Entries that cannot be parsed are logged and skipped.
+fn parse_stream_photos(data: &Value) -> Vec<StreamPhoto> {
+    let Some(entries) = data.get("photos").and_then(Value::as_array) else {
+        warn!("Webstream response has no photos array");
+        return Vec::new();
+    };
+
+    entries
+        .iter()
+        .enumerate()
+        .filter_map(
+            |(i, entry)| match serde_json::from_value::<Image>(entry.clone()) {
+                Ok(image) => Some(StreamPhoto {
+                    image,
+                    contributor: parse_contributor(entry),
+                    batch_guid: entry
+                        .get("batchGuid")
+                        .and_then(Value::as_str)
+                        .map(str::to_string),
+                }),
+                Err(e) => {
+                    warn!("Failed to parse photo at index {i}: {e}");
+                    None
+                }
+            },
+        )
+        .collect()
+}
+
+/// Reads the contributor's name from a webstream photo entry
+fn parse_contributor(entry: &Value) -> Option<String> {
+    let field = |name: &str| {
+        entry
+            .get(name)
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+    };
+
+    if let Some(full_name) = field("contributorFullName") {
+        return Some(full_name.to_string());
+    }
+
+    let parts: Vec<&str> = [field("contributorFirstName"), field("contributorLastName")]
+        .into_iter()
+        .flatten()
+        .collect();
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(" "))
+    }
+}
+
 /// Parse a date string in RFC3339 format to a UTC DateTime
 fn parse_photo_date(date_str: &str) -> Result<DateTime<Utc>, ICloudError> {
     debug!("Parsing date: {date_str}");
@@ -565,10 +1060,12 @@ fn determine_mime_type(url: &str, derivative_key: &str) -> String {
 }
 
 /// Process a single photo from the iCloud API response and add it to the album
-fn process_photo(
-    album: &mut Album,
-    photo: icloud_album_rs::models::Image,
-) -> Result<(), ICloudError> {
+fn process_photo(album: &mut Album, stream_photo: StreamPhoto) -> Result<(), ICloudError> {
+    let StreamPhoto {
+        image: photo,
+        contributor,
+        batch_guid,
+    } = stream_photo;
     debug!("Processing photo: {}", photo.photo_guid);
     trace!(
         "Photo data: caption={:?}, derivatives_count={}",
@@ -590,6 +1087,19 @@ fn process_photo(
         }
     };
 
+    // A malformed batch date only affects grouping, so don't reject the photo for it
+    let batch_created_at = photo
+        .batch_date_created
+        .as_deref()
+        .and_then(|date_str| parse_photo_date(date_str).ok());
+
+    // Keep iCloud's own checksum of the chosen derivative for cross-album de-duplication
+    let asset_checksum = photo
+        .derivatives
+        .values()
+        .find(|derivative| derivative.url.as_deref() == Some(url.as_str()))
+        .map(|derivative| derivative.checksum.clone());
+
     // Create a checksum and build the photo object
     let guid = photo.photo_guid.clone();
     let checksum = generate_photo_checksum(&guid, &url);
@@ -615,6 +1125,11 @@ fn process_photo(
         width,
         height,
         mime_type,
+        contributor,
+        batch_guid,
+        batch_created_at,
+        asset_checksum,
+        sources: Vec::new(),
     };
 
     // Add the photo to our album
@@ -627,6 +1142,7 @@ fn process_photo(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::path::Path;
 
     #[test]
     fn test_album_new() {
@@ -635,6 +1151,248 @@ mod tests {
         assert!(album.photos.is_empty());
     }
 
+    fn create_merge_photo(guid: &str, asset_checksum: &str, source: &str) -> Photo {
+        Photo {
+            guid: guid.to_string(),
+            filename: format!("{guid}.jpg"),
+            caption: None,
+            created_at: Utc::now(),
+            checksum: format!("checksum_{guid}"),
+            url: format!("https://example.com/{guid}.jpg"),
+            width: 800,
+            height: 600,
+            mime_type: "image/jpeg".to_string(),
+            contributor: None,
+            batch_guid: None,
+            batch_created_at: None,
+            asset_checksum: Some(asset_checksum.to_string()),
+            sources: vec![source.to_string()],
+        }
+    }
+
+    #[test]
+    fn test_album_merge_deduplicates() {
+        let mut phone = Album::new("Trip (phone)".to_string());
+        for photo in [
+            create_merge_photo("p1", "asset1", "phone"),
+            create_merge_photo("shared", "asset2", "phone"),
+        ] {
+            phone.photos.insert(photo.guid.clone(), photo);
+        }
+
+        let mut camera = Album::new("Trip (camera)".to_string());
+        for photo in [
+            create_merge_photo("c1", "asset3", "camera"),
+            create_merge_photo("shared", "asset2", "camera"),
+            // Same image posted to both albums under a different GUID
+            create_merge_photo("c2", "asset1", "camera"),
+        ] {
+            camera.photos.insert(photo.guid.clone(), photo);
+        }
+
+        phone.merge(camera);
+
+        assert_eq!(phone.name, "Trip (phone)");
+        assert_eq!(phone.photos.len(), 3);
+        assert!(!phone.photos.contains_key("c2"));
+        assert_eq!(phone.photos["p1"].sources, vec!["phone", "camera"]);
+        assert_eq!(phone.photos["shared"].sources, vec!["phone", "camera"]);
+        assert_eq!(phone.photos["c1"].sources, vec!["camera"]);
+    }
+
+    #[test]
+    fn test_parse_stream_photos_reads_contributor() {
+        let data = json!({
+            "streamName": "Family",
+            "photos": [
+                {
+                    "photoGuid": "guid1",
+                    "derivatives": {},
+                    "caption": "Beach day",
+                    "contributorFullName": "Jane Doe",
+                    "batchGuid": "batch1"
+                },
+                {
+                    "photoGuid": "guid2",
+                    "derivatives": {},
+                    "contributorFirstName": "John",
+                    "contributorLastName": "Doe"
+                },
+                {
+                    "photoGuid": "guid3",
+                    "derivatives": {}
+                },
+                {
+                    "caption": "missing guid"
+                }
+            ]
+        });
+
+        let photos = parse_stream_photos(&data);
+
+        assert_eq!(photos.len(), 3);
+        assert_eq!(photos[0].image.photo_guid, "guid1");
+        assert_eq!(photos[0].contributor, Some("Jane Doe".to_string()));
+        assert_eq!(photos[1].contributor, Some("John Doe".to_string()));
+        assert_eq!(photos[2].contributor, None);
+        assert_eq!(photos[0].batch_guid, Some("batch1".to_string()));
+        assert_eq!(photos[1].batch_guid, None);
+    }
+
+    fn create_stream_image(guid: &str, derivatives: &[(&str, &str, u32)]) -> Image {
+        let derivatives = derivatives
+            .iter()
+            .map(|(key, checksum, width)| {
+                let derivative = json!({ "checksum": checksum, "width": width, "height": width });
+                (key.to_string(), serde_json::from_value(derivative).unwrap())
+            })
+            .collect();
+        Image {
+            photo_guid: guid.to_string(),
+            derivatives,
+            caption: None,
+            date_created: None,
+            batch_date_created: None,
+            width: None,
+            height: None,
+        }
+    }
+
+    #[test]
+    fn test_known_asset_url() {
+        let mut known = IndexedPhoto::new(
+            "guid1".to_string(),
+            "guid1.jpg".to_string(),
+            None,
+            Utc::now(),
+            "checksum1".to_string(),
+            "https://cvws.icloud-content.com/known.jpg".to_string(),
+            2048,
+            2048,
+            std::path::PathBuf::from("content/photostream/guid1/original.jpg"),
+            Path::new(""),
+        );
+
+        // Indexed before asset checksums were recorded
+        let image = create_stream_image("guid1", &[("2048", "big", 2048), ("342", "small", 342)]);
+        assert_eq!(known_asset_url(&image, &known), None);
+
+        // Unchanged content reuses the indexed URL for the preferred derivative
+        known.asset_checksum = Some("big".to_string());
+        assert_eq!(
+            known_asset_url(&image, &known),
+            Some((
+                "big".to_string(),
+                "https://cvws.icloud-content.com/known.jpg".to_string()
+            ))
+        );
+
+        // The original wins over larger derivatives, so a new original means changed content
+        let image = create_stream_image(
+            "guid1",
+            &[("2048", "big", 2048), ("original", "orig", 1024)],
+        );
+        assert_eq!(known_asset_url(&image, &known), None);
+    }
+
+    #[test]
+    fn test_album_from_response() -> Result<()> {
+        let response = AlbumResponse {
+            album_url: "https://www.icloud.com/sharedalbum/#B0aGWZmrRGZRiRW".to_string(),
+            fetched_at: Utc::now(),
+            stream: json!({
+                "streamName": "Saved Album",
+                "photos": [{
+                    "photoGuid": "guid1",
+                    "caption": "From the cache",
+                    "dateCreated": "2024-05-01T12:00:00Z",
+                    "derivatives": {
+                        "2048": { "checksum": "big", "width": "2048", "height": "1536" }
+                    }
+                }]
+            }),
+            asset_urls: HashMap::from([(
+                "big".to_string(),
+                "https://cvws.icloud-content.com/big.jpg".to_string(),
+            )]),
+        };
+
+        let album = album_from_response("B0aGWZmrRGZRiRW", &response)?;
+        assert_eq!(album.name, "Saved Album");
+        let photo = &album.photos["guid1"];
+        assert_eq!(photo.url, "https://cvws.icloud-content.com/big.jpg");
+        assert_eq!(photo.caption, Some("From the cache".to_string()));
+        assert_eq!(photo.asset_checksum, Some("big".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_webstream_status_classification() {
+        let status = |status| HttpStatusError {
+            url: "https://p23-sharedstreams.icloud.com/B0test/sharedstreams/webstream".to_string(),
+            status,
+            retry_after: Some(std::time::Duration::from_secs(30)),
+        };
+
+        assert!(matches!(
+            ICloudError::from_webstream_status(status(404)),
+            ICloudError::AlbumNotFound(_)
+        ));
+        assert!(matches!(
+            ICloudError::from_webstream_status(status(403)),
+            ICloudError::AlbumNotPublic(_)
+        ));
+        assert!(matches!(
+            ICloudError::from_webstream_status(status(429)),
+            ICloudError::RateLimited {
+                retry_after: Some(_)
+            }
+        ));
+        assert!(matches!(
+            ICloudError::from_webstream_status(status(500)),
+            ICloudError::Context { .. }
+        ));
+    }
+
+    #[test]
+    fn test_validate_stream() {
+        assert!(validate_stream(&json!({ "streamName": "Empty", "photos": [] })).is_ok());
+        assert!(matches!(
+            validate_stream(&json!({ "streamName": "Broken" })),
+            Err(ICloudError::MalformedResponse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_asset_urls_in_chunks() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let requests = server
+            .mock("POST", "/webasseturls")
+            .with_status(200)
+            .with_body(
+                json!({
+                    "items": {
+                        "chk1": { "url_location": "cvws.icloud-content.com", "url_path": "/chk1.jpg" }
+                    }
+                })
+                .to_string(),
+            )
+            .expect(3)
+            .create_async()
+            .await;
+
+        let guids: Vec<String> = (0..250).map(|i| format!("guid{i}")).collect();
+        let base_url = format!("{}/", server.url());
+        let urls = resolve_asset_urls(&Client::new(), &base_url, &guids).await?;
+
+        assert_eq!(urls.len(), 1);
+        assert!(urls.contains_key("chk1"));
+        requests.assert_async().await;
+
+        Ok(())
+    }
+
     #[test]
     fn test_photo_serialization() -> Result<()> {
         let photo = Photo {
@@ -647,6 +1405,11 @@ mod tests {
             width: 1920,
             height: 1080,
             mime_type: "image/jpeg".to_string(),
+            contributor: None,
+            batch_guid: None,
+            batch_created_at: None,
+            asset_checksum: None,
+            sources: Vec::new(),
         };
 
         let serialized = serde_json::to_string(&photo)?;
@@ -673,6 +1436,11 @@ mod tests {
             width: 1920,
             height: 1080,
             mime_type: "image/jpeg".to_string(),
+            contributor: None,
+            batch_guid: None,
+            batch_created_at: None,
+            asset_checksum: None,
+            sources: Vec::new(),
         };
 
         album.photos.insert(photo.guid.clone(), photo);
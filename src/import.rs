@@ -0,0 +1,494 @@
+//! Rebuilding a photostream index from page bundles that are already on disk.
+//!
+//! Sites that had photo bundles before using this tool, or that lost their
+//! index file, would otherwise download every photo again. Importing scans
+//! the output directory, reads each bundle's frontmatter and media file, and
+//! matches the bundle against the live album, first by GUID (the `guid`
+//! frontmatter field or the directory name) and then by the content hash of
+//! its media. Matched bundles are added to the index with the album's
+//! checksum, so the next sync treats them as unchanged.
+//!
+//! Bundles that match no photo in the album are left alone; adding them to
+//! the index would make the next sync delete them.
+
+use anyhow::{Context, Result};
+use log::warn;
+use serde_yaml::Mapping;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::exif::extract_exif;
+use crate::frontmatter;
+use crate::geocode::Location;
+use crate::icloud::{Album, Photo};
+use crate::index::{IndexedPhoto, PhotoIndex, content_hash};
+use crate::source::mime_type_for;
+
+/// File name of the media in bundles written by this tool
+const MEDIA_FILE: &str = "original.jpg";
+
+/// How a bundle was matched to a photo in the album
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchedBy {
+    Guid,
+    ContentHash,
+}
+
+/// What happened to one bundle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BundleOutcome {
+    /// The bundle was added to the index, and moved to the directory named
+    /// after its GUID if it was somewhere else
+    Imported {
+        guid: String,
+        matched_by: MatchedBy,
+        moved_to: Option<PathBuf>,
+    },
+    /// The bundle's photo was already in the index
+    AlreadyIndexed(String),
+    /// No photo in the album matches the bundle
+    Unmatched,
+    /// The bundle couldn't be imported
+    Skipped(String),
+}
+
+/// Imports the bundles in `content_dir` that match photos in `album`
+///
+/// `site_root` is the directory of the Hugo site, which photo paths in the
+/// index are relative to.
+pub fn import_bundles(
+    content_dir: &Path,
+    site_root: &Path,
+    album: &Album,
+    index: &mut PhotoIndex,
+) -> Result<Vec<(PathBuf, BundleOutcome)>> {
+    let mut bundles: Vec<PathBuf> = fs::read_dir(content_dir)
+        .with_context(|| format!("Failed to read {}", content_dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir() && !is_hidden(path))
+        .collect();
+    bundles.sort();
+
+    let mut claimed: HashSet<String> = HashSet::new();
+    let mut outcomes = Vec::new();
+    for bundle in bundles {
+        let outcome = import_bundle(&bundle, content_dir, site_root, album, index, &mut claimed)
+            .unwrap_or_else(|e| BundleOutcome::Skipped(format!("{e:#}")));
+        outcomes.push((bundle, outcome));
+    }
+
+    Ok(outcomes)
+}
+
+fn import_bundle(
+    bundle: &Path,
+    content_dir: &Path,
+    site_root: &Path,
+    album: &Album,
+    index: &mut PhotoIndex,
+    claimed: &mut HashSet<String>,
+) -> Result<BundleOutcome> {
+    let Some(media) = find_media(bundle)? else {
+        return Ok(BundleOutcome::Skipped("No media file".to_string()));
+    };
+
+    let page = bundle.join("index.md");
+    let frontmatter = if page.exists() {
+        frontmatter::read(&page)?.unwrap_or_default()
+    } else {
+        Mapping::new()
+    };
+
+    let contents =
+        fs::read(&media).with_context(|| format!("Failed to read {}", media.display()))?;
+    let hash = content_hash(&contents);
+
+    let guid = frontmatter::string(&frontmatter, "guid").unwrap_or_else(|| file_name(bundle));
+    let matched = match album.photos.get(&guid) {
+        Some(photo) => Some((photo, MatchedBy::Guid)),
+        None => album
+            .photos
+            .values()
+            .find(|photo| photo.checksum == hash || photo.asset_checksum.as_ref() == Some(&hash))
+            .map(|photo| (photo, MatchedBy::ContentHash)),
+    };
+    let Some((photo, matched_by)) = matched else {
+        return Ok(BundleOutcome::Unmatched);
+    };
+
+    if index.get_photo(&photo.guid).is_some() {
+        return Ok(BundleOutcome::AlreadyIndexed(photo.guid.clone()));
+    }
+    if claimed.contains(&photo.guid) {
+        return Ok(BundleOutcome::Skipped(format!(
+            "Another bundle was already imported as {}",
+            photo.guid
+        )));
+    }
+
+    // Syncs expect each photo's bundle in the directory named after its GUID
+    let target = content_dir.join(&photo.guid);
+    let moved_to = if bundle != target {
+        if target.exists() {
+            return Ok(BundleOutcome::Skipped(format!(
+                "{} already exists",
+                target.display()
+            )));
+        }
+        fs::rename(bundle, &target).with_context(|| {
+            format!(
+                "Failed to move {} to {}",
+                bundle.display(),
+                target.display()
+            )
+        })?;
+        Some(target.clone())
+    } else {
+        None
+    };
+
+    claimed.insert(photo.guid.clone());
+    let media = target.join(file_name(&media));
+    index.add_or_update_photo(indexed_photo(photo, &media, &frontmatter, site_root));
+
+    Ok(BundleOutcome::Imported {
+        guid: photo.guid.clone(),
+        matched_by,
+        moved_to,
+    })
+}
+
+/// Builds the index entry for a matched bundle
+///
+/// Everything the album knows comes from the album, so the next sync sees no
+/// change. EXIF and location fields come from the frontmatter, falling back
+/// to the media file's EXIF data.
+fn indexed_photo(
+    photo: &Photo,
+    media: &Path,
+    frontmatter: &Mapping,
+    site_root: &Path,
+) -> IndexedPhoto {
+    let mut indexed_photo = IndexedPhoto::new(
+        photo.guid.clone(),
+        photo.filename.clone(),
+        photo.caption.clone(),
+        photo.created_at,
+        photo.checksum.clone(),
+        photo.url.clone(),
+        photo.width,
+        photo.height,
+        media.to_path_buf(),
+        site_root,
+    );
+    indexed_photo.mime_type = photo.mime_type.clone();
+    indexed_photo.contributor = photo.contributor.clone();
+    indexed_photo.sources = photo.sources.clone();
+    indexed_photo.asset_checksum = photo.asset_checksum.clone();
+
+    if let Ok(exif) = extract_exif(media) {
+        indexed_photo.update_exif(&exif);
+    }
+
+    let text = |key| frontmatter::string(frontmatter, key);
+    let number = |key| frontmatter::number(frontmatter, key);
+    if let Some(make) = text("camera_make") {
+        indexed_photo.camera_make = Some(make);
+    }
+    if let Some(model) = text("camera_model") {
+        indexed_photo.camera_model = Some(model);
+    }
+    if let Some(taken_at) = frontmatter::date(frontmatter, "exif_date") {
+        indexed_photo.exif_date_time = Some(taken_at);
+    }
+    if let (Some(lat), Some(lon)) = (number("original_latitude"), number("original_longitude")) {
+        indexed_photo.latitude = Some(lat);
+        indexed_photo.longitude = Some(lon);
+    }
+    if let (Some(lat), Some(lon)) = (number("latitude"), number("longitude")) {
+        indexed_photo.fuzzed_latitude = Some(lat);
+        indexed_photo.fuzzed_longitude = Some(lon);
+    }
+    if let Some(iso) = number("iso") {
+        indexed_photo.iso = Some(iso as u32);
+    }
+    if let Some(exposure) = text("exposure_time") {
+        indexed_photo.exposure_time = Some(exposure);
+    }
+    if let Some(aperture) = number("f_number") {
+        indexed_photo.f_number = Some(aperture as f32);
+    }
+    if let Some(focal) = number("focal_length") {
+        indexed_photo.focal_length = Some(focal as f32);
+    }
+    if let Some(formatted_address) = text("location") {
+        indexed_photo.update_location(Location {
+            formatted_address,
+            city: text("city"),
+            state: text("state"),
+            country: text("country"),
+        });
+    }
+
+    if let Err(e) = indexed_photo.record_file(site_root) {
+        warn!("Failed to hash photo {}: {e:#}", photo.guid);
+    }
+    indexed_photo
+}
+
+/// Finds a bundle's media file, preferring the name this tool uses
+fn find_media(bundle: &Path) -> Result<Option<PathBuf>> {
+    let preferred = bundle.join(MEDIA_FILE);
+    if preferred.is_file() {
+        return Ok(Some(preferred));
+    }
+
+    let mut media: Vec<PathBuf> = fs::read_dir(bundle)
+        .with_context(|| format!("Failed to read {}", bundle.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && !is_hidden(path) && mime_type_for(path).is_some())
+        .collect();
+    media.sort();
+    Ok(media.into_iter().next())
+}
+
+fn is_hidden(path: &Path) -> bool {
+    file_name(path).starts_with('.')
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::collections::HashMap;
+    use tempfile::tempdir;
+
+    fn create_photo(guid: &str, checksum: &str) -> Photo {
+        Photo {
+            guid: guid.to_string(),
+            filename: format!("{guid}.jpg"),
+            caption: Some(format!("Caption for {guid}")),
+            created_at: Utc::now(),
+            checksum: checksum.to_string(),
+            url: format!("https://example.com/{guid}.jpg"),
+            width: 800,
+            height: 600,
+            mime_type: "image/jpeg".to_string(),
+            contributor: None,
+            batch_guid: None,
+            batch_created_at: None,
+            asset_checksum: None,
+            sources: Vec::new(),
+        }
+    }
+
+    fn create_bundle(dir: &Path, media: &str, contents: &str, page: Option<&str>) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join(media), contents)?;
+        if let Some(page) = page {
+            fs::write(dir.join("index.md"), page)?;
+        }
+        Ok(())
+    }
+
+    fn album(photos: Vec<Photo>) -> Album {
+        Album {
+            name: "Test Album".to_string(),
+            photos: photos
+                .into_iter()
+                .map(|photo| (photo.guid.clone(), photo))
+                .collect::<HashMap<_, _>>(),
+        }
+    }
+
+    #[test]
+    fn test_import_by_guid() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/photostream");
+        create_bundle(
+            &content_dir.join("beach"),
+            "beach.jpg",
+            "BEACH",
+            Some(
+                "---\ntitle: Beach\nguid: photo1\ncamera_make: Apple\nlocation: Chicago, IL, USA\ncity: Chicago\n---\n",
+            ),
+        )?;
+        let album = album(vec![create_photo("photo1", "remote1")]);
+
+        let mut index = PhotoIndex::new();
+        let outcomes = import_bundles(&content_dir, temp_dir.path(), &album, &mut index)?;
+        assert_eq!(
+            outcomes,
+            vec![(
+                content_dir.join("beach"),
+                BundleOutcome::Imported {
+                    guid: "photo1".to_string(),
+                    matched_by: MatchedBy::Guid,
+                    moved_to: Some(content_dir.join("photo1")),
+                }
+            )]
+        );
+
+        let photo1 = index.get_photo("photo1").unwrap();
+        assert_eq!(photo1.checksum, "remote1");
+        assert_eq!(photo1.camera_make.as_deref(), Some("Apple"));
+        assert_eq!(
+            photo1.location.as_ref().unwrap().city.as_deref(),
+            Some("Chicago")
+        );
+        assert_eq!(
+            photo1.local_path,
+            PathBuf::from("content/photostream/photo1/beach.jpg")
+        );
+        assert!(photo1.file_path(temp_dir.path()).exists());
+        assert_eq!(photo1.content_hash, Some(content_hash(b"BEACH")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_by_content_hash() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/photostream");
+        create_bundle(&content_dir.join("sunset"), "original.jpg", "SUNSET", None)?;
+        let album = album(vec![create_photo("photo2", &content_hash(b"SUNSET"))]);
+
+        let mut index = PhotoIndex::new();
+        let outcomes = import_bundles(&content_dir, temp_dir.path(), &album, &mut index)?;
+        assert_eq!(
+            outcomes,
+            vec![(
+                content_dir.join("sunset"),
+                BundleOutcome::Imported {
+                    guid: "photo2".to_string(),
+                    matched_by: MatchedBy::ContentHash,
+                    moved_to: Some(content_dir.join("photo2")),
+                }
+            )]
+        );
+        assert!(content_dir.join("photo2/original.jpg").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bundle_named_after_guid_stays() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/photostream");
+        create_bundle(&content_dir.join("photo1"), "original.jpg", "PHOTO", None)?;
+        let album = album(vec![create_photo("photo1", "remote1")]);
+
+        let mut index = PhotoIndex::new();
+        let outcomes = import_bundles(&content_dir, temp_dir.path(), &album, &mut index)?;
+        assert_eq!(
+            outcomes[0].1,
+            BundleOutcome::Imported {
+                guid: "photo1".to_string(),
+                matched_by: MatchedBy::Guid,
+                moved_to: None,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unmatched_bundle_is_left_alone() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/photostream");
+        create_bundle(&content_dir.join("party"), "party.jpg", "PARTY", None)?;
+        let album = album(vec![create_photo("photo1", "remote1")]);
+
+        let mut index = PhotoIndex::new();
+        let outcomes = import_bundles(&content_dir, temp_dir.path(), &album, &mut index)?;
+        assert_eq!(
+            outcomes,
+            vec![(content_dir.join("party"), BundleOutcome::Unmatched)]
+        );
+        assert_eq!(index.photo_count(), 0);
+        assert!(content_dir.join("party/party.jpg").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_again_leaves_index_alone() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/photostream");
+        create_bundle(&content_dir.join("beach"), "beach.jpg", "BEACH", None)?;
+        let album = album(vec![create_photo("photo1", &content_hash(b"BEACH"))]);
+
+        let mut index = PhotoIndex::new();
+        import_bundles(&content_dir, temp_dir.path(), &album, &mut index)?;
+        let outcomes = import_bundles(&content_dir, temp_dir.path(), &album, &mut index)?;
+        assert_eq!(
+            outcomes,
+            vec![(
+                content_dir.join("photo1"),
+                BundleOutcome::AlreadyIndexed("photo1".to_string())
+            )]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_skipped_bundles() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/photostream");
+        // No media to import
+        create_bundle(&content_dir.join("empty"), "notes.txt", "NOTES", None)?;
+        // A copy of the photo whose directory is already taken
+        create_bundle(&content_dir.join("copy"), "copy.jpg", "BEACH", None)?;
+        create_bundle(&content_dir.join("photo1"), "original.jpg", "EDITED", None)?;
+        let album = album(vec![create_photo("photo1", &content_hash(b"BEACH"))]);
+
+        let mut index = PhotoIndex::new();
+        let outcomes = import_bundles(&content_dir, temp_dir.path(), &album, &mut index)?;
+        assert_eq!(
+            outcomes,
+            vec![
+                (
+                    content_dir.join("copy"),
+                    BundleOutcome::Skipped(format!(
+                        "{} already exists",
+                        content_dir.join("photo1").display()
+                    ))
+                ),
+                (
+                    content_dir.join("empty"),
+                    BundleOutcome::Skipped("No media file".to_string())
+                ),
+                (
+                    content_dir.join("photo1"),
+                    BundleOutcome::Imported {
+                        guid: "photo1".to_string(),
+                        matched_by: MatchedBy::Guid,
+                        moved_to: None,
+                    }
+                ),
+            ]
+        );
+        assert!(content_dir.join("copy/copy.jpg").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_missing_content_dir_fails() {
+        let temp_dir = tempdir().unwrap();
+        let content_dir = temp_dir.path().join("missing");
+        let mut index = PhotoIndex::new();
+
+        let err =
+            import_bundles(&content_dir, temp_dir.path(), &album(vec![]), &mut index).unwrap_err();
+        assert!(format!("{err:#}").contains("Failed to read"));
+    }
+}
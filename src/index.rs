@@ -12,22 +12,44 @@
 //!
 //! This allows the application to efficiently determine which photos need
 //! to be added, updated, or removed during synchronization.
+//!
+//! Changes made while a journal is open are also appended to it right away
+//! (see the `journal` module), so an interrupted sync keeps its progress.
 
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
 use crate::geocode::Location;
+use crate::hashtags;
+use crate::journal::{self, Journal, JournalEntry};
+use crate::migrate::{self, NewerSchemaError};
+use crate::persist;
+
+/// Number of previous index versions kept next to the index file
+pub const INDEX_BACKUPS: usize = 3;
 
 /// Default MIME type for backward compatibility
 fn default_mime_type() -> String {
     "image/jpeg".to_string()
 }
 
+/// Whether a photo may be published, in outputs that require approval
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Approval {
+    /// Synced, but waiting to be approved; the page is a draft
+    Pending,
+    /// Approved and published
+    Approved,
+    /// Kept off the site for good; the entry stays so syncs skip the photo
+    Rejected,
+}
+
 /// Represents a stored photo's metadata in our local index
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IndexedPhoto {
@@ -49,11 +71,33 @@ pub struct IndexedPhoto {
     pub height: u32,
     /// When this photo was last synchronized
     pub last_sync: DateTime<Utc>,
-    /// Local path to the photo
+    /// Path to the photo, relative to the site root when it is inside it
     pub local_path: PathBuf,
     /// MIME type of the photo (e.g., "image/jpeg", "image/png")
     #[serde(default = "default_mime_type")]
     pub mime_type: String,
+    /// Name of the album member who posted the photo
+    #[serde(default)]
+    pub contributor: Option<String>,
+    /// URLs of the source albums the photo was found in
+    #[serde(default)]
+    pub sources: Vec<String>,
+    /// Content checksum of the downloaded derivative, used to skip URL
+    /// resolution for unchanged photos
+    #[serde(default)]
+    pub asset_checksum: Option<String>,
+    /// Size of the file on disk in bytes
+    #[serde(default)]
+    pub file_size: Option<u64>,
+    /// MD5 hash of the file on disk, used to detect damaged or replaced files
+    #[serde(default)]
+    pub content_hash: Option<String>,
+    /// Terms of the hashtags in the caption
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Approval state, only set in outputs that require approval
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approval: Option<Approval>,
 
     // EXIF metadata
     /// Make of the camera used (e.g., "Apple")
@@ -85,7 +129,7 @@ pub struct IndexedPhoto {
 }
 
 /// Represents a gallery collection of photos
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Gallery {
     /// Unique identifier for the gallery
     pub id: String,
@@ -137,19 +181,110 @@ impl Gallery {
     }
 }
 
+/// Represents a batch of photos that were posted to the album together
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Batch {
+    /// Batch GUID from iCloud (or the photo GUID for photos without a batch)
+    pub id: String,
+    /// URL-friendly name for the batch directory
+    pub slug: String,
+    /// Caption posted with the batch (if any)
+    pub caption: Option<String>,
+    /// When the batch was posted
+    pub created_at: DateTime<Utc>,
+    /// List of photo GUIDs included in this batch
+    pub photos: Vec<String>,
+    /// When the batch was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Batch {
+    /// Creates a new, empty batch
+    pub fn new(id: String, slug: String, created_at: DateTime<Utc>) -> Self {
+        Self {
+            id,
+            slug,
+            caption: None,
+            created_at,
+            photos: Vec::new(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    /// Adds a photo to the batch
+    pub fn add_photo(&mut self, guid: String) {
+        if !self.photos.contains(&guid) {
+            self.photos.push(guid);
+            self.updated_at = Utc::now();
+        }
+    }
+
+    /// Removes a photo from the batch
+    pub fn remove_photo(&mut self, guid: &str) {
+        if let Some(index) = self.photos.iter().position(|p| p == guid) {
+            self.photos.remove(index);
+            self.updated_at = Utc::now();
+        }
+    }
+}
+
+/// A photo the GPS filter rule left out after it was downloaded
+///
+/// Remembered so syncs don't download the photo again to check the rule,
+/// until the photo or the rule changes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FilteredPhoto {
+    /// Checksum of the photo when it was checked
+    pub checksum: String,
+    /// Whether the photo has GPS coordinates
+    pub has_gps: bool,
+}
+
+/// Records that the last sync of an output couldn't update its content
+///
+/// The local content is kept as it was. Hugo templates can read the marker
+/// from the data file to show a notice.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StaleMarker {
+    /// When the output first went stale
+    pub since: DateTime<Utc>,
+    /// Why the last sync couldn't update the output
+    pub reason: String,
+}
+
 /// Represents our local database of photos
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PhotoIndex {
+    /// Schema version the index was written with (see the `migrate` module)
+    #[serde(default)]
+    pub schema_version: u32,
     /// When the index was last updated
     pub last_updated: DateTime<Utc>,
     /// Map of photo GUIDs to indexed photos
-    pub photos: HashMap<String, IndexedPhoto>,
+    pub photos: BTreeMap<String, IndexedPhoto>,
     /// Map of gallery IDs to galleries
-    pub galleries: HashMap<String, Gallery>,
+    pub galleries: BTreeMap<String, Gallery>,
+    /// Map of batch IDs to upload batches
+    #[serde(default)]
+    pub batches: BTreeMap<String, Batch>,
+    /// Photos the GPS filter rule left out, by GUID
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub filtered: BTreeMap<String, FilteredPhoto>,
+    /// Set when the last sync left the content untouched because of a problem with the album
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stale: Option<StaleMarker>,
+    /// Journal that changes are appended to while a sync runs
+    #[serde(skip)]
+    journal: Option<Journal>,
+    /// Backup the index was loaded from because the index file was damaged
+    #[serde(skip)]
+    recovered_from: Option<PathBuf>,
 }
 
 impl IndexedPhoto {
     /// Creates a new photo index entry with minimal data
+    ///
+    /// `local_path` is stored relative to `site_root` when it's inside it.
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         guid: String,
@@ -161,6 +296,7 @@ impl IndexedPhoto {
         width: u32,
         height: u32,
         local_path: PathBuf,
+        site_root: &Path,
     ) -> Self {
         // Determine MIME type from filename if possible
         let mime_type = if filename.ends_with(".jpg") || filename.ends_with(".jpeg") {
@@ -173,8 +309,10 @@ impl IndexedPhoto {
             "image/gif".to_string()
         } else if filename.ends_with(".webp") {
             "image/webp".to_string()
-        } else if filename.ends_with(".mp4") || filename.ends_with(".mov") {
+        } else if filename.ends_with(".mp4") {
             "video/mp4".to_string()
+        } else if filename.ends_with(".mov") {
+            "video/quicktime".to_string()
         } else {
             // Default to JPEG if we can't determine the type
             "image/jpeg".to_string()
@@ -183,6 +321,7 @@ impl IndexedPhoto {
         Self {
             guid,
             filename,
+            tags: caption.as_deref().map(hashtags::parse).unwrap_or_default(),
             caption,
             created_at,
             checksum,
@@ -190,8 +329,14 @@ impl IndexedPhoto {
             width,
             height,
             last_sync: Utc::now(),
-            local_path,
+            local_path: relative_to(&local_path, site_root),
             mime_type,
+            contributor: None,
+            sources: Vec::new(),
+            asset_checksum: None,
+            file_size: None,
+            content_hash: None,
+            approval: None,
             camera_make: None,
             camera_model: None,
             exif_date_time: None,
@@ -207,6 +352,31 @@ impl IndexedPhoto {
         }
     }
 
+    /// Whether the photo was rejected, so syncs leave it alone
+    pub fn is_rejected(&self) -> bool {
+        self.approval == Some(Approval::Rejected)
+    }
+
+    /// Whether the photo is kept off the site until it's approved, or for good
+    pub fn is_unapproved(&self) -> bool {
+        matches!(self.approval, Some(Approval::Pending | Approval::Rejected))
+    }
+
+    /// The photo's file, resolved against the site root
+    pub fn file_path(&self, site_root: &Path) -> PathBuf {
+        site_root.join(&self.local_path)
+    }
+
+    /// Records the size and content hash of the photo's file
+    pub fn record_file(&mut self, site_root: &Path) -> Result<()> {
+        let path = self.file_path(site_root);
+        let contents =
+            fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        self.file_size = Some(contents.len() as u64);
+        self.content_hash = Some(content_hash(&contents));
+        Ok(())
+    }
+
     /// Update this photo with EXIF metadata
     pub fn update_exif(&mut self, exif: &crate::exif::ExifMetadata) {
         self.camera_make = exif.camera_make.clone();
@@ -222,73 +392,257 @@ impl IndexedPhoto {
         self.focal_length = exif.focal_length;
     }
 
+    /// Checks whether the remote photo's metadata (caption, date, dimensions,
+    /// contributor, source albums) differs from what we have indexed
+    ///
+    /// Content changes are detected separately through the checksum.
+    pub fn metadata_differs(&self, photo: &crate::icloud::Photo) -> bool {
+        self.caption != photo.caption
+            || self.created_at != photo.created_at
+            || self.width != photo.width
+            || self.height != photo.height
+            || self.contributor != photo.contributor
+            || self.sources != photo.sources
+    }
+
+    /// Update this photo with the metadata fields of a remote photo
+    pub fn update_metadata(&mut self, photo: &crate::icloud::Photo) {
+        self.caption = photo.caption.clone();
+        self.tags = self
+            .caption
+            .as_deref()
+            .map(hashtags::parse)
+            .unwrap_or_default();
+        self.created_at = photo.created_at;
+        self.width = photo.width;
+        self.height = photo.height;
+        self.contributor = photo.contributor.clone();
+        self.sources = photo.sources.clone();
+        self.last_sync = Utc::now();
+    }
+
     /// Update this photo with location data from geocoding
     pub fn update_location(&mut self, location: crate::geocode::Location) {
         self.location = Some(location);
     }
+
+    /// Rebuilds the remote photo this entry was made from, for downloading it again
+    pub fn to_photo(&self) -> crate::icloud::Photo {
+        crate::icloud::Photo {
+            guid: self.guid.clone(),
+            filename: self.filename.clone(),
+            caption: self.caption.clone(),
+            created_at: self.created_at,
+            checksum: self.checksum.clone(),
+            url: self.url.clone(),
+            width: self.width,
+            height: self.height,
+            mime_type: self.mime_type.clone(),
+            contributor: self.contributor.clone(),
+            batch_guid: None,
+            batch_created_at: None,
+            asset_checksum: self.asset_checksum.clone(),
+            sources: self.sources.clone(),
+        }
+    }
 }
 
 impl PhotoIndex {
     /// Creates a new empty photo index
     pub fn new() -> Self {
         Self {
+            schema_version: migrate::CURRENT_SCHEMA_VERSION,
             last_updated: Utc::now(),
-            photos: HashMap::new(),
-            galleries: HashMap::new(),
+            photos: BTreeMap::new(),
+            galleries: BTreeMap::new(),
+            batches: BTreeMap::new(),
+            filtered: BTreeMap::new(),
+            stale: None,
+            journal: None,
+            recovered_from: None,
         }
     }
 
     /// Load the photo index from the specified path
-    pub fn load(path: &Path) -> Result<Self> {
-        // If the file doesn't exist, create a new empty index
-        if !path.exists() {
-            return Ok(Self::new());
-        }
+    ///
+    /// Changes journaled by an interrupted sync are replayed on top of the file.
+    /// If the file can't be read, the newest backup that can is used instead.
+    /// Older files are migrated to the current schema, newer ones are refused;
+    /// `site_root` is the directory photo paths are made relative to.
+    pub fn load(path: &Path, site_root: &Path) -> Result<Self> {
+        // If the file doesn't exist, start from a new empty index
+        let mut index = if path.exists() {
+            match Self::read(path, site_root) {
+                Ok(index) => index,
+                // Backups of a newer index may be older, loading one would lose data
+                Err(err) if err.is::<NewerSchemaError>() => return Err(err),
+                Err(err) => Self::recover(path, site_root, err)?,
+            }
+        } else {
+            Self::new()
+        };
+
+        index.replay_journal(path)?;
+        Ok(index)
+    }
 
-        // Read and parse the YAML file
+    /// Reads and parses an index file, migrating it to the current schema
+    fn read(path: &Path, site_root: &Path) -> Result<Self> {
         let yaml = fs::read_to_string(path)
             .with_context(|| format!("Failed to read index file from {}", path.display()))?;
 
-        let index: PhotoIndex = serde_yaml::from_str(&yaml)
+        let value: serde_yaml::Value = serde_yaml::from_str(&yaml)
             .with_context(|| format!("Failed to parse index file from {}", path.display()))?;
 
-        Ok(index)
+        Self::from_value(value, site_root)
+            .with_context(|| format!("Failed to parse index file from {}", path.display()))
     }
 
-    /// Save the photo index to the specified path
-    pub fn save(&self, path: &Path) -> Result<()> {
-        // Ensure parent directory exists
-        if let Some(parent) = path.parent() {
-            if !parent.as_os_str().is_empty() {
-                fs::create_dir_all(parent).with_context(|| {
-                    format!("Failed to create directory for {}", path.display())
-                })?;
+    /// Builds an index from its raw form, migrating it to the current schema
+    pub fn from_value(mut value: serde_yaml::Value, site_root: &Path) -> Result<Self> {
+        migrate::migrate(&mut value, site_root)?;
+        Ok(serde_yaml::from_value(value)?)
+    }
+
+    /// Applies the changes journaled next to the index file at `path`
+    /// by a sync that was interrupted before it saved
+    pub fn replay_journal(&mut self, path: &Path) -> Result<()> {
+        let entries = journal::read_entries(path)?;
+        if !entries.is_empty() {
+            log::info!(
+                "Replaying {} journaled changes from an interrupted sync",
+                entries.len()
+            );
+            for entry in entries {
+                self.apply(entry);
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads the newest readable backup of a damaged index file
+    fn recover(path: &Path, site_root: &Path, err: anyhow::Error) -> Result<Self> {
+        for backup in persist::backups(path) {
+            match Self::read(&backup, site_root) {
+                Ok(mut index) => {
+                    log::warn!("{err:#}; using the backup at {} instead", backup.display());
+                    index.recovered_from = Some(backup);
+                    return Ok(index);
+                }
+                Err(backup_err) => log::warn!("{backup_err:#}"),
+            }
+        }
+
+        Err(err.context("No readable backup of the index was found"))
+    }
+
+    /// The backup this index was loaded from, if the index file was damaged
+    pub fn recovered_from(&self) -> Option<&Path> {
+        self.recovered_from.as_deref()
+    }
+
+    /// Starts journaling changes next to the index file at `path`
+    pub fn open_journal(&mut self, path: &Path) -> Result<()> {
+        self.journal = Some(Journal::open(path)?);
+        Ok(())
+    }
+
+    /// Applies a journaled change
+    fn apply(&mut self, entry: JournalEntry) {
+        match entry {
+            JournalEntry::Photo { photo } => self.add_or_update_photo(*photo),
+            JournalEntry::RemovePhoto { guid } => {
+                self.remove_photo(&guid);
+            }
+            JournalEntry::Gallery { gallery } => self.add_or_update_gallery(gallery),
+            JournalEntry::RemoveGallery { id } => {
+                self.remove_gallery(&id);
+            }
+            JournalEntry::Batch { batch } => self.add_or_update_batch(batch),
+            JournalEntry::RemoveBatch { id } => {
+                self.remove_batch(&id);
             }
         }
+    }
+
+    /// Appends a change to the journal, if one is open
+    ///
+    /// A journal that can't be written only costs the ability to resume, so
+    /// the failure is logged rather than failing the sync.
+    fn record(&self, entry: impl FnOnce() -> JournalEntry) {
+        if let Some(journal) = &self.journal
+            && let Err(e) = journal.append(&entry())
+        {
+            log::warn!("{e:#}");
+        }
+    }
 
+    /// Save the photo index to the specified path
+    ///
+    /// The file is replaced atomically and the previous versions are kept as
+    /// backups next to it.
+    pub fn save(&self, path: &Path) -> Result<()> {
         // Serialize and write to file
         let yaml =
             serde_yaml::to_string(self).with_context(|| "Failed to serialize index to YAML")?;
 
-        fs::write(path, yaml)
+        persist::write_with_backups(path, yaml.as_bytes(), INDEX_BACKUPS)
             .with_context(|| format!("Failed to write index file to {}", path.display()))?;
 
-        Ok(())
+        self.checkpoint(path)
+    }
+
+    /// Empties the journal of the index file at `path` once everything
+    /// journaled so far was saved
+    pub fn checkpoint(&self, path: &Path) -> Result<()> {
+        match &self.journal {
+            Some(journal) => journal.truncate(),
+            None => journal::remove(path),
+        }
+    }
+
+    /// Marks the output as stale, keeping the original time if it already was
+    pub fn mark_stale(&mut self, reason: String) {
+        let since = self
+            .stale
+            .as_ref()
+            .map_or_else(Utc::now, |stale| stale.since);
+        self.stale = Some(StaleMarker { since, reason });
+        self.last_updated = Utc::now();
+    }
+
+    /// Clears the stale marker after a successful sync
+    pub fn clear_stale(&mut self) {
+        if self.stale.take().is_some() {
+            self.last_updated = Utc::now();
+        }
     }
 
     /// Add or update a photo in the index
     pub fn add_or_update_photo(&mut self, photo: IndexedPhoto) {
+        self.record(|| JournalEntry::Photo {
+            photo: Box::new(photo.clone()),
+        });
         self.photos.insert(photo.guid.clone(), photo);
         self.last_updated = Utc::now();
     }
 
     /// Remove a photo from the index
     pub fn remove_photo(&mut self, guid: &str) -> Option<IndexedPhoto> {
+        self.record(|| JournalEntry::RemovePhoto {
+            guid: guid.to_string(),
+        });
+
         // Remove photo from all galleries
         for gallery in self.galleries.values_mut() {
             gallery.remove_photo(guid);
         }
 
+        // Remove photo from all batches
+        for batch in self.batches.values_mut() {
+            batch.remove_photo(guid);
+        }
+
         // Remove from photos collection
         let result = self.photos.remove(guid);
         if result.is_some() {
@@ -297,6 +651,25 @@ impl PhotoIndex {
         result
     }
 
+    /// Points the photos stored under `from` to the same files under `to`,
+    /// returning how many photos were changed
+    pub fn rebase_paths(&mut self, from: &Path, to: &Path, site_root: &Path) -> usize {
+        let from = relative_to(from, site_root);
+        let to = relative_to(to, site_root);
+
+        let mut rebased = 0;
+        for photo in self.photos.values_mut() {
+            if let Ok(rest) = relative_to(&photo.local_path, site_root).strip_prefix(&from) {
+                photo.local_path = to.join(rest);
+                rebased += 1;
+            }
+        }
+        if rebased > 0 {
+            self.last_updated = Utc::now();
+        }
+        rebased
+    }
+
     /// Get a photo from the index by GUID
     pub fn get_photo(&self, guid: &str) -> Option<&IndexedPhoto> {
         self.photos.get(guid)
@@ -309,13 +682,35 @@ impl PhotoIndex {
 
     /// Add a new gallery or update an existing one
     pub fn add_or_update_gallery(&mut self, gallery: Gallery) {
+        self.record(|| JournalEntry::Gallery {
+            gallery: gallery.clone(),
+        });
         self.galleries.insert(gallery.id.clone(), gallery);
         self.last_updated = Utc::now();
     }
 
+    /// Changes a gallery in place, returning false if there is no such gallery
+    ///
+    /// The change is only recorded if the gallery actually differs afterwards.
+    pub fn update_gallery(&mut self, id: &str, update: impl FnOnce(&mut Gallery)) -> bool {
+        let Some(gallery) = self.galleries.get_mut(id) else {
+            return false;
+        };
+        let before = gallery.clone();
+        update(gallery);
+
+        // Leave the index untouched when nothing changed, so it stays byte-identical
+        if *gallery != before {
+            let gallery = gallery.clone();
+            self.record(|| JournalEntry::Gallery { gallery });
+            self.last_updated = Utc::now();
+        }
+        true
+    }
+
     /// Remove a gallery from the index
-    #[allow(dead_code)]
     pub fn remove_gallery(&mut self, id: &str) -> Option<Gallery> {
+        self.record(|| JournalEntry::RemoveGallery { id: id.to_string() });
         let result = self.galleries.remove(id);
         if result.is_some() {
             self.last_updated = Utc::now();
@@ -345,6 +740,84 @@ impl PhotoIndex {
             Vec::new()
         }
     }
+
+    /// Add a new batch or update an existing one
+    pub fn add_or_update_batch(&mut self, batch: Batch) {
+        self.record(|| JournalEntry::Batch {
+            batch: batch.clone(),
+        });
+        self.batches.insert(batch.id.clone(), batch);
+        self.last_updated = Utc::now();
+    }
+
+    /// Changes a batch in place, returning false if there is no such batch
+    ///
+    /// The change is only recorded if the batch actually differs afterwards.
+    pub fn update_batch(&mut self, id: &str, update: impl FnOnce(&mut Batch)) -> bool {
+        let Some(batch) = self.batches.get_mut(id) else {
+            return false;
+        };
+        let before = batch.clone();
+        update(batch);
+
+        // Nothing to record when the batch didn't change
+        if *batch != before {
+            let batch = batch.clone();
+            self.record(|| JournalEntry::Batch { batch });
+            self.last_updated = Utc::now();
+        }
+        true
+    }
+
+    /// Remove a batch from the index
+    pub fn remove_batch(&mut self, id: &str) -> Option<Batch> {
+        self.record(|| JournalEntry::RemoveBatch { id: id.to_string() });
+        let result = self.batches.remove(id);
+        if result.is_some() {
+            self.last_updated = Utc::now();
+        }
+        result
+    }
+
+    /// Get a batch by ID
+    pub fn get_batch(&self, id: &str) -> Option<&Batch> {
+        self.batches.get(id)
+    }
+
+    /// Number of batches in the index
+    pub fn batch_count(&self) -> usize {
+        self.batches.len()
+    }
+
+    /// Get all photos in a batch
+    pub fn get_batch_photos(&self, batch_id: &str) -> Vec<&IndexedPhoto> {
+        if let Some(batch) = self.batches.get(batch_id) {
+            batch
+                .photos
+                .iter()
+                .filter_map(|guid| self.photos.get(guid))
+                .collect()
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+/// Hashes file contents the way `IndexedPhoto::content_hash` is stored
+pub fn content_hash(contents: &[u8]) -> String {
+    format!("{:x}", md5::compute(contents))
+}
+
+/// Makes an absolute path under `root` relative to it
+///
+/// Relative paths are kept as they are, as are absolute paths outside `root`.
+pub fn relative_to(path: &Path, root: &Path) -> PathBuf {
+    let path = if path.is_absolute() {
+        path.strip_prefix(root).unwrap_or(path)
+    } else {
+        path.strip_prefix(".").unwrap_or(path)
+    };
+    path.to_path_buf()
 }
 
 impl Default for PhotoIndex {
@@ -359,8 +832,9 @@ pub fn convert_to_indexed_photo(
     icloud_photo: &crate::icloud::Photo,
     content_dir: &Path,
     photo_id: &str,
+    site_root: &Path,
 ) -> IndexedPhoto {
-    IndexedPhoto::new(
+    let mut indexed_photo = IndexedPhoto::new(
         icloud_photo.guid.clone(),
         icloud_photo.filename.clone(),
         icloud_photo.caption.clone(),
@@ -373,7 +847,12 @@ pub fn convert_to_indexed_photo(
             .join(photo_id)
             .join("original.jpg")
             .to_path_buf(),
-    )
+        site_root,
+    );
+    indexed_photo.contributor = icloud_photo.contributor.clone();
+    indexed_photo.sources = icloud_photo.sources.clone();
+    indexed_photo.asset_checksum = icloud_photo.asset_checksum.clone();
+    indexed_photo
 }
 
 #[cfg(test)]
@@ -392,6 +871,7 @@ mod tests {
             1920,
             1080,
             PathBuf::from("/content/photostream/test_photo/original.jpg"),
+            Path::new(""),
         )
     }
 
@@ -449,7 +929,7 @@ mod tests {
         index.save(&index_path)?;
 
         // Load from file
-        let loaded_index = PhotoIndex::load(&index_path)?;
+        let loaded_index = PhotoIndex::load(&index_path, temp_dir.path())?;
 
         // Verify content
         assert_eq!(loaded_index.photo_count(), 2);
@@ -468,7 +948,7 @@ mod tests {
         let index_path = temp_dir.path().join("nonexistent_index.yaml");
 
         // Load from nonexistent file (should create new empty index)
-        let index = PhotoIndex::load(&index_path)?;
+        let index = PhotoIndex::load(&index_path, temp_dir.path())?;
 
         // Verify it's a new empty index
         assert_eq!(index.photo_count(), 0);
@@ -520,4 +1000,173 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_stale_marker_keeps_first_time() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let index_path = temp_dir.path().join("index.yaml");
+        let mut index = PhotoIndex::new();
+
+        index.mark_stale("Album not found".to_string());
+        let since = index.stale.as_ref().unwrap().since;
+        index.mark_stale("Album is no longer public".to_string());
+        index.save(&index_path)?;
+
+        let loaded = PhotoIndex::load(&index_path, temp_dir.path())?;
+        let stale = loaded.stale.as_ref().unwrap();
+        assert_eq!(stale.since, since);
+        assert_eq!(stale.reason, "Album is no longer public");
+
+        index.clear_stale();
+        index.save(&index_path)?;
+        assert!(!fs::read_to_string(&index_path)?.contains("stale"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_journal_replays_unsaved_changes() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let index_path = temp_dir.path().join("index.yaml");
+
+        let mut index = PhotoIndex::new();
+        index.add_or_update_photo(create_test_photo());
+        index.save(&index_path)?;
+
+        // Changes after opening the journal survive without a full save
+        index.open_journal(&index_path)?;
+        let mut photo2 = create_test_photo();
+        photo2.guid = "test_guid_456".to_string();
+        index.add_or_update_photo(photo2);
+        index.remove_photo("test_guid_123");
+        index.add_or_update_batch(Batch::new(
+            "batch1".to_string(),
+            "batch-1".to_string(),
+            Utc::now(),
+        ));
+        index.update_batch("batch1", |batch| {
+            batch.add_photo("test_guid_456".to_string())
+        });
+
+        let resumed = PhotoIndex::load(&index_path, temp_dir.path())?;
+        assert_eq!(resumed.photo_count(), 1);
+        assert!(resumed.get_photo("test_guid_456").is_some());
+        assert_eq!(
+            resumed.get_batch("batch1").unwrap().photos,
+            vec!["test_guid_456"]
+        );
+
+        // A full save empties the journal
+        index.save(&index_path)?;
+        assert!(journal::read_entries(&index_path)?.is_empty());
+        assert_eq!(
+            PhotoIndex::load(&index_path, temp_dir.path())?.photo_count(),
+            1
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_damaged_index_falls_back_to_backup() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let index_path = temp_dir.path().join("index.yaml");
+
+        // Nothing to fall back on yet
+        fs::write(&index_path, "photos: [not, a, map")?;
+        assert!(PhotoIndex::load(&index_path, temp_dir.path()).is_err());
+        fs::remove_file(&index_path)?;
+
+        let mut index = PhotoIndex::new();
+        index.add_or_update_photo(create_test_photo());
+        index.save(&index_path)?;
+        let mut photo2 = create_test_photo();
+        photo2.guid = "test_guid_456".to_string();
+        index.add_or_update_photo(photo2);
+        index.save(&index_path)?;
+
+        // A crash outside of our writes leaves the file truncated
+        fs::write(
+            &index_path,
+            "last_updated: 2024-01-01T00:00:00Z\nphotos:\n  test_gu",
+        )?;
+
+        let recovered = PhotoIndex::load(&index_path, temp_dir.path())?;
+        assert_eq!(recovered.photo_count(), 1);
+        assert_eq!(
+            recovered.recovered_from(),
+            Some(persist::backup_path(&index_path, 1).as_path())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_newer_schema_is_refused() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let index_path = temp_dir.path().join("index.yaml");
+
+        let mut index = PhotoIndex::new();
+        index.save(&index_path)?;
+        index.add_or_update_photo(create_test_photo());
+        index.save(&index_path)?;
+        assert!(persist::backup_path(&index_path, 1).exists());
+
+        let yaml = fs::read_to_string(&index_path)?.replace(
+            &format!("schema_version: {}", migrate::CURRENT_SCHEMA_VERSION),
+            &format!("schema_version: {}", migrate::CURRENT_SCHEMA_VERSION + 1),
+        );
+        fs::write(&index_path, yaml)?;
+
+        // The older backup must not be used in its place
+        let err = PhotoIndex::load(&index_path, temp_dir.path()).unwrap_err();
+        assert!(err.is::<NewerSchemaError>());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_relative_to_site_root() {
+        let root = Path::new("/srv/site");
+        assert_eq!(
+            relative_to(Path::new("/srv/site/content/photos/a.jpg"), root),
+            PathBuf::from("content/photos/a.jpg")
+        );
+        assert_eq!(
+            relative_to(Path::new("./content/photos/a.jpg"), root),
+            PathBuf::from("content/photos/a.jpg")
+        );
+        assert_eq!(
+            relative_to(Path::new("/elsewhere/a.jpg"), root),
+            PathBuf::from("/elsewhere/a.jpg")
+        );
+    }
+
+    #[test]
+    fn test_rebase_paths() {
+        let mut index = PhotoIndex::new();
+        let mut photo = create_test_photo();
+        photo.local_path = PathBuf::from("content/photostream/test_guid_123/original.jpg");
+        index.add_or_update_photo(photo);
+        let mut other = create_test_photo();
+        other.guid = "test_guid_456".to_string();
+        other.local_path = PathBuf::from("content/other/test_guid_456.jpg");
+        index.add_or_update_photo(other);
+
+        let rebased = index.rebase_paths(
+            Path::new("/srv/site/content/photostream"),
+            Path::new("content/photos"),
+            Path::new("/srv/site"),
+        );
+
+        assert_eq!(rebased, 1);
+        assert_eq!(
+            index.get_photo("test_guid_123").unwrap().local_path,
+            PathBuf::from("content/photos/test_guid_123/original.jpg")
+        );
+        assert_eq!(
+            index.get_photo("test_guid_456").unwrap().local_path,
+            PathBuf::from("content/other/test_guid_456.jpg")
+        );
+    }
 }
@@ -0,0 +1,185 @@
+//! Append-only journal for the photo index.
+//!
+//! The index is only written in full at the end of a sync. To avoid losing
+//! the record of everything downloaded when a sync crashes or is interrupted,
+//! every change to the index is also appended to a journal next to the index
+//! file, one JSON line per change, as soon as it happens.
+//!
+//! Loading the index replays the journal on top of the last full save, so an
+//! interrupted sync resumes where it stopped: photos that were already
+//! downloaded are found unchanged and skipped. Saving the index in full
+//! empties the journal again.
+
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::index::{Batch, Gallery, IndexedPhoto};
+
+/// A single change to the photo index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum JournalEntry {
+    /// A photo was added or updated
+    Photo { photo: Box<IndexedPhoto> },
+    /// A photo was removed
+    RemovePhoto { guid: String },
+    /// A gallery was added or changed
+    Gallery { gallery: Gallery },
+    /// A gallery was removed
+    RemoveGallery { id: String },
+    /// A batch was added or changed
+    Batch { batch: Batch },
+    /// A batch was removed
+    RemoveBatch { id: String },
+}
+
+/// Returns the journal path for an index file
+///
+/// The journal is a hidden file next to the index, so Hugo doesn't try to
+/// read it as site data.
+pub fn journal_path(index_path: &Path) -> PathBuf {
+    let file_name = index_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "index".to_string());
+    index_path.with_file_name(format!(".{file_name}.journal"))
+}
+
+/// An open journal that index changes are appended to
+#[derive(Debug, Clone)]
+pub struct Journal {
+    /// Path of the journal file
+    path: PathBuf,
+    /// The journal file, opened for appending
+    file: Arc<Mutex<File>>,
+}
+
+impl Journal {
+    /// Opens the journal for an index file, keeping existing entries
+    pub fn open(index_path: &Path) -> Result<Self> {
+        let path = journal_path(index_path);
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory for {}", path.display()))?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open journal {}", path.display()))?;
+
+        Ok(Self {
+            path,
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// Appends an entry and flushes it to disk
+    pub fn append(&self, entry: &JournalEntry) -> Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())
+            .and_then(|()| file.sync_data())
+            .with_context(|| format!("Failed to write journal {}", self.path.display()))
+    }
+
+    /// Empties the journal after the index was saved in full
+    pub fn truncate(&self) -> Result<()> {
+        let file = self.file.lock().unwrap();
+        file.set_len(0)
+            .with_context(|| format!("Failed to truncate journal {}", self.path.display()))
+    }
+}
+
+/// Reads the journal entries recorded for an index file
+///
+/// A crash can leave a partly written last line behind; it is skipped.
+pub fn read_entries(index_path: &Path) -> Result<Vec<JournalEntry>> {
+    let path = journal_path(index_path);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let contents = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read journal {}", path.display()))?;
+
+    let mut entries = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => warn!(
+                "Skipping unreadable line {} of journal {}: {e}",
+                number + 1,
+                path.display()
+            ),
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Deletes the journal of an index file, if there is one
+pub fn remove(index_path: &Path) -> Result<()> {
+    let path = journal_path(index_path);
+    if path.exists() {
+        fs::remove_file(&path)
+            .with_context(|| format!("Failed to remove journal {}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_journal_path_is_hidden() {
+        assert_eq!(
+            journal_path(Path::new("data/photos/index.yaml")),
+            PathBuf::from("data/photos/.index.yaml.journal")
+        );
+    }
+
+    #[test]
+    fn test_read_entries_skips_partial_line() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let index_path = temp_dir.path().join("index.yaml");
+
+        let journal = Journal::open(&index_path)?;
+        journal.append(&JournalEntry::RemovePhoto {
+            guid: "photo1".to_string(),
+        })?;
+        journal.append(&JournalEntry::RemoveBatch {
+            id: "batch1".to_string(),
+        })?;
+
+        // Simulate a crash in the middle of writing an entry
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(journal_path(&index_path))?;
+        file.write_all(b"{\"op\":\"remove_ph")?;
+
+        let entries = read_entries(&index_path)?;
+        assert_eq!(entries.len(), 2);
+        assert!(matches!(&entries[0], JournalEntry::RemovePhoto { guid } if guid == "photo1"));
+
+        journal.truncate()?;
+        assert!(read_entries(&index_path)?.is_empty());
+
+        Ok(())
+    }
+}
@@ -18,14 +18,34 @@
 
 // Export modules for integration testing
 pub mod api_debug;
+pub mod approval;
+pub mod batch;
+pub mod cache;
 pub mod config;
+pub mod download;
 pub mod exif;
+pub mod filter;
+pub mod frontmatter;
 pub mod gallery;
+pub mod gc;
 pub mod geocode;
+pub mod hashtags;
 pub mod icloud;
+pub mod import;
 pub mod index;
-pub mod mock;
+pub mod journal;
+pub mod migrate;
+pub mod overrides;
+pub mod persist;
+pub mod relocate;
+pub mod rerender;
+pub mod retry;
+pub mod shutdown;
+pub mod source;
+pub mod store;
 pub mod sync;
+pub mod throttle;
+pub mod verify;
 
 #[cfg(test)]
 mod tests {
@@ -47,6 +67,16 @@ mod tests {
         Command::cargo_bin("icloudAlbum2hugo").expect("Failed to find icloudAlbum2hugo binary")
     }
 
+    /// Creates a local photo folder at `<dir>/photos` to sync from
+    fn create_photo_folder(dir: &std::path::Path) -> Result<(), Box<dyn Error>> {
+        let photos_dir = dir.join("photos");
+        fs::create_dir_all(&photos_dir)?;
+        fs::write(photos_dir.join("photo1.jpg"), "PLACEHOLDER IMAGE ONE")?;
+        fs::write(photos_dir.join("photo2.jpg"), "PLACEHOLDER IMAGE TWO")?;
+        fs::write(photos_dir.join("photo1.jpg.yaml"), "caption: First photo\n")?;
+        Ok(())
+    }
+
     #[test]
     fn test_config_generation() -> Result<(), Box<dyn Error>> {
         let temp_dir = TempDir::new()?;
@@ -164,12 +194,13 @@ mod tests {
 fuzz_meters: 100.0
 outputs:
   - output_type: photostream
-    album_url: "https://www.icloud.com/sharedalbum/#test123"
+    album_url: "photos"
     out_dir: "content/photostream"
     data_file: "data/photos/index.yaml"
     enabled: true
 "#;
         fs::write(&config_path, config_content)?;
+        create_photo_folder(temp_dir.path())?;
 
         // Run sync command
         let mut cmd = cargo_bin();
@@ -182,6 +213,7 @@ outputs:
         let stdout = String::from_utf8(output.get_output().stdout.clone())?;
         assert!(stdout.contains("Syncing photos"), "Should mention syncing");
         assert!(stdout.contains("Album URL:"), "Should show album URL");
+        assert!(stdout.contains("Added: 2"), "Should add the local photos");
 
         Ok(())
     }
@@ -196,12 +228,13 @@ outputs:
 fuzz_meters: 100.0
 outputs:
   - output_type: photostream
-    album_url: "https://www.icloud.com/sharedalbum/#test123"
+    album_url: "photos"
     out_dir: "content/photostream"
     data_file: "data/photos/index.yaml"
     enabled: true
 "#;
         fs::write(&config_path, config_content)?;
+        create_photo_folder(temp_dir.path())?;
 
         // Run status command
         let mut cmd = cargo_bin();
@@ -246,15 +279,17 @@ outputs:
 fuzz_meters: 100.0
 outputs:
   - output_type: photostream
-    album_url: "https://www.icloud.com/sharedalbum/#test123"
+    album_url: "{}/photos"
     out_dir: "{}/content/photostream"
     data_file: "{}"
     enabled: true
 "#,
+            temp_dir.path().display(),
             temp_dir.path().display(),
             index_path.display()
         );
         fs::write(&config_path, config_content)?;
+        create_photo_folder(temp_dir.path())?;
 
         // Create a simple index.yaml file
         let index_content = r#"
@@ -330,12 +365,13 @@ galleries: {}
 fuzz_meters: 50.0
 outputs:
   - output_type: photostream
-    album_url: "https://www.icloud.com/sharedalbum/#custom123"
+    album_url: "photos"
     out_dir: "custom/path"
     data_file: "custom/data.yaml"
     enabled: true
 "#;
         fs::write(&custom_path, config_content)?;
+        create_photo_folder(temp_dir.path())?;
 
         // Run sync with custom config
         let mut cmd = cargo_bin();
@@ -343,6 +379,7 @@ outputs:
             .arg("sync")
             .arg("--config")
             .arg(&custom_path)
+            .current_dir(temp_dir.path())
             .assert()
             .success();
 
@@ -356,6 +393,265 @@ outputs:
         Ok(())
     }
 
+    #[test]
+    fn test_offline_sync() -> Result<(), Box<dyn Error>> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("icloudalbums.yaml");
+
+        // Offline syncs need a cache directory
+        fs::write(
+            &config_path,
+            "outputs:\n  - album_url: photos\n    out_dir: content/photostream\n    data_file: data/index.yaml\n",
+        )?;
+        let mut cmd = cargo_bin();
+        cmd.arg("sync")
+            .arg("--offline")
+            .current_dir(temp_dir.path())
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("cache_dir"));
+
+        let config_content = r#"
+cache_dir: "cache"
+outputs:
+  - output_type: photostream
+    album_url: "photos"
+    out_dir: "content/photostream"
+    data_file: "data/index.yaml"
+"#;
+        fs::write(&config_path, config_content)?;
+        create_photo_folder(temp_dir.path())?;
+
+        let mut cmd = cargo_bin();
+        cmd.arg("sync")
+            .current_dir(temp_dir.path())
+            .assert()
+            .success();
+
+        // Offline, every page is re-rendered from disk and nothing is deleted
+        fs::remove_file(temp_dir.path().join("photos").join("photo2.jpg"))?;
+        let mut cmd = cargo_bin();
+        let output = cmd
+            .arg("sync")
+            .arg("--offline")
+            .current_dir(temp_dir.path())
+            .assert()
+            .success();
+
+        let stdout = String::from_utf8(output.get_output().stdout.clone())?;
+        assert!(stdout.contains("Updated: 1"), "Should re-render the photos");
+        assert!(stdout.contains("Deleted: 0"), "Should not delete offline");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_album_keeps_content() -> Result<(), Box<dyn Error>> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("icloudalbums.yaml");
+        let config_content = r#"
+outputs:
+  - output_type: photostream
+    album_url: "photos"
+    out_dir: "content/photostream"
+    data_file: "data/index.yaml"
+"#;
+        fs::write(&config_path, config_content)?;
+        create_photo_folder(temp_dir.path())?;
+
+        let mut cmd = cargo_bin();
+        cmd.arg("sync")
+            .current_dir(temp_dir.path())
+            .assert()
+            .success();
+
+        // An album that suddenly has no photos is not synced
+        for photo in ["photo1.jpg", "photo1.jpg.yaml", "photo2.jpg"] {
+            fs::remove_file(temp_dir.path().join("photos").join(photo))?;
+        }
+        let mut cmd = cargo_bin();
+        cmd.arg("sync")
+            .current_dir(temp_dir.path())
+            .assert()
+            .code(7);
+
+        let index = fs::read_to_string(temp_dir.path().join("data").join("index.yaml"))?;
+        assert!(index.contains("stale:"), "Should mark the output as stale");
+        let bundles = fs::read_dir(temp_dir.path().join("content").join("photostream"))?.count();
+        assert_eq!(bundles, 2, "Should keep the local content");
+
+        // Unless the user confirms it really is empty
+        let mut cmd = cargo_bin();
+        cmd.arg("sync")
+            .arg("--allow-empty")
+            .current_dir(temp_dir.path())
+            .assert()
+            .success();
+
+        let index = fs::read_to_string(temp_dir.path().join("data").join("index.yaml"))?;
+        assert!(!index.contains("stale:"), "Should clear the stale marker");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_and_repair_commands() -> Result<(), Box<dyn Error>> {
+        let temp_dir = TempDir::new()?;
+        let config_content = r#"
+outputs:
+  - output_type: photostream
+    album_url: "photos"
+    out_dir: "content/photostream"
+    data_file: "data/index.yaml"
+"#;
+        fs::write(temp_dir.path().join("icloudalbums.yaml"), config_content)?;
+        create_photo_folder(temp_dir.path())?;
+
+        let mut cmd = cargo_bin();
+        cmd.arg("sync")
+            .current_dir(temp_dir.path())
+            .assert()
+            .success();
+
+        let mut cmd = cargo_bin();
+        cmd.arg("verify")
+            .current_dir(temp_dir.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Everything matches the index"));
+
+        // Delete a photo by hand and leave a stray directory behind
+        let content_dir = temp_dir.path().join("content").join("photostream");
+        let bundle = fs::read_dir(&content_dir)?.next().unwrap()?.path();
+        fs::remove_file(bundle.join("original.jpg"))?;
+        fs::create_dir_all(content_dir.join("stray"))?;
+
+        let mut cmd = cargo_bin();
+        cmd.arg("verify")
+            .current_dir(temp_dir.path())
+            .assert()
+            .failure()
+            .stdout(predicate::str::contains("original.jpg"))
+            .stdout(predicate::str::contains("stray isn't in the index"));
+
+        // Orphans are kept unless the user says otherwise
+        let mut cmd = cargo_bin();
+        cmd.arg("repair")
+            .current_dir(temp_dir.path())
+            .write_stdin("\n")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("downloaded again"))
+            .stdout(predicate::str::contains("kept"));
+        assert!(bundle.join("original.jpg").exists());
+        assert!(content_dir.join("stray").exists());
+
+        let mut cmd = cargo_bin();
+        cmd.arg("repair")
+            .arg("--orphans")
+            .arg("remove")
+            .current_dir(temp_dir.path())
+            .assert()
+            .success();
+        assert!(!content_dir.join("stray").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_command() -> Result<(), Box<dyn Error>> {
+        let temp_dir = TempDir::new()?;
+        let config_content = r#"
+outputs:
+  - output_type: photostream
+    album_url: "photos"
+    out_dir: "content/photostream"
+    data_file: "data/index.yaml"
+"#;
+        fs::write(temp_dir.path().join("icloudalbums.yaml"), config_content)?;
+        create_photo_folder(temp_dir.path())?;
+
+        let mut cmd = cargo_bin();
+        cmd.arg("sync")
+            .current_dir(temp_dir.path())
+            .assert()
+            .success();
+
+        // Lose the index, then rebuild it from the bundles
+        fs::remove_file(temp_dir.path().join("data").join("index.yaml"))?;
+        let mut cmd = cargo_bin();
+        cmd.arg("import")
+            .current_dir(temp_dir.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Imported 2 of 2 bundles"));
+
+        // The next sync has nothing to download
+        let mut cmd = cargo_bin();
+        cmd.arg("sync")
+            .current_dir(temp_dir.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Added: 0"))
+            .stdout(predicate::str::contains("Unchanged: 2"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_paths_are_relative_to_the_site() -> Result<(), Box<dyn Error>> {
+        let temp_dir = TempDir::new()?;
+        let site_dir = temp_dir.path().join("site");
+        fs::create_dir_all(&site_dir)?;
+        create_photo_folder(temp_dir.path())?;
+        let config_content = format!(
+            r#"
+outputs:
+  - output_type: photostream
+    album_url: "{}"
+    out_dir: "content/photostream"
+    data_file: "data/index.yaml"
+"#,
+            temp_dir.path().join("photos").display()
+        );
+        fs::write(site_dir.join("icloudalbums.yaml"), config_content)?;
+
+        // Run from outside the site, pointing at its config
+        let mut cmd = cargo_bin();
+        cmd.arg("sync")
+            .arg("--config")
+            .arg("site/icloudalbums.yaml")
+            .current_dir(temp_dir.path())
+            .assert()
+            .success();
+
+        assert!(site_dir.join("content/photostream").is_dir());
+        assert!(!temp_dir.path().join("content").exists());
+        let index = fs::read_to_string(site_dir.join("data/index.yaml"))?;
+        let local_paths: Vec<&str> = index
+            .lines()
+            .filter(|line| line.trim_start().starts_with("local_path:"))
+            .collect();
+        assert_eq!(local_paths.len(), 2);
+        assert!(
+            local_paths
+                .iter()
+                .all(|line| line.contains("local_path: content/photostream/"))
+        );
+
+        // The index still matches the files when checked from another directory
+        let mut cmd = cargo_bin();
+        cmd.arg("verify")
+            .arg("--config")
+            .arg(site_dir.join("icloudalbums.yaml"))
+            .current_dir(site_dir.join("content"))
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Everything matches the index"));
+
+        Ok(())
+    }
+
     #[test]
     fn test_missing_config_error() -> Result<(), Box<dyn Error>> {
         let temp_dir = TempDir::new()?;
@@ -372,4 +668,264 @@ outputs:
 
         Ok(())
     }
+
+    #[test]
+    fn test_rerender_command() -> Result<(), Box<dyn Error>> {
+        let temp_dir = TempDir::new()?;
+        let config_content = r#"
+outputs:
+  - output_type: photostream
+    album_url: "photos"
+    out_dir: "content/photostream"
+    data_file: "data/index.yaml"
+"#;
+        fs::write(temp_dir.path().join("icloudalbums.yaml"), config_content)?;
+        create_photo_folder(temp_dir.path())?;
+
+        let mut cmd = cargo_bin();
+        cmd.arg("sync")
+            .current_dir(temp_dir.path())
+            .assert()
+            .success();
+
+        // Pages that were edited or deleted are written again from the index
+        let content_dir = temp_dir.path().join("content").join("photostream");
+        let mut bundles = fs::read_dir(&content_dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        bundles.sort();
+        let page = fs::read_to_string(bundles[0].join("index.md"))?;
+        let title = page
+            .lines()
+            .find(|line| line.starts_with("title: "))
+            .unwrap();
+        fs::write(
+            bundles[0].join("index.md"),
+            page.replace(title, "title: Edited"),
+        )?;
+        fs::remove_file(bundles[1].join("index.md"))?;
+
+        let mut cmd = cargo_bin();
+        cmd.arg("rerender")
+            .arg("--refresh-metadata")
+            .current_dir(temp_dir.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Wrote 2 pages"));
+        assert_eq!(fs::read_to_string(bundles[0].join("index.md"))?, page);
+        assert!(bundles[1].join("index.md").exists());
+
+        Ok(())
+    }
+    #[test]
+    fn test_approve_and_reject_commands() -> Result<(), Box<dyn Error>> {
+        let temp_dir = TempDir::new()?;
+        let config_content = r#"
+outputs:
+  - output_type: photostream
+    album_url: "photos"
+    out_dir: "content/photostream"
+    data_file: "data/index.yaml"
+    require_approval: true
+"#;
+        fs::write(temp_dir.path().join("icloudalbums.yaml"), config_content)?;
+        create_photo_folder(temp_dir.path())?;
+
+        let mut cmd = cargo_bin();
+        cmd.arg("sync")
+            .current_dir(temp_dir.path())
+            .assert()
+            .success();
+
+        // New photos are synced as drafts
+        let content_dir = temp_dir.path().join("content").join("photostream");
+        let mut bundles = fs::read_dir(&content_dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        bundles.sort();
+        let guid =
+            |bundle: &std::path::Path| bundle.file_name().unwrap().to_string_lossy().to_string();
+        for bundle in &bundles {
+            assert!(fs::read_to_string(bundle.join("index.md"))?.contains("draft: true"));
+        }
+
+        let mut cmd = cargo_bin();
+        cmd.arg("approve")
+            .arg(guid(&bundles[0]))
+            .current_dir(temp_dir.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("approved"));
+        assert!(!fs::read_to_string(bundles[0].join("index.md"))?.contains("draft"));
+
+        let mut cmd = cargo_bin();
+        cmd.arg("reject")
+            .arg(guid(&bundles[1]))
+            .current_dir(temp_dir.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("rejected"));
+        assert!(!bundles[1].exists());
+
+        // Rejected photos aren't downloaded again
+        let mut cmd = cargo_bin();
+        cmd.arg("sync")
+            .current_dir(temp_dir.path())
+            .assert()
+            .success();
+        assert!(!bundles[1].exists());
+        assert!(bundles[0].exists());
+
+        Ok(())
+    }
+    #[test]
+    fn test_filter_rules() -> Result<(), Box<dyn Error>> {
+        let temp_dir = TempDir::new()?;
+        let config = |filter: &str| {
+            format!(
+                r#"
+outputs:
+  - output_type: photostream
+    album_url: "photos"
+    out_dir: "content/photostream"
+    data_file: "data/index.yaml"
+    filter:
+      {filter}
+"#
+            )
+        };
+        let config_path = temp_dir.path().join("icloudalbums.yaml");
+        fs::write(&config_path, config("caption: \"^First\""))?;
+        create_photo_folder(temp_dir.path())?;
+
+        let mut cmd = cargo_bin();
+        cmd.arg("sync")
+            .current_dir(temp_dir.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(
+                "Filters kept 1 photos and left out 1",
+            ))
+            .stdout(predicate::str::contains("caption: 1"));
+        let content_dir = temp_dir.path().join("content").join("photostream");
+        assert_eq!(fs::read_dir(&content_dir)?.count(), 1);
+
+        // Rules that leave nothing don't delete what was synced
+        fs::write(&config_path, config("caption: \"^Nothing\""))?;
+        let mut cmd = cargo_bin();
+        cmd.arg("sync")
+            .current_dir(temp_dir.path())
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("The filter rules left no photos"));
+        assert_eq!(fs::read_dir(&content_dir)?.count(), 1);
+
+        // Neither do rules that delete more synced photos than allowed
+        fs::write(
+            &config_path,
+            config("exclude_caption: \"^First\"\n      max_deletions: 0"),
+        )?;
+        let mut cmd = cargo_bin();
+        cmd.arg("sync")
+            .current_dir(temp_dir.path())
+            .assert()
+            .code(8)
+            .stderr(predicate::str::contains(
+                "would delete 1 synced photos, more than the 0 allowed",
+            ));
+        assert_eq!(fs::read_dir(&content_dir)?.count(), 1);
+
+        let mut cmd = cargo_bin();
+        cmd.arg("sync")
+            .arg("--allow-deletions")
+            .current_dir(temp_dir.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Deleted: 1"));
+        assert_eq!(fs::read_dir(&content_dir)?.count(), 1);
+
+        // The placeholder images have no coordinates
+        fs::write(&config_path, config("has_gps: true"))?;
+        let mut cmd = cargo_bin();
+        cmd.arg("sync")
+            .current_dir(temp_dir.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(
+                "Left out 2 photos that don't pass the GPS rule",
+            ));
+        assert_eq!(fs::read_dir(&content_dir)?.count(), 0);
+
+        // They aren't downloaded again while the rule holds
+        let mut cmd = cargo_bin();
+        cmd.arg("sync")
+            .current_dir(temp_dir.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("GPS coordinates: 2"))
+            .stdout(predicate::str::contains("Added: 0"));
+        assert_eq!(fs::read_dir(&content_dir)?.count(), 0);
+
+        // And come back once it's relaxed
+        fs::write(&config_path, config("has_gps: false"))?;
+        let mut cmd = cargo_bin();
+        cmd.arg("sync")
+            .current_dir(temp_dir.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Added: 2"));
+        assert_eq!(fs::read_dir(&content_dir)?.count(), 2);
+        let index = fs::read_to_string(temp_dir.path().join("data").join("index.yaml"))?;
+        assert!(!index.contains("filtered:"));
+
+        Ok(())
+    }
+    #[test]
+    fn test_hashtags_become_tags() -> Result<(), Box<dyn Error>> {
+        let temp_dir = TempDir::new()?;
+        let config_content = r#"
+outputs:
+  - output_type: photostream
+    album_url: "photos"
+    out_dir: "content/photostream"
+    data_file: "data/index.yaml"
+    hashtags:
+      strip_from_caption: true
+      term_pages: "content/tags"
+"#;
+        fs::write(temp_dir.path().join("icloudalbums.yaml"), config_content)?;
+        create_photo_folder(temp_dir.path())?;
+        fs::write(
+            temp_dir.path().join("photos").join("photo1.jpg.yaml"),
+            "caption: \"First photo #summer #Kids\"\n",
+        )?;
+
+        let mut cmd = cargo_bin();
+        cmd.arg("sync")
+            .current_dir(temp_dir.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Wrote term pages for 2 hashtags"));
+
+        let index = fs::read_to_string(temp_dir.path().join("data").join("index.yaml"))?;
+        assert!(index.contains("tags:\n    - summer\n    - kids\n"));
+
+        let page = fs::read_dir(temp_dir.path().join("content").join("photostream"))?
+            .map(|entry| entry.map(|entry| entry.path().join("index.md")))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .map(fs::read_to_string)
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .find(|page| page.contains("First photo"))
+            .expect("page of the photo with a caption");
+        assert!(page.contains("tags:\n  - \"summer\"\n  - \"kids\"\n"));
+        assert!(!page.contains("#summer"));
+
+        let tags_dir = temp_dir.path().join("content").join("tags");
+        assert!(tags_dir.join("summer").join("_index.md").exists());
+        assert!(tags_dir.join("kids").join("_index.md").exists());
+
+        Ok(())
+    }
 }
@@ -16,24 +16,55 @@
 //! - Maintains a master YAML index file
 
 mod api_debug;
+mod approval;
+mod batch;
+mod cache;
 mod config;
+mod download;
 mod exif;
+mod filter;
+mod frontmatter;
 mod gallery;
+mod gc;
 mod geocode;
+mod hashtags;
 mod icloud;
+mod import;
 mod index;
-mod mock;
+mod journal;
+mod migrate;
+mod overrides;
+mod persist;
+mod relocate;
+mod rerender;
+mod retry;
+mod shutdown;
+mod source;
+mod store;
 mod sync;
+mod throttle;
+mod verify;
 
 use anyhow::{Context, Result};
+use approval::Decision;
+use batch::BatchSyncer;
 use clap::{Parser, Subcommand};
 use config::{Config, OutputType};
+use filter::PhotoFilter;
 use gallery::GallerySyncer;
-use icloud::fetch_album;
+use icloud::ICloudError;
 use log::{debug, error, info, warn};
+use overrides::Overrides;
+use retry::RetryPolicy;
+use shutdown::{INTERRUPTED_EXIT_CODE, Shutdown};
+use source::{FetchOptions, fetch_albums};
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use store::IndexStore;
 use sync::Syncer;
+use verify::OrphanAction;
 
 // Helper function to both log a message and print it to the console for user feedback
 fn console_log(message: &str, level: log::Level) {
@@ -94,6 +125,20 @@ enum Commands {
         /// Only process outputs with these names
         #[arg(short, long)]
         output: Option<Vec<String>>,
+
+        /// Rebuild albums from the latest saved responses in the cache
+        /// directory and re-render pages from files already on disk
+        #[arg(long)]
+        offline: bool,
+
+        /// Sync albums that return no photos, deleting all local content
+        #[arg(long)]
+        allow_empty: bool,
+
+        /// Sync even when the filter rules would delete more photos than
+        /// their max_deletions
+        #[arg(long)]
+        allow_deletions: bool,
     },
 
     /// Show status of photos
@@ -106,6 +151,137 @@ enum Commands {
         #[arg(short, long)]
         output: Option<Vec<String>>,
     },
+
+    /// Move an output's page bundles to a new output directory
+    Relocate {
+        /// Path to config file
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+
+        /// Name of the output to move
+        #[arg(short, long)]
+        output: String,
+
+        /// New output directory
+        #[arg(long, value_name = "DIR")]
+        to: String,
+    },
+
+    /// Upgrade data files to the current schema version
+    Migrate {
+        /// Path to config file
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+
+        /// Only migrate outputs with these names
+        #[arg(short, long)]
+        output: Option<Vec<String>>,
+    },
+
+    /// Rebuild the index of photostream outputs from their existing page bundles
+    Import {
+        /// Path to config file
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+
+        /// Only import outputs with these names
+        #[arg(short, long)]
+        output: Option<Vec<String>>,
+    },
+
+    /// Drop photos and galleries the outputs no longer use
+    Gc {
+        /// Path to config file
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+
+        /// Only collect outputs with these names
+        #[arg(short, long)]
+        output: Option<Vec<String>>,
+
+        /// Only report what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Write every page again from the index, without downloading
+    Rerender {
+        /// Path to config file
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+
+        /// Only rerender outputs with these names
+        #[arg(short, long)]
+        output: Option<Vec<String>>,
+
+        /// Read EXIF data from the files on disk and geocode it again first
+        #[arg(long)]
+        refresh_metadata: bool,
+    },
+
+    /// Check page bundles against the index
+    Verify {
+        /// Path to config file
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+
+        /// Only verify outputs with these names
+        #[arg(short, long)]
+        output: Option<Vec<String>>,
+    },
+
+    /// Fix the problems found by verify
+    Repair {
+        /// Path to config file
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+
+        /// Only repair outputs with these names
+        #[arg(short, long)]
+        output: Option<Vec<String>>,
+
+        /// What to do with files the index doesn't know about, instead of asking
+        #[arg(long, value_enum)]
+        orphans: Option<OrphanAction>,
+    },
+
+    /// Publish photos that are waiting for approval
+    Approve {
+        /// Path to config file
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+
+        /// Only approve photos in outputs with these names
+        #[arg(short, long)]
+        output: Option<Vec<String>>,
+
+        /// GUIDs of the photos to approve
+        #[arg(required_unless_present = "all_before")]
+        guids: Vec<String>,
+
+        /// Approve every waiting photo created before this date
+        #[arg(long, value_name = "DATE", value_parser = parse_date_arg)]
+        all_before: Option<chrono::DateTime<chrono::Utc>>,
+    },
+
+    /// Keep photos off the site for good and delete their files
+    Reject {
+        /// Path to config file
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+
+        /// Only reject photos in outputs with these names
+        #[arg(short, long)]
+        output: Option<Vec<String>>,
+
+        /// GUIDs of the photos to reject
+        #[arg(required_unless_present = "all_before")]
+        guids: Vec<String>,
+
+        /// Reject every waiting photo created before this date
+        #[arg(long, value_name = "DATE", value_parser = parse_date_arg)]
+        all_before: Option<chrono::DateTime<chrono::Utc>>,
+    },
 }
 
 #[tokio::main]
@@ -125,10 +301,24 @@ async fn main() -> Result<()> {
             init_config(config, *force).context("Failed to initialize configuration")?;
             Ok(())
         }
-        Commands::Sync { config, output } => {
+        Commands::Sync {
+            config,
+            output,
+            offline,
+            allow_empty,
+            allow_deletions,
+        } => {
             // ------- LOAD CONFIGURATION -------
             let config_data = load_config(config).context("Failed to load configuration")?;
 
+            if *offline && config_data.cache_dir.is_none() {
+                anyhow::bail!(
+                    "Offline sync needs saved album responses. Set cache_dir in the config and run a normal sync first."
+                );
+            }
+            let response_cache = config_data.response_cache();
+            let overrides = Arc::new(config_data.overrides()?);
+
             println!("┌─────────────────────────────────────────────┐");
             println!("│        icloudAlbum2hugo Photo Sync         │");
             println!("└─────────────────────────────────────────────┘");
@@ -153,6 +343,22 @@ async fn main() -> Result<()> {
                 "  • Location fuzz amount: {:?} meters",
                 config_data.fuzz_meters
             );
+            if let Some(cache_dir) = &config_data.cache_dir {
+                println!("  • Response cache: {cache_dir}");
+            }
+            if let Some(overrides_file) = &config_data.overrides_file {
+                println!("  • Photo overrides: {overrides_file}");
+            }
+            if *offline {
+                println!("  • Offline: re-rendering from saved responses and files on disk");
+            }
+
+            // Exit code of the first output that went stale or couldn't be loaded
+            let mut exit_code = None;
+
+            // The first Ctrl-C lets photos in progress finish and saves the index
+            let shutdown = Shutdown::new();
+            shutdown.listen_for_ctrl_c();
 
             // Process each output
             for output_config in outputs_to_process {
@@ -163,40 +369,96 @@ async fn main() -> Result<()> {
                         .unwrap_or(match output_config.output_type {
                             OutputType::Photostream => "Photostream",
                             OutputType::Gallery => "Gallery",
+                            OutputType::Batches => "Batches",
                         });
 
                 println!("\n━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
                 println!("🔄 Processing output: {output_name}");
                 println!("  • Type: {:?}", output_config.output_type);
-                println!("  • Album URL: {}", output_config.album_url);
+                for album_url in output_config.album_sources() {
+                    println!("  • Album URL: {album_url}");
+                }
                 println!("  • Output directory: {}", output_config.out_dir);
                 println!("  • Data file: {}", output_config.data_file);
 
+                let retry_policy = RetryPolicy::from_config(&output_config.retry);
+                let photo_filter = match PhotoFilter::new(&output_config.filter) {
+                    Ok(photo_filter) => photo_filter,
+                    Err(err) => {
+                        eprintln!("  ⚠️  Error: {err:#}");
+                        println!("  ℹ️  Skipping this output and continuing with others");
+                        exit_code.get_or_insert(1);
+                        continue;
+                    }
+                };
+
                 // ------- LOAD PHOTO INDEX -------
-                let data_file_path = PathBuf::from(&output_config.data_file);
+                let data_file_path = config_data.data_file(output_config);
+                let index_store = match config_data.index_store(output_config) {
+                    Ok(store) => store,
+                    Err(err) => {
+                        eprintln!("  ⚠️  Error: Could not open photo index: {err:#}");
+                        println!("  ℹ️  Skipping this output and continuing with others");
+                        exit_code.get_or_insert(1);
+                        continue;
+                    }
+                };
                 println!(
                     "\n📂 Loading photo index from {}...",
-                    data_file_path.display()
+                    index_store.describe()
                 );
 
-                let mut photo_index = match index::PhotoIndex::load(&data_file_path) {
+                let mut photo_index = match index_store.load() {
                     Ok(index) => {
+                        if let Some(backup) = index.recovered_from() {
+                            eprintln!(
+                                "  ⚠️  Warning: {} is damaged, loaded the backup at {} instead",
+                                data_file_path.display(),
+                                backup.display()
+                            );
+                        }
                         println!("  • Photo index loaded with {} photos", index.photo_count());
                         if index.gallery_count() > 0 {
                             println!("  • Index contains {} galleries", index.gallery_count());
                         }
+                        if index.batch_count() > 0 {
+                            println!("  • Index contains {} batches", index.batch_count());
+                        }
                         index
                     }
                     Err(err) => {
-                        eprintln!("  ⚠️  Warning: Could not load photo index: {err}");
-                        println!("  ℹ️  Creating new empty index");
-                        index::PhotoIndex::new()
+                        // Starting over with an empty index would download everything again
+                        eprintln!("  ⚠️  Error: Could not load photo index: {err:#}");
+                        println!("  ℹ️  Fix or remove the data file to sync this output again");
+                        println!("  ℹ️  Skipping this output and continuing with others");
+                        exit_code.get_or_insert(1);
+                        continue;
                     }
                 };
 
+                let loaded_at = photo_index.last_updated;
+
+                // Record every finished photo right away, so an interrupted
+                // sync can resume where it stopped
+                if let Err(err) = photo_index.open_journal(&data_file_path) {
+                    eprintln!("  ⚠️  Warning: Could not open index journal: {err:#}");
+                    println!("  ℹ️  An interrupted sync will have to start over");
+                }
+
                 // ------- FETCH ALBUM DATA -------
-                println!("\n🔄 Fetching album data from iCloud...");
-                let album = match fetch_album(&output_config.album_url).await {
+                println!("\n🔄 Fetching album data...");
+                let fetch_options = FetchOptions {
+                    index: output_config.two_phase_fetch.then_some(&photo_index),
+                    cache: response_cache.as_ref(),
+                    offline: *offline,
+                };
+                let mut album = match fetch_albums(
+                    &output_config.album_sources(),
+                    &retry_policy,
+                    fetch_options,
+                )
+                .await
+                {
                     Ok(album) => {
                         println!(
                             "  • Album '{}' fetched with {} photos",
@@ -206,21 +468,90 @@ async fn main() -> Result<()> {
                         album
                     }
                     Err(err) => {
-                        eprintln!("  ⚠️  Error: Failed to fetch album data: {err}");
+                        eprintln!("  ⚠️  Error: Failed to fetch album data: {err:#}");
+                        if let Some(reason) = StaleReason::from_error(&err) {
+                            mark_output_stale(&mut photo_index, index_store.as_ref(), reason, &err);
+                            exit_code.get_or_insert(reason.exit_code());
+                        }
                         println!("  ℹ️  Skipping this output and continuing with others");
                         continue;
                     }
                 };
 
+                let album_was_empty = album.photos.is_empty();
+                if !photo_filter.is_empty() {
+                    let report = photo_filter.apply(&mut album, &photo_index);
+                    print_filter_report(&report);
+
+                    // Rules that suddenly leave out many synced photos were
+                    // most likely mistyped
+                    let max_deletions = photo_filter.max_deletions();
+                    if report.deletions > max_deletions && !*allow_deletions {
+                        let reason = StaleReason::TooManyDeletions;
+                        let err = anyhow::anyhow!(
+                            "The filter rules would delete {} synced photos, more than the {max_deletions} allowed",
+                            report.deletions
+                        );
+                        eprintln!("  ⚠️  Error: {err}");
+                        println!("  ℹ️  Run sync with --allow-deletions if the rules are right");
+                        mark_output_stale(&mut photo_index, index_store.as_ref(), reason, &err);
+                        exit_code.get_or_insert(reason.exit_code());
+                        println!("  ℹ️  Skipping this output and continuing with others");
+                        continue;
+                    }
+                }
+
+                // An album that suddenly has no photos was most likely unshared,
+                // and filters that leave nothing were most likely mistyped;
+                // syncing either would delete everything
+                if album.photos.is_empty() && photo_index.photo_count() > 0 && !*allow_empty {
+                    let reason = StaleReason::EmptyAlbum;
+                    let err = if album_was_empty {
+                        anyhow::anyhow!(
+                            "Album returned no photos, but the index has {}",
+                            photo_index.photo_count()
+                        )
+                    } else {
+                        anyhow::anyhow!(
+                            "The filter rules left no photos, but the index has {}",
+                            photo_index.photo_count()
+                        )
+                    };
+                    eprintln!("  ⚠️  Error: {err}");
+                    println!("  ℹ️  Run sync with --allow-empty if the output really is empty");
+                    mark_output_stale(&mut photo_index, index_store.as_ref(), reason, &err);
+                    exit_code.get_or_insert(reason.exit_code());
+                    println!("  ℹ️  Skipping this output and continuing with others");
+                    continue;
+                }
+                photo_index.clear_stale();
+
                 // ------- PREPARE FOR SYNC -------
-                let content_dir = PathBuf::from(&output_config.out_dir);
+                let content_dir = config_data.content_dir(output_config);
+                let output_overrides = output_overrides(&overrides, output_config);
+                let download_config = output_config.downloads.or(&config_data.downloads);
+                match download_config.max_bytes_per_second {
+                    Some(rate) => println!(
+                        "  • Downloads: {} at a time, capped at {rate} bytes/s",
+                        download_config.concurrency()
+                    ),
+                    None => println!("  • Downloads: {} at a time", download_config.concurrency()),
+                }
 
                 // Process according to output type
                 let results = match output_config.output_type {
                     OutputType::Photostream => {
                         // Use the existing Syncer for photostream
                         println!("\n📷 Syncing photos to photostream...");
-                        let syncer = Syncer::new(content_dir, data_file_path.clone());
+                        let syncer = Syncer::new(content_dir, data_file_path.clone())
+                            .with_retry_policy(retry_policy)
+                            .with_download_config(&download_config)
+                            .with_offline(*offline)
+                            .with_shutdown(shutdown.clone())
+                            .with_index_store(index_store.clone())
+                            .with_overrides(output_overrides.clone())
+                            .with_require_approval(output_config.require_approval)
+                            .with_site_root(config_data.site_root().to_path_buf());
                         syncer
                             .sync_photos(&album, &mut photo_index)
                             .await
@@ -235,17 +566,47 @@ async fn main() -> Result<()> {
                             output_config.description.clone(),
                             data_file_path.clone(),
                             output_config.privacy.clone(),
-                        );
+                        )
+                        .with_retry_policy(retry_policy)
+                        .with_download_config(&download_config)
+                        .with_offline(*offline)
+                        .with_shutdown(shutdown.clone())
+                        .with_index_store(index_store.clone())
+                        .with_overrides(output_overrides.clone())
+                        .with_require_approval(output_config.require_approval)
+                        .with_site_root(config_data.site_root().to_path_buf());
                         gallery_syncer
                             .sync_gallery(&album, &mut photo_index)
                             .await
                             .context("Failed to sync gallery")?
                     }
+                    OutputType::Batches => {
+                        // One page bundle per upload batch
+                        println!("\n🗂️  Syncing upload batches...");
+                        let batch_syncer = BatchSyncer::new(
+                            content_dir,
+                            data_file_path.clone(),
+                            output_config.privacy.clone(),
+                        )
+                        .with_retry_policy(retry_policy)
+                        .with_download_config(&download_config)
+                        .with_offline(*offline)
+                        .with_shutdown(shutdown.clone())
+                        .with_index_store(index_store.clone())
+                        .with_overrides(output_overrides.clone())
+                        .with_require_approval(output_config.require_approval)
+                        .with_site_root(config_data.site_root().to_path_buf());
+                        batch_syncer
+                            .sync_batches(&album, &mut photo_index)
+                            .await
+                            .context("Failed to sync batches")?
+                    }
                 };
 
                 // ------- COUNT RESULTS -------
                 let mut added = 0;
                 let mut updated = 0;
+                let mut metadata_updated = 0;
                 let mut unchanged = 0;
                 let mut deleted = 0;
                 let mut failed = 0;
@@ -254,6 +615,7 @@ async fn main() -> Result<()> {
                     match result {
                         sync::SyncResult::Added(_) => added += 1,
                         sync::SyncResult::Updated(_) => updated += 1,
+                        sync::SyncResult::MetadataUpdated(_) => metadata_updated += 1,
                         sync::SyncResult::Unchanged(_) => unchanged += 1,
                         sync::SyncResult::Deleted(_) => deleted += 1,
                         sync::SyncResult::Failed(guid, error) => {
@@ -263,12 +625,49 @@ async fn main() -> Result<()> {
                     }
                 }
 
+                // ------- APPLY GPS RULE -------
+                // Coordinates are only known once a photo's file was read
+                let removal = photo_filter.remove_gps_rejects(
+                    &output_config.output_type,
+                    &config_data.content_dir(output_config),
+                    config_data.site_root(),
+                    &mut photo_index,
+                );
+                if !removal.removed.is_empty() {
+                    println!(
+                        "\n📍 Left out {} photos that don't pass the GPS rule",
+                        removal.removed.len()
+                    );
+                    for (guid, error) in &removal.failures {
+                        eprintln!("  ⚠️  Failed to delete files of photo {guid}: {error}");
+                        failed += 1;
+                    }
+                    let renderer = output_renderer(&config_data, output_config, &overrides);
+                    for page in &removal.pages {
+                        if let Err(e) = renderer.render(&photo_index, page).await {
+                            eprintln!("  ⚠️  Failed to render {page}: {e:#}");
+                            failed += 1;
+                        }
+                    }
+                }
+
+                // ------- WRITE TERM PAGES -------
+                if let Some(dir) = term_pages_dir(&config_data, output_config) {
+                    match hashtags::write_term_pages(&dir, &photo_index, &output_overrides).await {
+                        Ok(count) => println!("\n🏷️  Wrote term pages for {count} hashtags"),
+                        Err(err) => {
+                            eprintln!("  ⚠️  Failed to write term pages: {err:#}");
+                            failed += 1;
+                        }
+                    }
+                }
+
                 // ------- SAVE UPDATED INDEX -------
-                println!("\n💾 Saving photo index to {}...", data_file_path.display());
-                match photo_index.save(&data_file_path) {
+                println!("\n💾 Saving photo index to {}...", index_store.describe());
+                match save_index(&photo_index, index_store.as_ref(), loaded_at) {
                     Ok(_) => println!("  • Photo index saved successfully"),
                     Err(err) => {
-                        eprintln!("  ⚠️  Warning: Failed to save photo index: {err}");
+                        eprintln!("  ⚠️  Warning: Failed to save photo index: {err:#}");
                         eprintln!(
                             "  ℹ️  Your changes have been applied but not saved to the index file"
                         );
@@ -279,12 +678,24 @@ async fn main() -> Result<()> {
                 println!("\n✅ Sync completed for {output_name}");
                 println!("  • Added: {added}");
                 println!("  • Updated: {updated}");
+                println!("  • Metadata updated: {metadata_updated}");
                 println!("  • Unchanged: {unchanged}");
                 println!("  • Deleted: {deleted}");
                 if failed > 0 {
                     println!("  • Failed: {failed} (see warnings above)");
                 }
                 println!("  • Total photos in index: {}", photo_index.photo_count());
+
+                if shutdown.is_requested() {
+                    println!("\n⏸️  Sync interrupted, progress so far was saved");
+                    println!("  ℹ️  Run sync again to pick up where it stopped");
+                    std::process::exit(INTERRUPTED_EXIT_CODE);
+                }
+            }
+
+            if let Some(code) = exit_code {
+                println!("\n⚠️  Some outputs couldn't be synced, their local content was kept");
+                std::process::exit(code);
             }
 
             println!("\n🎉 All outputs processed successfully!");
@@ -293,6 +704,7 @@ async fn main() -> Result<()> {
         Commands::Status { config, output } => {
             // ------- LOAD CONFIGURATION -------
             let config_data = load_config(config).context("Failed to load configuration")?;
+            let response_cache = config_data.response_cache();
 
             println!("┌─────────────────────────────────────────────┐");
             println!("│            icloudAlbum2hugo Status          │");
@@ -325,27 +737,55 @@ async fn main() -> Result<()> {
                         .unwrap_or(match output_config.output_type {
                             OutputType::Photostream => "Photostream",
                             OutputType::Gallery => "Gallery",
+                            OutputType::Batches => "Batches",
                         });
 
                 println!("\n━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
                 println!("🔍 Checking output: {output_name}");
                 println!("  • Type: {:?}", output_config.output_type);
-                println!("  • Album URL: {}", output_config.album_url);
+                for album_url in output_config.album_sources() {
+                    println!("  • Album URL: {album_url}");
+                }
                 println!("  • Output directory: {}", output_config.out_dir);
                 println!("  • Data file: {}", output_config.data_file);
 
+                let retry_policy = RetryPolicy::from_config(&output_config.retry);
+
                 // ------- LOAD PHOTO INDEX -------
-                let data_file_path = PathBuf::from(&output_config.data_file);
-                println!(
-                    "\n📂 Loading photo index from {}...",
-                    data_file_path.display()
-                );
-                let photo_index = match index::PhotoIndex::load(&data_file_path) {
+                let data_file_path = config_data.data_file(output_config);
+                let loaded = config_data
+                    .index_store(output_config)
+                    .and_then(|index_store| {
+                        println!(
+                            "\n📂 Loading photo index from {}...",
+                            index_store.describe()
+                        );
+                        index_store.load()
+                    });
+                let photo_index = match loaded {
                     Ok(index) => {
+                        if let Some(backup) = index.recovered_from() {
+                            eprintln!(
+                                "  ⚠️  Warning: {} is damaged, loaded the backup at {} instead",
+                                data_file_path.display(),
+                                backup.display()
+                            );
+                        }
                         println!("  • Photo index loaded with {} photos", index.photo_count());
                         if index.gallery_count() > 0 {
                             println!("  • Index contains {} galleries", index.gallery_count());
                         }
+                        if index.batch_count() > 0 {
+                            println!("  • Index contains {} batches", index.batch_count());
+                        }
+                        let pending = index
+                            .photos
+                            .values()
+                            .filter(|photo| photo.approval == Some(index::Approval::Pending))
+                            .count();
+                        if pending > 0 {
+                            println!("  • {pending} photos are waiting for approval");
+                        }
                         index
                     }
                     Err(err) => {
@@ -356,6 +796,13 @@ async fn main() -> Result<()> {
                 };
 
                 // ------- DISPLAY LOCAL INDEX STATS -------
+                if let Some(stale) = &photo_index.stale {
+                    println!(
+                        "  ⚠️  Output is stale since {}: {}",
+                        stale.since, stale.reason
+                    );
+                }
+
                 if photo_index.photo_count() > 0 {
                     println!("  • Last updated: {}", photo_index.last_updated);
 
@@ -393,23 +840,46 @@ async fn main() -> Result<()> {
                     );
 
                     // Show gallery info if this is a gallery output
-                    if let OutputType::Gallery = output_config.output_type {
-                        if photo_index.gallery_count() > 0 {
-                            println!("\n🖼️  Gallery Information:");
-                            for gallery in photo_index.galleries.values() {
-                                println!(
-                                    "  • Gallery '{}' contains {} photos",
-                                    gallery.name,
-                                    gallery.photos.len()
-                                );
-                            }
+                    if let OutputType::Gallery = output_config.output_type
+                        && photo_index.gallery_count() > 0
+                    {
+                        println!("\n🖼️  Gallery Information:");
+                        for gallery in photo_index.galleries.values() {
+                            println!(
+                                "  • Gallery '{}' contains {} photos",
+                                gallery.name,
+                                gallery.photos.len()
+                            );
                         }
                     }
+
+                    // Show batch info if this is a batches output
+                    if let OutputType::Batches = output_config.output_type
+                        && photo_index.batch_count() > 0
+                    {
+                        println!("\n🗂️  Batch Information:");
+                        println!(
+                            "  • {} upload batches with {} photos",
+                            photo_index.batch_count(),
+                            photo_index.photo_count()
+                        );
+                    }
                 }
 
                 // ------- FETCH REMOTE ALBUM DATA -------
-                println!("\n🔄 Fetching album data from iCloud...");
-                let album = match fetch_album(&output_config.album_url).await {
+                println!("\n🔄 Fetching album data...");
+                let fetch_options = FetchOptions {
+                    index: output_config.two_phase_fetch.then_some(&photo_index),
+                    cache: response_cache.as_ref(),
+                    offline: false,
+                };
+                let album = match fetch_albums(
+                    &output_config.album_sources(),
+                    &retry_policy,
+                    fetch_options,
+                )
+                .await
+                {
                     Ok(album) => {
                         println!(
                             "  • Album '{}' fetched with {} photos",
@@ -419,7 +889,7 @@ async fn main() -> Result<()> {
                         Some(album)
                     }
                     Err(err) => {
-                        eprintln!("  ⚠️  Warning: Could not fetch album: {err}");
+                        eprintln!("  ⚠️  Warning: Could not fetch album: {err:#}");
                         eprintln!("    Error details: {err}");
                         println!("  ℹ️  Status will only show local information");
                         None
@@ -427,7 +897,16 @@ async fn main() -> Result<()> {
                 };
 
                 // ------- COMPARE LOCAL AND REMOTE DATA -------
-                if let Some(album) = album {
+                if let Some(mut album) = album {
+                    match PhotoFilter::new(&output_config.filter) {
+                        Ok(photo_filter) if !photo_filter.is_empty() => {
+                            let report = photo_filter.apply(&mut album, &photo_index);
+                            print_filter_report(&report);
+                        }
+                        Ok(_) => {}
+                        Err(err) => eprintln!("  ⚠️  Warning: {err:#}"),
+                    }
+
                     // Get the set of photo IDs from both sources
                     let remote_ids: std::collections::HashSet<&String> =
                         album.photos.keys().collect();
@@ -442,6 +921,7 @@ async fn main() -> Result<()> {
                     // Count potential updates by comparing checksums
                     let mut update_count = 0;
                     let mut updated_ids = Vec::new();
+                    let mut metadata_update_count = 0;
                     for &&id in &common_ids {
                         let remote_photo = album
                             .photos
@@ -455,6 +935,8 @@ async fn main() -> Result<()> {
                         if remote_photo.checksum != local_photo.checksum {
                             update_count += 1;
                             updated_ids.push(id);
+                        } else if local_photo.metadata_differs(remote_photo) {
+                            metadata_update_count += 1;
                         }
                     }
 
@@ -462,9 +944,13 @@ async fn main() -> Result<()> {
                     println!("\n📊 Status Summary for {output_name}:");
                     println!("  • Local photos: {}", photo_index.photos.len());
                     println!("  • Remote photos: {}", album.photos.len());
-                    println!("  • Photos in sync: {}", common_ids.len() - update_count);
+                    println!(
+                        "  • Photos in sync: {}",
+                        common_ids.len() - update_count - metadata_update_count
+                    );
                     println!("  • New photos to download: {}", new_ids.len());
                     println!("  • Photos to update: {update_count}");
+                    println!("  • Photos with metadata changes: {metadata_update_count}");
                     println!("  • Photos to remove: {}", removed_ids.len());
 
                     // Show detailed information if requested
@@ -529,14 +1015,17 @@ async fn main() -> Result<()> {
 
                     // ------- PROVIDE RECOMMENDATIONS -------
                     println!("\n📋 Suggested Actions:");
-                    if new_ids.is_empty() && update_count == 0 && removed_ids.is_empty() {
+                    if new_ids.is_empty()
+                        && update_count == 0
+                        && metadata_update_count == 0
+                        && removed_ids.is_empty()
+                    {
                         println!("  ✅ Everything is up to date! No action needed.");
                     } else {
                         println!("  • Run 'icloudAlbum2hugo sync' to update your local files");
-                        if output_config.name.is_some() {
+                        if let Some(name) = &output_config.name {
                             println!(
-                                "  • To update only this output: icloudAlbum2hugo sync -o \"{}\"",
-                                output_config.name.as_ref().unwrap()
+                                "  • To update only this output: icloudAlbum2hugo sync -o \"{name}\""
                             );
                         }
                     }
@@ -556,7 +1045,601 @@ async fn main() -> Result<()> {
             println!("\n🎉 Status check completed for all outputs!");
             Ok(())
         }
+        Commands::Relocate { config, output, to } => {
+            let config_path = Config::get_config_path(config);
+            let mut config_data = load_config(config).context("Failed to load configuration")?;
+
+            let Some(position) = config_data
+                .outputs
+                .iter()
+                .position(|o| o.name.as_deref() == Some(output.as_str()))
+            else {
+                anyhow::bail!("No output named '{output}' in {}", config_path.display());
+            };
+            let output_config = &config_data.outputs[position];
+
+            // The new directory is given relative to where the tool runs, and
+            // is stored relative to the site root
+            let from = config_data.content_dir(output_config);
+            let to_dir =
+                std::path::absolute(to).with_context(|| format!("Failed to resolve {to}"))?;
+            let out_dir = index::relative_to(&to_dir, config_data.site_root())
+                .to_string_lossy()
+                .to_string();
+            println!(
+                "📦 Moving '{output}' from {} to {out_dir}...",
+                output_config.out_dir
+            );
+            let index_store = config_data.index_store(output_config)?;
+            let rebased = relocate::relocate_output(
+                &from,
+                &to_dir,
+                config_data.site_root(),
+                index_store.as_ref(),
+            )
+            .with_context(|| format!("Failed to relocate output '{output}'"))?;
+            println!(
+                "  • Updated the paths of {rebased} photos in {}",
+                index_store.describe()
+            );
+
+            println!("  • Set out_dir to {out_dir} in {}", config_path.display());
+            config_data.outputs[position].out_dir = out_dir;
+            config_data
+                .save_to_file(&config_path)
+                .context("Failed to update the configuration")?;
+
+            Ok(())
+        }
+        Commands::Migrate { config, output } => {
+            let config_data = load_config(config).context("Failed to load configuration")?;
+
+            let outputs_to_migrate = match &output {
+                Some(names) => config_data.get_outputs_by_name(names),
+                None => config_data.outputs.iter().collect(),
+            };
+
+            if outputs_to_migrate.is_empty() {
+                println!("⚠️  No outputs found to migrate. Check your configuration.");
+                return Ok(());
+            }
+
+            println!(
+                "🔧 Migrating data files to schema version {}",
+                migrate::CURRENT_SCHEMA_VERSION
+            );
+
+            let mut failed = false;
+            for output_config in outputs_to_migrate {
+                let data_file_path = config_data.data_file(output_config);
+
+                // Indexes in the database are migrated as they're loaded
+                if config_data.index_database.is_some() {
+                    let migrated = config_data.index_store(output_config).and_then(|store| {
+                        let index = store.load()?;
+                        store.save(&index)?;
+                        store.export(&index)
+                    });
+                    match migrated {
+                        Ok(()) => println!("  • {}: up to date", data_file_path.display()),
+                        Err(err) => {
+                            eprintln!("  ⚠️  {}: {err:#}", data_file_path.display());
+                            failed = true;
+                        }
+                    }
+                    continue;
+                }
+
+                if !data_file_path.exists() {
+                    println!("  • {}: no data file yet", data_file_path.display());
+                    continue;
+                }
+
+                match migrate::migrate_file(&data_file_path, config_data.site_root()) {
+                    Ok(migrate::MigrationReport {
+                        backup: Some(backup),
+                        from,
+                    }) => println!(
+                        "  • {}: migrated from schema version {from}, old version kept at {}",
+                        data_file_path.display(),
+                        backup.display()
+                    ),
+                    Ok(_) => println!("  • {}: already up to date", data_file_path.display()),
+                    Err(err) => {
+                        eprintln!("  ⚠️  {}: {err:#}", data_file_path.display());
+                        failed = true;
+                    }
+                }
+            }
+
+            if failed {
+                anyhow::bail!("Some data files could not be migrated");
+            }
+            Ok(())
+        }
+        Commands::Import { config, output } => {
+            let config_data = load_config(config).context("Failed to load configuration")?;
+            let response_cache = config_data.response_cache();
+
+            let outputs_to_import = match &output {
+                Some(names) => config_data.get_outputs_by_name(names),
+                None => config_data.enabled_outputs(),
+            };
+
+            if outputs_to_import.is_empty() {
+                println!("⚠️  No outputs found to import. Check your configuration.");
+                return Ok(());
+            }
+
+            for output_config in outputs_to_import {
+                if !matches!(output_config.output_type, OutputType::Photostream) {
+                    println!(
+                        "\n⏭️  Skipping {}, only photostream outputs can be imported",
+                        output_config.out_dir
+                    );
+                    continue;
+                }
+
+                println!("\n📥 Importing bundles from {}...", output_config.out_dir);
+                let index_store = config_data.index_store(output_config)?;
+                let mut photo_index = index_store.load().context("Failed to load photo index")?;
+                let loaded_at = photo_index.last_updated;
+
+                let retry_policy = RetryPolicy::from_config(&output_config.retry);
+                let fetch_options = FetchOptions {
+                    index: None,
+                    cache: response_cache.as_ref(),
+                    offline: false,
+                };
+                let album =
+                    fetch_albums(&output_config.album_sources(), &retry_policy, fetch_options)
+                        .await
+                        .context("Failed to fetch album")?;
+                println!(
+                    "  • Album '{}' fetched with {} photos",
+                    album.name,
+                    album.photos.len()
+                );
+
+                let outcomes = import::import_bundles(
+                    &config_data.content_dir(output_config),
+                    config_data.site_root(),
+                    &album,
+                    &mut photo_index,
+                )?;
+                let mut imported = 0;
+                for (bundle, outcome) in &outcomes {
+                    match outcome {
+                        import::BundleOutcome::Imported {
+                            guid,
+                            matched_by,
+                            moved_to,
+                        } => {
+                            imported += 1;
+                            println!(
+                                "  • {}: imported as {guid} (matched by {matched_by:?})",
+                                bundle.display()
+                            );
+                            if let Some(moved_to) = moved_to {
+                                println!("    moved to {}", moved_to.display());
+                            }
+                        }
+                        import::BundleOutcome::AlreadyIndexed(guid) => {
+                            debug!("{} is already indexed as {guid}", bundle.display())
+                        }
+                        import::BundleOutcome::Unmatched => {
+                            println!("  • {}: not in the album, left alone", bundle.display())
+                        }
+                        import::BundleOutcome::Skipped(reason) => {
+                            eprintln!("  ⚠️  {}: {reason}", bundle.display())
+                        }
+                    }
+                }
+                println!("  • Imported {imported} of {} bundles", outcomes.len());
+
+                save_index(&photo_index, index_store.as_ref(), loaded_at)
+                    .context("Failed to save photo index")?;
+            }
+            Ok(())
+        }
+        Commands::Gc {
+            config,
+            output,
+            dry_run,
+        } => {
+            let config_data = load_config(config).context("Failed to load configuration")?;
+
+            let outputs_to_collect = match &output {
+                Some(names) => config_data.get_outputs_by_name(names),
+                None => config_data.enabled_outputs(),
+            };
+
+            if outputs_to_collect.is_empty() {
+                println!("⚠️  No outputs found to collect. Check your configuration.");
+                return Ok(());
+            }
+
+            let (mut photos, mut bytes) = (0, 0);
+            for output_config in outputs_to_collect {
+                println!("\n🧹 Collecting garbage in {}...", output_config.data_file);
+                let index_store = config_data.index_store(output_config)?;
+                let mut photo_index = index_store.load().context("Failed to load photo index")?;
+                let loaded_at = photo_index.last_updated;
+
+                let report = gc::collect_garbage(
+                    &output_config.output_type,
+                    &config_data.content_dir(output_config),
+                    config_data.site_root(),
+                    &mut photo_index,
+                    *dry_run,
+                )?;
+                for warning in &report.warnings {
+                    eprintln!("  ⚠️  {warning}");
+                }
+                if report.is_empty() {
+                    println!("  • Nothing to collect");
+                    continue;
+                }
+                for gallery in &report.galleries {
+                    println!("  • Stale gallery '{gallery}'");
+                }
+                println!(
+                    "  • {} unused photos, {} files ({})",
+                    report.photos.len(),
+                    report.files.len(),
+                    format_bytes(report.bytes)
+                );
+                photos += report.photos.len();
+                bytes += report.bytes;
+
+                if !*dry_run {
+                    save_index(&photo_index, index_store.as_ref(), loaded_at)
+                        .context("Failed to save photo index")?;
+                }
+            }
+
+            if *dry_run {
+                println!(
+                    "\n🔎 Dry run: {photos} photos and {} would be removed",
+                    format_bytes(bytes)
+                );
+            } else {
+                println!(
+                    "\n✅ Removed {photos} photos and reclaimed {}",
+                    format_bytes(bytes)
+                );
+            }
+            Ok(())
+        }
+        Commands::Rerender {
+            config,
+            output,
+            refresh_metadata,
+        } => {
+            let config_data = load_config(config).context("Failed to load configuration")?;
+
+            let overrides = Arc::new(config_data.overrides()?);
+
+            let outputs_to_rerender = match &output {
+                Some(names) => config_data.get_outputs_by_name(names),
+                None => config_data.enabled_outputs(),
+            };
+
+            if outputs_to_rerender.is_empty() {
+                println!("⚠️  No outputs found to rerender. Check your configuration.");
+                return Ok(());
+            }
+
+            let mut failed = 0;
+            for output_config in outputs_to_rerender {
+                println!("\n🖨️  Rerendering {}...", output_config.out_dir);
+                let index_store = config_data.index_store(output_config)?;
+                let mut photo_index = index_store.load().context("Failed to load photo index")?;
+                let loaded_at = photo_index.last_updated;
+
+                let report = rerender::rerender_output(
+                    &output_config.output_type,
+                    &config_data.content_dir(output_config),
+                    config_data.site_root(),
+                    &mut photo_index,
+                    &output_renderer(&config_data, output_config, &overrides),
+                    *refresh_metadata,
+                )
+                .await;
+                for failure in &report.failures {
+                    eprintln!("  ❌ {failure}");
+                }
+                if let Some(dir) = term_pages_dir(&config_data, output_config) {
+                    match hashtags::write_term_pages(
+                        &dir,
+                        &photo_index,
+                        &output_overrides(&overrides, output_config),
+                    )
+                    .await
+                    {
+                        Ok(count) => println!("  • Wrote term pages for {count} hashtags"),
+                        Err(err) => {
+                            eprintln!("  ❌ Term pages: {err:#}");
+                            failed += 1;
+                        }
+                    }
+                }
+                if *refresh_metadata {
+                    println!("  • Refreshed metadata of {} photos", report.refreshed);
+                }
+                println!("  • Wrote {} pages", report.pages);
+                failed += report.failures.len();
+
+                save_index(&photo_index, index_store.as_ref(), loaded_at)
+                    .context("Failed to save photo index")?;
+            }
+
+            if failed > 0 {
+                anyhow::bail!("{failed} photos or pages could not be rerendered");
+            }
+            Ok(())
+        }
+        Commands::Verify { config, output } => {
+            let config_data = load_config(config).context("Failed to load configuration")?;
+
+            let outputs_to_verify = match &output {
+                Some(names) => config_data.get_outputs_by_name(names),
+                None => config_data.enabled_outputs(),
+            };
+
+            if outputs_to_verify.is_empty() {
+                println!("⚠️  No outputs found to verify. Check your configuration.");
+                return Ok(());
+            }
+
+            let mut issue_count = 0;
+            for output_config in outputs_to_verify {
+                println!("\n🔍 Verifying {}...", output_config.out_dir);
+                let photo_index = config_data
+                    .index_store(output_config)
+                    .and_then(|index_store| index_store.load())
+                    .context("Failed to load photo index")?;
+
+                let issues = verify::verify_output(
+                    &output_config.output_type,
+                    &config_data.content_dir(output_config),
+                    config_data.site_root(),
+                    &photo_index,
+                )?;
+                if issues.is_empty() {
+                    println!("  • Everything matches the index");
+                }
+                for issue in &issues {
+                    println!("  ⚠️  {issue}");
+                }
+                issue_count += issues.len();
+            }
+
+            if issue_count > 0 {
+                anyhow::bail!(
+                    "Found {issue_count} problems, run 'icloudAlbum2hugo repair' to fix them"
+                );
+            }
+            Ok(())
+        }
+        Commands::Repair {
+            config,
+            output,
+            orphans,
+        } => {
+            let config_data = load_config(config).context("Failed to load configuration")?;
+
+            let overrides = Arc::new(config_data.overrides()?);
+
+            let outputs_to_repair = match &output {
+                Some(names) => config_data.get_outputs_by_name(names),
+                None => config_data.enabled_outputs(),
+            };
+
+            if outputs_to_repair.is_empty() {
+                println!("⚠️  No outputs found to repair. Check your configuration.");
+                return Ok(());
+            }
+
+            let mut failed = 0;
+            for output_config in outputs_to_repair {
+                println!("\n🔧 Repairing {}...", output_config.out_dir);
+                let index_store = config_data.index_store(output_config)?;
+                let mut photo_index = index_store.load().context("Failed to load photo index")?;
+                let loaded_at = photo_index.last_updated;
+
+                let content_dir = config_data.content_dir(output_config);
+                let issues = verify::verify_output(
+                    &output_config.output_type,
+                    &content_dir,
+                    config_data.site_root(),
+                    &photo_index,
+                )?;
+                if issues.is_empty() {
+                    println!("  • Nothing to repair");
+                    continue;
+                }
+
+                let retry_policy = RetryPolicy::from_config(&output_config.retry);
+                let download_config = output_config.downloads.or(&config_data.downloads);
+                let downloader = download::Downloader::new(retry_policy)
+                    .with_bandwidth_limit(download_config.max_bytes_per_second);
+                let renderer = output_renderer(&config_data, output_config, &overrides);
+
+                let outcomes = verify::repair(
+                    issues,
+                    &mut photo_index,
+                    &content_dir,
+                    config_data.site_root(),
+                    &downloader,
+                    &renderer,
+                    |path| orphans.unwrap_or_else(|| ask_orphan_action(path)),
+                )
+                .await;
+                for (issue, outcome) in &outcomes {
+                    println!("  • {issue}: {outcome}");
+                    if matches!(outcome, verify::Outcome::Failed(_)) {
+                        failed += 1;
+                    }
+                }
+
+                save_index(&photo_index, index_store.as_ref(), loaded_at)
+                    .context("Failed to save photo index")?;
+            }
+
+            if failed > 0 {
+                anyhow::bail!("{failed} problems could not be repaired");
+            }
+            Ok(())
+        }
+        Commands::Approve {
+            config,
+            output,
+            guids,
+            all_before,
+        } => decide_photos(config, output, guids, all_before, Decision::Approve).await,
+        Commands::Reject {
+            config,
+            output,
+            guids,
+            all_before,
+        } => decide_photos(config, output, guids, all_before, Decision::Reject).await,
+    }
+}
+
+/// Approves or rejects photos in every selected output, and renders the
+/// pages that show them again
+async fn decide_photos(
+    config: &Option<PathBuf>,
+    output: &Option<Vec<String>>,
+    guids: &[String],
+    all_before: &Option<chrono::DateTime<chrono::Utc>>,
+    decision: Decision,
+) -> Result<()> {
+    let config_data = load_config(config).context("Failed to load configuration")?;
+
+    let overrides = Arc::new(config_data.overrides()?);
+
+    let outputs_to_update = match &output {
+        Some(names) => config_data.get_outputs_by_name(names),
+        None => config_data.enabled_outputs(),
+    };
+
+    if outputs_to_update.is_empty() {
+        println!("⚠️  No outputs found to update. Check your configuration.");
+        return Ok(());
+    }
+
+    let selection = match all_before {
+        Some(date) => approval::Selection::PendingBefore(*date),
+        None => approval::Selection::Guids(guids.to_vec()),
+    };
+
+    let mut found = std::collections::HashSet::new();
+    let mut failed = 0;
+    for output_config in outputs_to_update {
+        let index_store = config_data.index_store(output_config)?;
+        let mut photo_index = index_store.load().context("Failed to load photo index")?;
+        let loaded_at = photo_index.last_updated;
+
+        let (outcomes, render_failures) = decide_and_render(
+            &config_data,
+            output_config,
+            &overrides,
+            &mut photo_index,
+            &selection,
+            decision,
+        )
+        .await;
+        if outcomes.is_empty() {
+            continue;
+        }
+
+        println!("\n✅ Updated {}", output_config.out_dir);
+        for (guid, outcome) in &outcomes {
+            println!("  • Photo {guid}: {outcome}");
+            if matches!(outcome, approval::Outcome::Failed(_)) {
+                failed += 1;
+            }
+            found.insert(guid.clone());
+        }
+        failed += render_failures;
+
+        save_index(&photo_index, index_store.as_ref(), loaded_at)
+            .context("Failed to save photo index")?;
+    }
+
+    match &selection {
+        approval::Selection::Guids(guids) => {
+            for guid in guids.iter().filter(|guid| !found.contains(*guid)) {
+                println!("⚠️  Photo {guid} isn't in any of the outputs");
+            }
+        }
+        approval::Selection::PendingBefore(_) if found.is_empty() => {
+            println!("No photos are waiting for approval before that date");
+        }
+        approval::Selection::PendingBefore(_) => {}
+    }
+
+    if failed > 0 {
+        anyhow::bail!("{failed} photos or pages could not be updated");
     }
+    Ok(())
+}
+
+/// Approves or rejects photos of an output and renders the pages that show
+/// them again
+///
+/// Returns the outcome for each photo, and how many pages failed to render.
+async fn decide_and_render(
+    config_data: &Config,
+    output_config: &config::OutputConfig,
+    overrides: &Arc<Overrides>,
+    photo_index: &mut index::PhotoIndex,
+    selection: &approval::Selection,
+    decision: Decision,
+) -> (Vec<(String, approval::Outcome)>, usize) {
+    let content_dir = config_data.content_dir(output_config);
+    let outcomes = approval::decide(
+        &output_config.output_type,
+        &content_dir,
+        config_data.site_root(),
+        photo_index,
+        selection,
+        decision,
+    );
+
+    let mut failed = 0;
+    let renderer = output_renderer(config_data, output_config, overrides);
+    for page in approval::affected_pages(
+        &output_config.output_type,
+        &content_dir,
+        photo_index,
+        &outcomes,
+    ) {
+        if let Err(e) = renderer.render(photo_index, &page).await {
+            eprintln!("  ❌ {page}: {e:#}");
+            failed += 1;
+        }
+    }
+    (outcomes, failed)
+}
+
+/// Prints how many photos each filter rule left out
+fn print_filter_report(report: &filter::FilterReport) {
+    println!(
+        "  • Filters kept {} photos and left out {}",
+        report.kept,
+        report.excluded_count()
+    );
+    for (rule, count) in &report.excluded {
+        println!("    - {rule}: {count}");
+    }
+}
+
+/// Parses a date given on the command line
+fn parse_date_arg(date: &str) -> Result<chrono::DateTime<chrono::Utc>, String> {
+    frontmatter::parse_date(date).ok_or_else(|| format!("invalid date '{date}'"))
 }
 
 /// Initialize the configuration file
@@ -573,12 +1656,13 @@ fn init_config(config_path_opt: &Option<PathBuf>, force: bool) -> Result<()> {
     }
 
     // Ensure parent directory exists
-    if let Some(parent) = config_path.parent() {
-        if !parent.as_os_str().is_empty() && !parent.exists() {
-            debug!("Creating parent directory: {}", parent.display());
-            fs::create_dir_all(parent)
-                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
-        }
+    if let Some(parent) = config_path.parent()
+        && !parent.as_os_str().is_empty()
+        && !parent.exists()
+    {
+        debug!("Creating parent directory: {}", parent.display());
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
     }
 
     // Create a default config with example outputs
@@ -588,12 +1672,19 @@ fn init_config(config_path_opt: &Option<PathBuf>, force: bool) -> Result<()> {
     let gallery_example = config::OutputConfig {
         output_type: config::OutputType::Gallery,
         album_url: "https://www.icloud.com/sharedalbum/GALLERY_TOKEN_GOES_HERE".to_string(),
+        album_urls: Vec::new(),
         out_dir: "content/galleries/my_gallery".to_string(),
         data_file: "data/photos/gallery.yaml".to_string(),
         name: Some("My Gallery".to_string()), // Optional - will use album name if not provided
         description: Some("A collection of photos from my album".to_string()), // Optional
         enabled: false,                       // Disabled by default
         privacy: config::PrivacyConfig::default(), // Default privacy settings
+        retry: config::RetryConfig::default(),
+        two_phase_fetch: false,
+        downloads: config::DownloadConfig::default(),
+        require_approval: false,
+        filter: config::FilterConfig::default(),
+        hashtags: None,
     };
 
     config.outputs.push(gallery_example);
@@ -602,12 +1693,19 @@ fn init_config(config_path_opt: &Option<PathBuf>, force: bool) -> Result<()> {
     let minimal_gallery_example = config::OutputConfig {
         output_type: config::OutputType::Gallery,
         album_url: "https://www.icloud.com/sharedalbum/ANOTHER_GALLERY_TOKEN".to_string(),
+        album_urls: Vec::new(),
         out_dir: "content/galleries/simple_gallery".to_string(),
         data_file: "data/photos/simple_gallery.yaml".to_string(),
         name: None,                                // Will use album name from iCloud
         description: None,                         // No description needed
         enabled: false,                            // Disabled by default
         privacy: config::PrivacyConfig::default(), // Default privacy settings
+        retry: config::RetryConfig::default(),
+        two_phase_fetch: false,
+        downloads: config::DownloadConfig::default(),
+        require_approval: false,
+        filter: config::FilterConfig::default(),
+        hashtags: None,
     };
 
     config.outputs.push(minimal_gallery_example);
@@ -632,6 +1730,170 @@ fn init_config(config_path_opt: &Option<PathBuf>, force: bool) -> Result<()> {
     Ok(())
 }
 
+/// Why an output was left untouched by a sync
+///
+/// Each reason has its own exit code so that scripts running the sync can
+/// tell what went wrong.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StaleReason {
+    /// The album was deleted
+    AlbumNotFound,
+    /// The album is no longer shared publicly
+    AlbumNotPublic,
+    /// iCloud kept rate limiting us
+    RateLimited,
+    /// iCloud sent something we couldn't parse
+    MalformedResponse,
+    /// The album returned no photos while the index still has some
+    EmptyAlbum,
+    /// The filter rules would delete more synced photos than allowed
+    TooManyDeletions,
+}
+
+impl StaleReason {
+    /// Finds the album failure behind a fetch error, if there is one
+    fn from_error(error: &anyhow::Error) -> Option<Self> {
+        error
+            .chain()
+            .find_map(|cause| match cause.downcast_ref::<ICloudError>()? {
+                ICloudError::AlbumNotFound(_) => Some(Self::AlbumNotFound),
+                ICloudError::AlbumNotPublic(_) => Some(Self::AlbumNotPublic),
+                ICloudError::RateLimited { .. } => Some(Self::RateLimited),
+                ICloudError::MalformedResponse(_) => Some(Self::MalformedResponse),
+                _ => None,
+            })
+    }
+
+    /// Process exit code reported when an output goes stale for this reason
+    fn exit_code(self) -> i32 {
+        match self {
+            Self::AlbumNotFound => 3,
+            Self::AlbumNotPublic => 4,
+            Self::RateLimited => 5,
+            Self::MalformedResponse => 6,
+            Self::EmptyAlbum => 7,
+            Self::TooManyDeletions => 8,
+        }
+    }
+}
+
+/// Keeps an output's content as it is and records why in its index
+fn mark_output_stale(
+    photo_index: &mut index::PhotoIndex,
+    index_store: &dyn IndexStore,
+    reason: StaleReason,
+    error: &anyhow::Error,
+) {
+    println!("  ℹ️  Keeping local content and marking this output as stale ({reason:?})");
+    let loaded_at = photo_index.last_updated;
+    photo_index.mark_stale(format!("{error:#}"));
+    if let Err(err) = save_index(photo_index, index_store, loaded_at) {
+        eprintln!("  ⚠️  Warning: Failed to save photo index: {err:#}");
+    }
+}
+
+/// Applies an output's hashtag settings to the overrides of all outputs
+fn output_overrides(overrides: &Overrides, output_config: &config::OutputConfig) -> Arc<Overrides> {
+    Arc::new(
+        overrides
+            .clone()
+            .with_hashtags(output_config.hashtags.clone()),
+    )
+}
+
+/// Returns the directory an output writes hashtag term pages to, if any
+fn term_pages_dir(config_data: &Config, output_config: &config::OutputConfig) -> Option<PathBuf> {
+    output_config
+        .hashtags
+        .as_ref()
+        .and_then(|hashtags| hashtags.term_pages.as_ref())
+        .map(|dir| config_data.resolve(dir))
+}
+
+/// Builds the syncer that renders an output's pages
+fn output_renderer(
+    config_data: &Config,
+    output_config: &config::OutputConfig,
+    overrides: &Arc<Overrides>,
+) -> verify::Renderer {
+    let overrides = &output_overrides(overrides, output_config);
+    let content_dir = config_data.content_dir(output_config);
+    let data_file_path = config_data.data_file(output_config);
+    let site_root = config_data.site_root().to_path_buf();
+    match output_config.output_type {
+        OutputType::Photostream => verify::Renderer::Photostream(
+            Syncer::new(content_dir, data_file_path)
+                .with_overrides(overrides.clone())
+                .with_site_root(site_root),
+        ),
+        OutputType::Gallery => verify::Renderer::Gallery(
+            GallerySyncer::new(
+                content_dir,
+                output_config.name.clone(),
+                output_config.description.clone(),
+                data_file_path,
+                output_config.privacy.clone(),
+            )
+            .with_overrides(overrides.clone())
+            .with_site_root(site_root),
+        ),
+        OutputType::Batches => verify::Renderer::Batches(
+            BatchSyncer::new(content_dir, data_file_path, output_config.privacy.clone())
+                .with_overrides(overrides.clone())
+                .with_site_root(site_root),
+        ),
+    }
+}
+
+/// Formats a byte count for humans, e.g. "3.2 MB"
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1000 {
+        return format!("{bytes} bytes");
+    }
+    let mut value = bytes as f64 / 1000.0;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+/// Asks what to do with a file the index doesn't know about; anything but
+/// an explicit answer keeps it
+fn ask_orphan_action(path: &Path) -> OrphanAction {
+    print!(
+        "  ❓ {} isn't in the index. [r]emove, [a]dopt or [k]eep? ",
+        path.display()
+    );
+    let _ = std::io::stdout().flush();
+
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return OrphanAction::Keep;
+    }
+    match answer.trim().to_lowercase().as_str() {
+        "r" | "remove" => OrphanAction::Remove,
+        "a" | "adopt" => OrphanAction::Adopt,
+        _ => OrphanAction::Keep,
+    }
+}
+
+/// Saves an index, and exports the data file for Hugo if the index changed
+/// since it was loaded or the data file is missing
+fn save_index(
+    photo_index: &index::PhotoIndex,
+    index_store: &dyn IndexStore,
+    loaded_at: chrono::DateTime<chrono::Utc>,
+) -> Result<()> {
+    index_store.save(photo_index)?;
+    if photo_index.last_updated != loaded_at || !index_store.data_file().exists() {
+        index_store.export(photo_index)?;
+    }
+    Ok(())
+}
+
 /// Load configuration from file
 fn load_config(config_path_opt: &Option<PathBuf>) -> Result<Config> {
     let config_path = Config::get_config_path(config_path_opt);
@@ -0,0 +1,288 @@
+//! Schema versioning and migrations for the photo index.
+//!
+//! Every index file records the schema version it was written with. When an
+//! older file is loaded, the migrations between its version and the current
+//! one are applied in order to the raw YAML, before it is parsed into a
+//! `PhotoIndex`. Fields that are simply added can still rely on serde
+//! defaults; migrations are for renames and changes in meaning.
+//!
+//! Files written by a newer version of the tool are refused, since this
+//! version can't know what changed and saving would lose data.
+
+use anyhow::{Context, Result, bail};
+use serde_yaml::{Mapping, Value};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::hashtags;
+use crate::index::{PhotoIndex, relative_to};
+use crate::persist;
+
+/// Schema version written by this version of the tool
+pub const CURRENT_SCHEMA_VERSION: u32 = 3;
+
+/// Key holding the schema version in the index file
+const VERSION_KEY: &str = "schema_version";
+
+/// A step that upgrades the index from one schema version to the next
+struct Migration {
+    /// Version this migration upgrades from; it produces `from + 1`
+    from: u32,
+    /// What the migration changes
+    description: &'static str,
+    /// Rewrites the raw index, given the site root
+    apply: fn(&mut Mapping, &Path) -> Result<()>,
+}
+
+/// All migrations, in order
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        description: "Record the schema version in the index file",
+        apply: |_, _| Ok(()),
+    },
+    Migration {
+        from: 1,
+        description: "Store photo paths relative to the site root",
+        apply: relative_local_paths,
+    },
+    Migration {
+        from: 2,
+        description: "Record the hashtags in photo captions as tags",
+        apply: caption_hashtags,
+    },
+];
+
+/// Rewrites absolute photo paths inside the site to relative ones
+fn relative_local_paths(index: &mut Mapping, site_root: &Path) -> Result<()> {
+    let Some(Value::Mapping(photos)) = index.get_mut("photos") else {
+        return Ok(());
+    };
+
+    for photo in photos.values_mut() {
+        if let Some(local_path) = photo.get_mut("local_path")
+            && let Some(path) = local_path.as_str()
+        {
+            let relative = relative_to(Path::new(path), site_root);
+            *local_path = Value::String(relative.to_string_lossy().to_string());
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses the hashtags in the caption of every photo
+fn caption_hashtags(index: &mut Mapping, _site_root: &Path) -> Result<()> {
+    let Some(Value::Mapping(photos)) = index.get_mut("photos") else {
+        return Ok(());
+    };
+
+    for photo in photos.values_mut() {
+        let tags = match photo.get("caption").and_then(Value::as_str) {
+            Some(caption) => hashtags::parse(caption),
+            None => continue,
+        };
+        if let Value::Mapping(photo) = photo
+            && !tags.is_empty()
+        {
+            photo.insert("tags".into(), tags.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Error for an index written by a newer version of the tool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NewerSchemaError {
+    /// Schema version of the file
+    pub found: u32,
+}
+
+impl fmt::Display for NewerSchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Index was written by a newer version of icloudAlbum2hugo (schema version {}, this version supports up to {CURRENT_SCHEMA_VERSION}); please upgrade",
+            self.found
+        )
+    }
+}
+
+impl std::error::Error for NewerSchemaError {}
+
+/// Reads the schema version of a raw index; files from before versioning are version 0
+pub fn schema_version(value: &Value) -> Result<u32> {
+    match value.get(VERSION_KEY) {
+        None | Some(Value::Null) => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .with_context(|| format!("Invalid schema version {version:?}")),
+    }
+}
+
+/// Upgrades a raw index to the current schema version, returning the version it had
+///
+/// `site_root` is the directory of the Hugo site the index belongs to.
+pub fn migrate(value: &mut Value, site_root: &Path) -> Result<u32> {
+    let found = schema_version(value)?;
+    if found > CURRENT_SCHEMA_VERSION {
+        return Err(NewerSchemaError { found }.into());
+    }
+
+    let Value::Mapping(mapping) = value else {
+        bail!("Index file is not a YAML mapping");
+    };
+
+    for migration in MIGRATIONS.iter().filter(|m| m.from >= found) {
+        log::info!(
+            "Migrating index to schema version {}: {}",
+            migration.from + 1,
+            migration.description
+        );
+        (migration.apply)(mapping, site_root).with_context(|| {
+            format!(
+                "Failed to migrate index to schema version {}",
+                migration.from + 1
+            )
+        })?;
+    }
+
+    mapping.insert(VERSION_KEY.into(), CURRENT_SCHEMA_VERSION.into());
+    Ok(found)
+}
+
+/// Outcome of migrating an index file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// Schema version the file had
+    pub from: u32,
+    /// Copy of the file as it was before the migration, if it was migrated
+    pub backup: Option<PathBuf>,
+}
+
+/// Returns where the pre-migration copy of an index file is kept
+pub fn migration_backup_path(path: &Path, from: u32) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{file_name}.schema-{from}.bak"))
+}
+
+/// Upgrades an index file in place, keeping a copy of the old version
+pub fn migrate_file(path: &Path, site_root: &Path) -> Result<MigrationReport> {
+    let contents = fs::read(path)
+        .with_context(|| format!("Failed to read index file from {}", path.display()))?;
+    let value: Value = serde_yaml::from_slice(&contents)
+        .with_context(|| format!("Failed to parse index file from {}", path.display()))?;
+
+    let from = schema_version(&value)?;
+    if from > CURRENT_SCHEMA_VERSION {
+        return Err(NewerSchemaError { found: from }.into());
+    }
+    if from == CURRENT_SCHEMA_VERSION {
+        return Ok(MigrationReport { from, backup: None });
+    }
+
+    let backup = migration_backup_path(path, from);
+    persist::write_atomic(&backup, &contents)?;
+
+    // Loading runs the migrations, saving writes the new version
+    PhotoIndex::load(path, site_root)?.save(path)?;
+
+    Ok(MigrationReport {
+        from,
+        backup: Some(backup),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_migrate_unversioned_index() -> Result<()> {
+        let mut value: Value = serde_yaml::from_str("last_updated: 2024-01-01T00:00:00Z\n")?;
+        assert_eq!(migrate(&mut value, Path::new(""))?, 0);
+        assert_eq!(schema_version(&value)?, CURRENT_SCHEMA_VERSION);
+        Ok(())
+    }
+
+    #[test]
+    fn test_refuses_newer_schema() -> Result<()> {
+        let mut value: Value =
+            serde_yaml::from_str(&format!("schema_version: {}\n", CURRENT_SCHEMA_VERSION + 1))?;
+        let err = migrate(&mut value, Path::new("")).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<NewerSchemaError>(),
+            Some(&NewerSchemaError {
+                found: CURRENT_SCHEMA_VERSION + 1
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_file_keeps_backup() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("index.yaml");
+        let original = "last_updated: 2024-01-01T00:00:00Z\nphotos: {}\ngalleries: {}\n";
+        fs::write(&path, original)?;
+
+        let report = migrate_file(&path, temp_dir.path())?;
+        assert_eq!(report.from, 0);
+        let backup = report.backup.unwrap();
+        assert_eq!(fs::read_to_string(&backup)?, original);
+        assert!(
+            fs::read_to_string(&path)?
+                .contains(&format!("schema_version: {CURRENT_SCHEMA_VERSION}"))
+        );
+
+        // Running it again has nothing to do
+        assert_eq!(migrate_file(&path, temp_dir.path())?.backup, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_makes_local_paths_relative() -> Result<()> {
+        let root = Path::new("/srv/site");
+        let yaml = format!(
+            "schema_version: 1\nphotos:\n  photo1:\n    local_path: {}\n  photo2:\n    local_path: /elsewhere/photo2.jpg\n",
+            root.join("content/photostream/photo1/original.jpg")
+                .display()
+        );
+        let mut value: Value = serde_yaml::from_str(&yaml)?;
+        migrate(&mut value, root)?;
+
+        assert_eq!(
+            value["photos"]["photo1"]["local_path"].as_str(),
+            Some("content/photostream/photo1/original.jpg")
+        );
+        assert_eq!(
+            value["photos"]["photo2"]["local_path"].as_str(),
+            Some("/elsewhere/photo2.jpg")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_parses_caption_hashtags() -> Result<()> {
+        let yaml = "schema_version: 2\nphotos:\n  photo1:\n    caption: 'Beach day #summer #kids'\n  photo2:\n    caption: No tags\n";
+        let mut value: Value = serde_yaml::from_str(yaml)?;
+        migrate(&mut value, Path::new(""))?;
+
+        assert_eq!(
+            value["photos"]["photo1"]["tags"],
+            serde_yaml::from_str::<Value>("[summer, kids]")?
+        );
+        assert!(value["photos"]["photo2"].get("tags").is_none());
+
+        Ok(())
+    }
+}
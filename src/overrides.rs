@@ -0,0 +1,234 @@
+//! Per-photo overrides kept in the site repository.
+//!
+//! Everyone in a shared album can see it, so fixing a wrong caption, a bad
+//! geocode or missing alt text in the album isn't always an option. The
+//! overrides file maps photo GUIDs to what should be shown instead:
+//!
+//! ```yaml
+//! 0F3A...:
+//!   title: Grandma's 90th
+//!   alt: Grandma blowing out the candles on her cake
+//!   location: Oak Park, IL
+//!   tags: [family, birthday]
+//! 7C21...:
+//!   hidden: true
+//! ```
+//!
+//! Overrides are applied when pages are rendered, so the index keeps what
+//! the album says and the overrides can be changed or removed at any time.
+//! The output's hashtag settings are applied along with them, since both
+//! decide what a page shows of the album's captions and tags.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::config::HashtagConfig;
+use crate::frontmatter::deserialize_date;
+use crate::geocode::Location;
+use crate::hashtags;
+use crate::index::IndexedPhoto;
+use crate::sync::format_photo_title;
+
+/// What to show instead of the album's data for one photo
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PhotoOverride {
+    /// Caption, used as the page body
+    pub caption: Option<String>,
+    /// Title, instead of the one made from the date, place and camera
+    pub title: Option<String>,
+    /// Alt text for the image
+    pub alt: Option<String>,
+    /// Place name, instead of the geocoded one
+    pub location: Option<String>,
+    /// Date the photo was taken
+    #[serde(default, deserialize_with = "deserialize_date")]
+    pub date: Option<DateTime<Utc>>,
+    /// Tags of the photo's page
+    pub tags: Option<Vec<String>>,
+    /// Leave the photo off the site
+    #[serde(default)]
+    pub hidden: bool,
+}
+
+/// The overrides of all photos, by GUID
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    photos: HashMap<String, PhotoOverride>,
+    /// Hashtag settings of the output the overrides are applied for
+    hashtags: Option<HashtagConfig>,
+}
+
+/// A photo as it's rendered, with its overrides applied
+#[derive(Debug, Clone)]
+pub struct PhotoView<'a> {
+    /// The photo with the overridden caption, location and date
+    pub photo: Cow<'a, IndexedPhoto>,
+    /// Page title
+    pub title: String,
+    /// Alt text, if one was given
+    pub alt: Option<String>,
+    /// Tags, if they were given or the output writes the caption's hashtags
+    pub tags: Option<Vec<String>>,
+    /// Whether the photo is left off the site, because it's hidden by its
+    /// overrides or not approved
+    pub hidden: bool,
+}
+
+impl Overrides {
+    /// Reads the overrides file
+    pub fn load(path: &Path) -> Result<Self> {
+        let yaml = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read overrides from {}", path.display()))?;
+        let photos: Option<HashMap<String, PhotoOverride>> = serde_yaml::from_str(&yaml)
+            .with_context(|| format!("Invalid overrides in {}", path.display()))?;
+        Ok(Self {
+            photos: photos.unwrap_or_default(),
+            hashtags: None,
+        })
+    }
+
+    /// Sets the hashtag settings of the output the overrides are applied for
+    pub fn with_hashtags(mut self, hashtags: Option<HashtagConfig>) -> Self {
+        self.hashtags = hashtags;
+        self
+    }
+
+    /// Returns the taxonomy hashtags are written to, if the output writes them
+    pub fn taxonomy(&self) -> Option<&str> {
+        self.hashtags
+            .as_ref()
+            .map(|hashtags| hashtags.taxonomy.as_str())
+    }
+
+    /// Returns a caption as it's shown on the site
+    pub fn caption<'c>(&self, caption: &'c str) -> Cow<'c, str> {
+        match &self.hashtags {
+            Some(hashtags) if hashtags.strip_from_caption => Cow::Owned(hashtags::strip(caption)),
+            _ => Cow::Borrowed(caption),
+        }
+    }
+
+    /// Returns the overrides of a photo
+    pub fn get(&self, guid: &str) -> Option<&PhotoOverride> {
+        self.photos.get(guid)
+    }
+
+    /// Applies a photo's overrides for rendering
+    pub fn apply<'a>(&self, photo: &'a IndexedPhoto) -> PhotoView<'a> {
+        let mut photo = Cow::Borrowed(photo);
+        if let Some(caption) = photo.caption.as_deref()
+            && let Cow::Owned(shown) = self.caption(caption)
+        {
+            photo.to_mut().caption = Some(shown).filter(|shown| !shown.is_empty());
+        }
+        let hashtags =
+            (self.hashtags.is_some() && !photo.tags.is_empty()).then(|| photo.tags.clone());
+
+        let Some(photo_override) = self.get(&photo.guid) else {
+            return PhotoView {
+                title: format_photo_title(&photo),
+                alt: None,
+                tags: hashtags,
+                hidden: photo.is_unapproved(),
+                photo,
+            };
+        };
+
+        if let Some(caption) = &photo_override.caption {
+            photo.to_mut().caption = Some(caption.clone());
+        }
+        // The city, state and country came from the same geocode as the name
+        if let Some(location) = &photo_override.location {
+            photo.to_mut().location = Some(Location {
+                formatted_address: location.clone(),
+                ..Location::default()
+            });
+        }
+        if let Some(date) = photo_override.date {
+            let photo = photo.to_mut();
+            photo.created_at = date;
+            photo.exif_date_time = photo.exif_date_time.map(|_| date);
+        }
+
+        PhotoView {
+            title: photo_override
+                .title
+                .clone()
+                .unwrap_or_else(|| format_photo_title(&photo)),
+            alt: photo_override.alt.clone(),
+            tags: photo_override.tags.clone().or(hashtags),
+            hidden: photo_override.hidden || photo.is_unapproved(),
+            photo,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn create_photo(guid: &str) -> IndexedPhoto {
+        IndexedPhoto::new(
+            guid.to_string(),
+            format!("{guid}.jpg"),
+            Some("From the album".to_string()),
+            "2024-03-01T12:00:00Z".parse().unwrap(),
+            format!("checksum_{guid}"),
+            format!("https://example.com/{guid}.jpg"),
+            800,
+            600,
+            format!("{guid}.jpg").into(),
+            Path::new(""),
+        )
+    }
+
+    #[test]
+    fn test_apply_overrides() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("overrides.yaml");
+        fs::write(
+            &path,
+            "photo1:\n  caption: Fixed caption\n  location: Oak Park, IL\n  date: 2024-02-29\n  alt: A cake\n  tags: [family]\nphoto2:\n  hidden: true\n",
+        )?;
+        let overrides = Overrides::load(&path)?;
+
+        let photo = create_photo("photo1");
+        let view = overrides.apply(&photo);
+        assert_eq!(view.photo.caption.as_deref(), Some("Fixed caption"));
+        assert_eq!(view.title, "February 29, 2024, Oak Park, IL");
+        assert_eq!(view.alt.as_deref(), Some("A cake"));
+        assert_eq!(view.tags, Some(vec!["family".to_string()]));
+        assert!(!view.hidden);
+
+        assert!(overrides.apply(&create_photo("photo2")).hidden);
+
+        let photo = create_photo("photo3");
+        let view = overrides.apply(&photo);
+        assert!(matches!(view.photo, Cow::Borrowed(_)));
+        assert_eq!(view.title, "March  1, 2024");
+
+        fs::write(&path, "photo1:\n  date: someday\n")?;
+        assert!(Overrides::load(&path).is_err());
+
+        // Hashtags become tags unless the overrides give tags
+        let overrides = Overrides::default().with_hashtags(Some(HashtagConfig {
+            strip_from_caption: true,
+            ..HashtagConfig::default()
+        }));
+        let mut photo = create_photo("photo4");
+        photo.caption = Some("Beach day #summer".to_string());
+        photo.tags = vec!["summer".to_string()];
+        let view = overrides.apply(&photo);
+        assert_eq!(view.photo.caption.as_deref(), Some("Beach day"));
+        assert_eq!(view.tags, Some(vec!["summer".to_string()]));
+
+        Ok(())
+    }
+}
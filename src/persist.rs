@@ -0,0 +1,150 @@
+//! Crash-safe file writes.
+//!
+//! Writing a file in place leaves it half written if the process dies in the
+//! middle, and a half-written index means re-downloading every photo. Files
+//! written here go to a temporary file first, are flushed to disk and then
+//! renamed over the target, so the target is always either the old or the
+//! new version.
+//!
+//! The previous versions of a file can also be kept as rotating backups, to
+//! fall back on if the file is damaged some other way.
+
+use anyhow::{Context, Result};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Writes `contents` to `path` atomically
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let parent = parent_dir(path);
+    fs::create_dir_all(&parent)
+        .with_context(|| format!("Failed to create directory for {}", path.display()))?;
+
+    let temp_path = sibling(path, "tmp");
+    let result = (|| -> std::io::Result<()> {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)
+    })();
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e).with_context(|| format!("Failed to write {}", path.display()));
+    }
+
+    // Make the rename itself durable. Not every platform can open a
+    // directory for this, so failures are ignored.
+    if let Ok(dir) = File::open(&parent) {
+        let _ = dir.sync_all();
+    }
+
+    Ok(())
+}
+
+/// Writes `contents` to `path` atomically, keeping up to `keep` previous versions
+///
+/// Nothing is rotated when the contents didn't change, so saving the same
+/// file twice doesn't push older backups out.
+pub fn write_with_backups(path: &Path, contents: &[u8], keep: usize) -> Result<()> {
+    if keep > 0
+        && let Ok(current) = fs::read(path)
+        && current != contents
+    {
+        rotate_backups(path, &current, keep)?;
+    }
+
+    write_atomic(path, contents)
+}
+
+/// Returns the path of the `n`th backup of a file, 1 being the newest
+///
+/// Backups are hidden files next to the original, so Hugo doesn't read them
+/// as site data.
+pub fn backup_path(path: &Path, n: usize) -> PathBuf {
+    sibling(path, &format!("{n}.bak"))
+}
+
+/// Lists the existing backups of a file, newest first
+pub fn backups(path: &Path) -> Vec<PathBuf> {
+    (1..)
+        .map(|n| backup_path(path, n))
+        .take_while(|backup| backup.exists())
+        .collect()
+}
+
+/// Shifts the existing backups down by one and stores `current` as the newest
+fn rotate_backups(path: &Path, current: &[u8], keep: usize) -> Result<()> {
+    let oldest = backup_path(path, keep);
+    if oldest.exists() {
+        fs::remove_file(&oldest)
+            .with_context(|| format!("Failed to remove backup {}", oldest.display()))?;
+    }
+
+    for n in (1..keep).rev() {
+        let from = backup_path(path, n);
+        if from.exists() {
+            let to = backup_path(path, n + 1);
+            fs::rename(&from, &to)
+                .with_context(|| format!("Failed to rotate backup {}", from.display()))?;
+        }
+    }
+
+    write_atomic(&backup_path(path, 1), current)
+}
+
+/// Directory containing `path`, `.` for bare file names
+fn parent_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// A hidden file next to `path`, named after it with an extra suffix
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    parent_dir(path).join(format!(".{file_name}.{suffix}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_write_atomic_replaces_file() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("data/index.yaml");
+
+        write_atomic(&path, b"first")?;
+        write_atomic(&path, b"second")?;
+
+        assert_eq!(fs::read_to_string(&path)?, "second");
+        assert!(!sibling(&path, "tmp").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_backups_rotate_and_skip_unchanged() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("index.yaml");
+
+        for version in ["v1", "v2", "v2", "v3", "v4"] {
+            write_with_backups(&path, version.as_bytes(), 2)?;
+        }
+
+        assert_eq!(fs::read_to_string(&path)?, "v4");
+        let backups = backups(&path);
+        assert_eq!(backups.len(), 2);
+        assert_eq!(fs::read_to_string(&backups[0])?, "v3");
+        assert_eq!(fs::read_to_string(&backups[1])?, "v2");
+        assert_eq!(backups[0], temp_dir.path().join(".index.yaml.1.bak"));
+
+        Ok(())
+    }
+}
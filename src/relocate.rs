@@ -0,0 +1,263 @@
+//! Moving an output to a new content directory.
+//!
+//! The page bundles are moved as a whole and the photo paths in the index are
+//! rewritten to match. If the index can't be saved afterwards, the bundles are
+//! moved back, so the output is never left half relocated.
+//!
+//! The data file is exported before the index is saved, because a database
+//! commit can't be taken back once the export fails. If the save fails after
+//! the export, the data file is exported again with the old paths.
+
+use anyhow::{Context, Result, bail};
+use log::warn;
+use std::fs;
+use std::path::Path;
+
+use crate::store::IndexStore;
+
+/// Moves an output's content from `from` to `to` and updates its index,
+/// returning how many photo paths were rewritten
+///
+/// `site_root` is the directory of the Hugo site, which photo paths in the
+/// index are relative to.
+pub fn relocate_output(
+    from: &Path,
+    to: &Path,
+    site_root: &Path,
+    store: &dyn IndexStore,
+) -> Result<usize> {
+    // Load the index first, so a broken index stops us before anything moves
+    let mut index = store.load()?;
+
+    let moved = if from.exists() {
+        if to.exists() && fs::read_dir(to)?.next().is_some() {
+            bail!("{} already exists and isn't empty", to.display());
+        }
+        move_dir(from, to)?;
+        true
+    } else if to.exists() {
+        // The content was already moved by hand, only the index is left
+        false
+    } else {
+        bail!("Neither {} nor {} exist", from.display(), to.display());
+    };
+
+    let original = index.clone();
+    let rebased = index.rebase_paths(from, to, site_root);
+    let saved = store.export(&index).and_then(|_| {
+        store.save(&index).inspect_err(|_| {
+            if let Err(e) = store.export(&original) {
+                warn!("Failed to restore {}: {e:#}", store.data_file().display());
+            }
+        })
+    });
+    if let Err(err) = saved {
+        if moved && let Err(e) = move_dir(to, from) {
+            warn!("Failed to move {} back: {e:#}", to.display());
+        }
+        return Err(err);
+    }
+
+    Ok(rebased)
+}
+
+/// Moves a directory, copying it when it can't be renamed (e.g. across disks)
+fn move_dir(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    if to.exists() {
+        // Only an empty directory can be in the way here
+        fs::remove_dir(to).with_context(|| format!("Failed to replace {}", to.display()))?;
+    }
+
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    copy_dir(from, to)
+        .with_context(|| format!("Failed to copy {} to {}", from.display(), to.display()))?;
+    fs::remove_dir_all(from).with_context(|| format!("Failed to remove {}", from.display()))
+}
+
+/// Recursively copies a directory
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::{IndexedPhoto, PhotoIndex};
+    use crate::store::{SqliteStore, YamlStore};
+    use chrono::Utc;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    /// Creates a photostream with one photo in `content/photostream`,
+    /// returning its index and the photo's file
+    fn photostream(site_root: &Path) -> Result<(PhotoIndex, PathBuf)> {
+        let photo_path = site_root.join("content/photostream/photo1/original.jpg");
+        fs::create_dir_all(photo_path.parent().unwrap())?;
+        fs::write(&photo_path, "IMAGE")?;
+
+        let mut index = PhotoIndex::new();
+        index.add_or_update_photo(IndexedPhoto::new(
+            "photo1".to_string(),
+            "photo1.jpg".to_string(),
+            None,
+            Utc::now(),
+            "checksum".to_string(),
+            "https://example.com/photo1.jpg".to_string(),
+            800,
+            600,
+            photo_path.clone(),
+            site_root,
+        ));
+        Ok((index, photo_path))
+    }
+
+    #[test]
+    fn test_relocate_output() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let from = temp_dir.path().join("content/photostream");
+        let to = temp_dir.path().join("content/photos");
+        let index_path = temp_dir.path().join("data/index.yaml");
+        let (index, _) = photostream(temp_dir.path())?;
+        index.save(&index_path)?;
+
+        let store = YamlStore::new(index_path.clone(), temp_dir.path().to_path_buf());
+        assert_eq!(relocate_output(&from, &to, temp_dir.path(), &store)?, 1);
+
+        let moved_path = to.join("photo1/original.jpg");
+        assert!(!from.exists());
+        assert_eq!(fs::read_to_string(&moved_path)?, "IMAGE");
+        let index = PhotoIndex::load(&index_path, temp_dir.path())?;
+        let photo = index.get_photo("photo1").unwrap();
+        assert_eq!(
+            photo.local_path,
+            Path::new("content/photos/photo1/original.jpg")
+        );
+        assert_eq!(photo.file_path(temp_dir.path()), moved_path);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_relocate_content_moved_by_hand() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let from = temp_dir.path().join("content/photostream");
+        let to = temp_dir.path().join("content/photos");
+        let index_path = temp_dir.path().join("data/index.yaml");
+        let (index, _) = photostream(temp_dir.path())?;
+        index.save(&index_path)?;
+        fs::rename(&from, &to)?;
+
+        let store = YamlStore::new(index_path.clone(), temp_dir.path().to_path_buf());
+        assert_eq!(relocate_output(&from, &to, temp_dir.path(), &store)?, 1);
+
+        let index = PhotoIndex::load(&index_path, temp_dir.path())?;
+        assert_eq!(
+            index.get_photo("photo1").unwrap().local_path,
+            Path::new("content/photos/photo1/original.jpg")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_relocate_into_occupied_dir_fails() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let from = temp_dir.path().join("content/photostream");
+        let to = temp_dir.path().join("content/photos");
+        let index_path = temp_dir.path().join("data/index.yaml");
+        let (index, photo_path) = photostream(temp_dir.path())?;
+        index.save(&index_path)?;
+        fs::create_dir_all(&to)?;
+        fs::write(to.join("other.md"), "OTHER")?;
+
+        let store = YamlStore::new(index_path.clone(), temp_dir.path().to_path_buf());
+        let err = relocate_output(&from, &to, temp_dir.path(), &store).unwrap_err();
+        assert!(err.to_string().contains("already exists and isn't empty"));
+        assert!(photo_path.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_relocate_with_nothing_to_move_fails() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let index_path = temp_dir.path().join("data/index.yaml");
+        PhotoIndex::new().save(&index_path)?;
+
+        let store = YamlStore::new(index_path, temp_dir.path().to_path_buf());
+        let err = relocate_output(
+            &temp_dir.path().join("content/photostream"),
+            &temp_dir.path().join("content/elsewhere"),
+            temp_dir.path(),
+            &store,
+        )
+        .unwrap_err();
+        assert!(err.to_string().starts_with("Neither"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_export_leaves_database_untouched() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let from = temp_dir.path().join("content/photostream");
+        let to = temp_dir.path().join("content/photos");
+        let data_dir = temp_dir.path().join("data");
+        let index_path = data_dir.join("index.yaml");
+        let database = temp_dir.path().join("index.db");
+        let (index, photo_path) = photostream(temp_dir.path())?;
+        let store = SqliteStore::open(&database, &index_path, temp_dir.path())?;
+        store.save(&index)?;
+
+        // A file where the data directory should be makes the export fail
+        fs::write(&data_dir, "not a directory")?;
+        assert!(relocate_output(&from, &to, temp_dir.path(), &store).is_err());
+
+        assert_eq!(fs::read_to_string(&photo_path)?, "IMAGE");
+        assert!(!to.exists());
+        let store = SqliteStore::open(&database, &index_path, temp_dir.path())?;
+        let photo = store.load()?.get_photo("photo1").cloned().unwrap();
+        assert_eq!(
+            photo.local_path,
+            Path::new("content/photostream/photo1/original.jpg")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_dir() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let from = temp_dir.path().join("from");
+        fs::create_dir_all(from.join("photo1"))?;
+        fs::write(from.join("photo1/original.jpg"), "IMAGE")?;
+        fs::write(from.join("index.md"), "PAGE")?;
+
+        let to = temp_dir.path().join("to");
+        copy_dir(&from, &to)?;
+        assert_eq!(fs::read_to_string(to.join("photo1/original.jpg"))?, "IMAGE");
+        assert_eq!(fs::read_to_string(to.join("index.md"))?, "PAGE");
+        assert!(from.exists());
+
+        Ok(())
+    }
+}
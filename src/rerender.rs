@@ -0,0 +1,317 @@
+//! Re-rendering an output's pages from its index.
+//!
+//! Syncs skip photos whose checksum didn't change, so changes to title
+//! formatting, privacy settings or the geocoder never reach existing pages.
+//! Re-rendering writes every page of an output again from the index without
+//! touching the network. It can first read the EXIF data of the files on disk
+//! again and geocode them, for when EXIF extraction or geocoding changed.
+
+use anyhow::Result;
+use std::path::Path;
+
+use crate::config::OutputType;
+use crate::exif::extract_exif;
+use crate::gallery;
+use crate::geocode::create_geocoding_service;
+use crate::index::{IndexedPhoto, PhotoIndex};
+use crate::verify::{Page, Renderer};
+
+/// What re-rendering an output did
+#[derive(Debug, Default)]
+pub struct RerenderReport {
+    /// Number of pages written
+    pub pages: usize,
+    /// Number of photos whose EXIF and location data was read again
+    pub refreshed: usize,
+    /// Photos and pages that failed, with the reason
+    pub failures: Vec<String>,
+}
+
+/// Writes every page of an output again from its index
+///
+/// With `refresh`, the EXIF data of each photo's file is read and geocoded
+/// again first, and the index is updated with it. `site_root` is the directory
+/// of the Hugo site, which photo paths in the index are relative to.
+pub async fn rerender_output(
+    output_type: &OutputType,
+    content_dir: &Path,
+    site_root: &Path,
+    index: &mut PhotoIndex,
+    renderer: &Renderer,
+    refresh: bool,
+) -> RerenderReport {
+    let mut report = RerenderReport::default();
+
+    if refresh {
+        // Rejected photos have no file left to read
+        let mut guids: Vec<String> = index
+            .photos
+            .values()
+            .filter(|photo| !photo.is_rejected())
+            .map(|photo| photo.guid.clone())
+            .collect();
+        guids.sort();
+        for guid in guids {
+            let Some(photo) = index.get_photo(&guid) else {
+                continue;
+            };
+            match refresh_metadata(photo, site_root) {
+                Ok(photo) => {
+                    index.add_or_update_photo(photo);
+                    report.refreshed += 1;
+                }
+                Err(e) => report.failures.push(format!("Photo {guid}: {e:#}")),
+            }
+        }
+    }
+
+    for page in pages(output_type, content_dir, index) {
+        match renderer.render(index, &page).await {
+            Ok(()) => report.pages += 1,
+            Err(e) => report.failures.push(format!("{page}: {e:#}")),
+        }
+    }
+
+    report
+}
+
+/// Lists the pages an output renders from its index
+pub fn pages(output_type: &OutputType, content_dir: &Path, index: &PhotoIndex) -> Vec<Page> {
+    let mut pages: Vec<Page> = match output_type {
+        // Rejected photos have no page
+        OutputType::Photostream => index
+            .photos
+            .values()
+            .filter(|photo| !photo.is_rejected())
+            .map(|photo| Page::Photo(photo.guid.clone()))
+            .collect(),
+        // The output has one page; prefer the gallery it shows now, then the
+        // one synced last
+        OutputType::Gallery => gallery::shown_gallery(content_dir, index)
+            .or_else(|| index.galleries.values().max_by_key(|g| g.updated_at))
+            .map(|gallery| Page::Gallery(gallery.id.clone()))
+            .into_iter()
+            .collect(),
+        OutputType::Batches => index.batches.keys().cloned().map(Page::Batch).collect(),
+    };
+    pages.sort();
+    pages
+}
+
+/// Reads a photo's EXIF data from its file again and geocodes it
+///
+/// The fuzzed coordinates are kept as long as the original ones didn't
+/// change, so pages don't move around on every run.
+fn refresh_metadata(photo: &IndexedPhoto, site_root: &Path) -> Result<IndexedPhoto> {
+    let exif = extract_exif(&photo.file_path(site_root))?;
+
+    let mut refreshed = photo.clone();
+    refreshed.update_exif(&exif);
+    if refreshed.latitude == photo.latitude && refreshed.longitude == photo.longitude {
+        refreshed.fuzzed_latitude = photo.fuzzed_latitude;
+        refreshed.fuzzed_longitude = photo.fuzzed_longitude;
+    }
+
+    refreshed.location = match (refreshed.latitude, refreshed.longitude) {
+        (Some(lat), Some(lon)) => Some(create_geocoding_service().reverse_geocode(lat, lon)?),
+        _ => None,
+    };
+    Ok(refreshed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::{Approval, Batch, Gallery, content_hash};
+    use crate::sync::Syncer;
+    use chrono::{Duration, Utc};
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    fn indexed_photo(guid: &str, local_path: &Path) -> IndexedPhoto {
+        IndexedPhoto::new(
+            guid.to_string(),
+            format!("{guid}.jpg"),
+            Some("A caption".to_string()),
+            Utc::now(),
+            format!("checksum_{guid}"),
+            format!("https://example.com/{guid}.jpg"),
+            800,
+            600,
+            local_path.to_path_buf(),
+            Path::new(""),
+        )
+    }
+
+    /// Creates a photostream with one bundle whose page is out of date,
+    /// returning its index and the bundle
+    fn photostream(content_dir: &Path) -> Result<(PhotoIndex, PathBuf)> {
+        let bundle = content_dir.join("photo1");
+        fs::create_dir_all(&bundle)?;
+        fs::write(bundle.join("original.jpg"), "IMAGE")?;
+        let old_body = "\nOld caption\n";
+        fs::write(
+            bundle.join("index.md"),
+            format!(
+                "---\ntitle: Old title\nbody_checksum: {}\n---\n{old_body}",
+                content_hash(old_body.as_bytes())
+            ),
+        )?;
+
+        let mut index = PhotoIndex::new();
+        let mut photo = indexed_photo("photo1", &bundle.join("original.jpg"));
+        photo.camera_make = Some("Apple".to_string());
+        index.add_or_update_photo(photo);
+        Ok((index, bundle))
+    }
+
+    fn renderer(temp_dir: &Path, content_dir: &Path) -> Renderer {
+        Renderer::Photostream(Syncer::new(
+            content_dir.to_path_buf(),
+            temp_dir.join("index.yaml"),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_rerender_photostream() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/photostream");
+        let (mut index, bundle) = photostream(&content_dir)?;
+
+        let report = rerender_output(
+            &OutputType::Photostream,
+            &content_dir,
+            temp_dir.path(),
+            &mut index,
+            &renderer(temp_dir.path(), &content_dir),
+            false,
+        )
+        .await;
+        assert_eq!((report.pages, report.refreshed), (1, 0));
+        assert!(report.failures.is_empty());
+
+        let page = fs::read_to_string(bundle.join("index.md"))?;
+        assert!(!page.contains("Old title"));
+        assert!(!page.contains("Old caption"));
+        assert!(page.contains("camera_make: \"Apple\""));
+        assert!(page.contains("A caption"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_refresh_reads_exif_again() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/photostream");
+        let (mut index, bundle) = photostream(&content_dir)?;
+
+        // The placeholder has no EXIF data, like a sync would find
+        let report = rerender_output(
+            &OutputType::Photostream,
+            &content_dir,
+            temp_dir.path(),
+            &mut index,
+            &renderer(temp_dir.path(), &content_dir),
+            true,
+        )
+        .await;
+        assert_eq!((report.pages, report.refreshed), (1, 1));
+        assert_eq!(index.get_photo("photo1").unwrap().camera_make, None);
+        assert!(!fs::read_to_string(bundle.join("index.md"))?.contains("camera_make"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_refresh_with_missing_file_fails() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/photostream");
+        let (mut index, bundle) = photostream(&content_dir)?;
+        fs::remove_file(bundle.join("original.jpg"))?;
+
+        // The photo keeps its data, and its page is still written
+        let report = rerender_output(
+            &OutputType::Photostream,
+            &content_dir,
+            temp_dir.path(),
+            &mut index,
+            &renderer(temp_dir.path(), &content_dir),
+            true,
+        )
+        .await;
+        assert_eq!((report.pages, report.refreshed), (1, 0));
+        assert_eq!(report.failures.len(), 1);
+        assert!(report.failures[0].starts_with("Photo photo1:"));
+        assert_eq!(
+            index.get_photo("photo1").unwrap().camera_make.as_deref(),
+            Some("Apple")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_photostream_pages_skip_rejected_photos() {
+        let mut index = PhotoIndex::new();
+        for guid in ["b", "a", "rejected"] {
+            index.add_or_update_photo(indexed_photo(guid, Path::new("x.jpg")));
+        }
+        index.photos.get_mut("rejected").unwrap().approval = Some(Approval::Rejected);
+
+        assert_eq!(
+            pages(&OutputType::Photostream, Path::new(""), &index),
+            vec![Page::Photo("a".to_string()), Page::Photo("b".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_gallery_page_falls_back_to_latest() {
+        let mut index = PhotoIndex::new();
+        let mut old = Gallery::new(
+            "old".to_string(),
+            "Old".to_string(),
+            "old".to_string(),
+            None,
+        );
+        old.updated_at = Utc::now() - Duration::days(1);
+        index.galleries.insert(old.id.clone(), old);
+        index.galleries.insert(
+            "new".to_string(),
+            Gallery::new(
+                "new".to_string(),
+                "New".to_string(),
+                "new".to_string(),
+                None,
+            ),
+        );
+
+        // Without a page telling which gallery is shown
+        assert_eq!(
+            pages(&OutputType::Gallery, Path::new("missing"), &index),
+            vec![Page::Gallery("new".to_string())]
+        );
+        assert!(
+            pages(
+                &OutputType::Gallery,
+                Path::new("missing"),
+                &PhotoIndex::new()
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_batch_pages() {
+        let mut index = PhotoIndex::new();
+        for id in ["b2", "b1"] {
+            let batch = Batch::new(id.to_string(), id.to_string(), Utc::now());
+            index.batches.insert(batch.id.clone(), batch);
+        }
+
+        assert_eq!(
+            pages(&OutputType::Batches, Path::new(""), &index),
+            vec![Page::Batch("b1".to_string()), Page::Batch("b2".to_string())]
+        );
+    }
+}
@@ -0,0 +1,292 @@
+//! Retry policy for network operations in icloud2hugo.
+//!
+//! iCloud occasionally answers with transient errors (503s, rate limits,
+//! dropped connections). Rather than skipping a whole output or failing a
+//! photo on the first error, album fetches and downloads go through a
+//! `RetryPolicy` that retries with exponential backoff and jitter, and waits
+//! for as long as the server asks when it sends a `Retry-After` header.
+//!
+//! Only errors that can plausibly succeed on a second try are retried:
+//! network errors, 5xx responses, 408 and 429 (including iCloud's
+//! `RateLimited` error). Anything else fails at once, in particular albums
+//! that were deleted or are no longer public.
+
+use anyhow::Result;
+use log::warn;
+use rand::Rng;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use crate::config::RetryConfig;
+use crate::icloud::ICloudError;
+
+/// An HTTP response with an unsuccessful status code
+#[derive(Debug)]
+pub struct HttpStatusError {
+    /// The URL that was requested
+    pub url: String,
+    /// The HTTP status code
+    pub status: u16,
+    /// How long the server asked us to wait before retrying
+    pub retry_after: Option<Duration>,
+}
+
+impl HttpStatusError {
+    /// Builds an error from a response, reading its `Retry-After` header
+    pub fn from_response(response: &reqwest::Response) -> Self {
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+
+        Self {
+            url: response.url().to_string(),
+            status: response.status().as_u16(),
+            retry_after,
+        }
+    }
+
+    /// Whether a request failing with this status is worth retrying
+    pub fn is_transient(&self) -> bool {
+        self.status == 408 || self.status == 429 || self.status >= 500
+    }
+}
+
+impl fmt::Display for HttpStatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HTTP {} from {}", self.status, self.url)
+    }
+}
+
+impl std::error::Error for HttpStatusError {}
+
+/// Checks whether an error is transient and the operation should be retried
+fn is_retryable(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        if let Some(status_error) = cause.downcast_ref::<HttpStatusError>() {
+            return status_error.is_transient();
+        }
+        cause.is::<reqwest::Error>()
+            || matches!(
+                cause.downcast_ref(),
+                Some(ICloudError::FetchError(_) | ICloudError::RateLimited { .. })
+            )
+    })
+}
+
+/// Returns the `Retry-After` delay requested by the server, if any
+fn retry_after(error: &anyhow::Error) -> Option<Duration> {
+    error.chain().find_map(|cause| {
+        if let Some(status_error) = cause.downcast_ref::<HttpStatusError>() {
+            return status_error.retry_after;
+        }
+        match cause.downcast_ref() {
+            Some(ICloudError::RateLimited { retry_after }) => *retry_after,
+            _ => None,
+        }
+    })
+}
+
+/// How often and how long to retry a failing operation
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one
+    max_attempts: u32,
+    /// Delay before the first retry
+    initial_delay: Duration,
+    /// Upper bound for any single delay
+    max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Creates a policy from the retry settings in the configuration
+    pub fn from_config(config: &RetryConfig) -> Self {
+        Self {
+            max_attempts: config.max_attempts.max(1),
+            initial_delay: Duration::from_millis(config.initial_delay_ms),
+            max_delay: Duration::from_millis(config.max_delay_ms),
+        }
+    }
+
+    /// Returns the delay before the given retry (1 for the first retry)
+    ///
+    /// The delay doubles with every retry and a random jitter of up to half
+    /// of it is subtracted, so that parallel downloads don't retry in lockstep.
+    /// A `Retry-After` from the server takes precedence.
+    pub fn delay_for(&self, retry: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(self.max_delay);
+        }
+
+        let backoff = self
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max_delay);
+        let jitter = rand::thread_rng().gen_range(0.0..=0.5);
+        backoff.mul_f64(1.0 - jitter)
+    }
+
+    /// Runs an operation, retrying transient failures until the attempts run out
+    ///
+    /// # Arguments
+    ///
+    /// * `description` - What the operation does, for log messages
+    /// * `operation` - Creates the future to run for each attempt
+    pub async fn run<T, F, Fut>(&self, description: &str, mut operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match operation().await {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt < self.max_attempts && is_retryable(&e) => {
+                    let delay = self.delay_for(attempt, retry_after(&e));
+                    warn!(
+                        "{description} failed (attempt {attempt}/{}), retrying in {:.1}s: {e:#}",
+                        self.max_attempts,
+                        delay.as_secs_f64()
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    return Err(if attempt > 1 {
+                        e.context(format!("{description} failed after {attempt} attempts"))
+                    } else {
+                        e
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::from_config(&RetryConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn fast_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy::from_config(&RetryConfig {
+            max_attempts,
+            initial_delay_ms: 1,
+            max_delay_ms: 5,
+        })
+    }
+
+    fn status_error(status: u16) -> anyhow::Error {
+        anyhow::anyhow!(HttpStatusError {
+            url: "https://example.com/photo.jpg".to_string(),
+            status,
+            retry_after: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_errors() -> Result<()> {
+        let attempts = AtomicU32::new(0);
+        let value = fast_policy(3)
+            .run("test", || async {
+                if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err(status_error(503))
+                } else {
+                    Ok(42)
+                }
+            })
+            .await?;
+
+        assert_eq!(value, 42);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_attempts() {
+        let attempts = AtomicU32::new(0);
+        let result: Result<()> = fast_policy(3)
+            .run("test", || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(status_error(429))
+            })
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_permanent_errors() {
+        let attempts = AtomicU32::new(0);
+        let result: Result<()> = fast_policy(3)
+            .run("test", || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(status_error(404))
+            })
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retries_rate_limits_but_not_missing_albums() {
+        let attempts = AtomicU32::new(0);
+        let result: Result<()> = fast_policy(3)
+            .run("test", || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(ICloudError::RateLimited { retry_after: None }.into())
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        let attempts = AtomicU32::new(0);
+        let result: Result<()> = fast_policy(3)
+            .run("test", || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(ICloudError::AlbumNotFound("gone".to_string()).into())
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_delay_for() {
+        let policy = RetryPolicy::from_config(&RetryConfig {
+            max_attempts: 5,
+            initial_delay_ms: 1000,
+            max_delay_ms: 10_000,
+        });
+
+        let first = policy.delay_for(1, None);
+        assert!(first >= Duration::from_millis(500) && first <= Duration::from_millis(1000));
+
+        let third = policy.delay_for(3, None);
+        assert!(third >= Duration::from_millis(2000) && third <= Duration::from_millis(4000));
+
+        // Capped at the maximum delay
+        assert!(policy.delay_for(10, None) <= Duration::from_secs(10));
+
+        // Retry-After wins, but is still capped
+        assert_eq!(
+            policy.delay_for(1, Some(Duration::from_secs(7))),
+            Duration::from_secs(7)
+        );
+        assert_eq!(
+            policy.delay_for(1, Some(Duration::from_secs(60))),
+            Duration::from_secs(10)
+        );
+    }
+}
@@ -0,0 +1,89 @@
+//! Graceful shutdown on Ctrl-C.
+//!
+//! The first Ctrl-C asks the syncers to stop starting new photos. Photos
+//! that are already being downloaded are finished and recorded, the index is
+//! saved, and the next sync picks up where this one stopped. A second Ctrl-C
+//! exits immediately; the journal still holds everything finished so far.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Exit code used when a sync was interrupted (128 + SIGINT)
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Shared flag telling the syncers to wind down
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+}
+
+impl Shutdown {
+    /// Creates a flag that hasn't been raised
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the syncers to stop starting new work
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether a shutdown was requested
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// Raises the flag on the first Ctrl-C and exits on the second
+    pub fn listen_for_ctrl_c(&self) {
+        let shutdown = self.clone();
+        tokio::spawn(async move {
+            while tokio::signal::ctrl_c().await.is_ok() {
+                if shutdown.is_requested() {
+                    eprintln!("\nInterrupted again, exiting now.");
+                    std::process::exit(INTERRUPTED_EXIT_CODE);
+                }
+                eprintln!(
+                    "\nInterrupted: finishing photos in progress and saving the index (Ctrl-C again to exit now)..."
+                );
+                shutdown.request();
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_flag_is_not_raised() {
+        assert!(!Shutdown::new().is_requested());
+        assert!(!Shutdown::default().is_requested());
+    }
+
+    #[test]
+    fn test_request_is_shared_between_clones() {
+        let shutdown = Shutdown::new();
+        let syncer_copy = shutdown.clone();
+        assert!(!syncer_copy.is_requested());
+
+        shutdown.request();
+        assert!(syncer_copy.is_requested());
+    }
+
+    #[test]
+    fn test_separate_flags_are_independent() {
+        let first = Shutdown::new();
+        let second = Shutdown::new();
+
+        first.request();
+        first.request();
+        assert!(first.is_requested());
+        assert!(!second.is_requested());
+    }
+
+    #[test]
+    fn test_interrupted_exit_code_follows_sigint() {
+        assert_eq!(INTERRUPTED_EXIT_CODE, 128 + 2);
+    }
+}
@@ -0,0 +1,516 @@
+//! Photo sources for icloud2hugo.
+//!
+//! A photo source produces an `Album` that the syncers turn into Hugo content.
+//! The `PhotoSource` trait has two implementations:
+//!
+//! - `ICloudSource` fetches an iCloud shared album
+//! - `LocalSource` reads a local folder of images, such as DSLR exports or
+//!   scanned film, with optional YAML sidecars for captions and dates
+//!
+//! `create_photo_source` picks the implementation from the configured URL:
+//! `http(s)://` URLs are iCloud albums, `file://` URLs and plain paths are
+//! local folders.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use log::{debug, info, warn};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+use crate::cache::ResponseCache;
+use crate::exif::extract_exif;
+use crate::icloud::{Album, Photo, fetch_album_with_options};
+use crate::index::PhotoIndex;
+use crate::retry::RetryPolicy;
+
+/// File extensions picked up from local folders, with their MIME types
+const LOCAL_EXTENSIONS: [(&str, &str); 8] = [
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("png", "image/png"),
+    ("heic", "image/heic"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("mp4", "video/mp4"),
+    ("mov", "video/quicktime"),
+];
+
+/// Options for fetching albums from their sources
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FetchOptions<'a> {
+    /// Photos synced so far; sources that can skip work for photos they
+    /// already know about (such as resolving iCloud asset URLs) use it
+    pub index: Option<&'a PhotoIndex>,
+    /// Where raw responses are saved, and read from when offline
+    pub cache: Option<&'a ResponseCache>,
+    /// Rebuild albums from the cache instead of fetching them
+    pub offline: bool,
+}
+
+/// A place photos can be synced from
+pub trait PhotoSource: Send + Sync {
+    /// Human-readable description of the source for logs
+    fn describe(&self) -> String;
+
+    /// Reads the source into an album
+    fn fetch_album<'a>(&'a self, options: FetchOptions<'a>) -> BoxFuture<'a, Result<Album>>;
+}
+
+/// An iCloud shared album
+pub struct ICloudSource {
+    /// Shared album URL
+    album_url: String,
+}
+
+impl ICloudSource {
+    /// Creates a source for the given shared album URL
+    pub fn new(album_url: String) -> Self {
+        Self { album_url }
+    }
+}
+
+impl PhotoSource for ICloudSource {
+    fn describe(&self) -> String {
+        format!("iCloud shared album {}", self.album_url)
+    }
+
+    fn fetch_album<'a>(&'a self, options: FetchOptions<'a>) -> BoxFuture<'a, Result<Album>> {
+        Box::pin(fetch_album_with_options(&self.album_url, options))
+    }
+}
+
+/// A local folder of images
+#[derive(Clone)]
+pub struct LocalSource {
+    /// Folder containing the images
+    dir: PathBuf,
+}
+
+/// Optional metadata for a local image, read from `<image file>.yaml`
+#[derive(Debug, Default, Deserialize)]
+struct Sidecar {
+    /// Caption for the photo
+    caption: Option<String>,
+    /// When the photo was taken, overriding the EXIF date
+    date: Option<DateTime<Utc>>,
+    /// Who took or contributed the photo
+    contributor: Option<String>,
+    /// Width of the photo in pixels
+    width: Option<u32>,
+    /// Height of the photo in pixels
+    height: Option<u32>,
+}
+
+impl LocalSource {
+    /// Creates a source for the given folder
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Reads the folder into an album named after the folder
+    ///
+    /// Reading and hashing every file blocks, so callers on the async
+    /// runtime run this on a blocking thread.
+    fn read_album(&self) -> Result<Album> {
+        let dir = fs::canonicalize(&self.dir)
+            .with_context(|| format!("Photo folder not found: {}", self.dir.display()))?;
+        info!("Reading local photo folder: {}", dir.display());
+
+        let name = dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "Local Photos".to_string());
+        let mut album = Album::new(name);
+
+        let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+            .with_context(|| format!("Failed to read photo folder {}", dir.display()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && mime_type_for(path).is_some())
+            .collect();
+        paths.sort();
+
+        let root = source_root(&self.dir);
+        for path in paths {
+            let relative = path.strip_prefix(&dir).unwrap_or(&path);
+            match read_local_photo(&path, &root, relative) {
+                Ok(photo) => {
+                    album.photos.insert(photo.guid.clone(), photo);
+                }
+                Err(e) => warn!("Skipping {}: {e:#}", path.display()),
+            }
+        }
+
+        info!("Found {} photos in {}", album.photos.len(), dir.display());
+        Ok(album)
+    }
+}
+
+impl PhotoSource for LocalSource {
+    fn describe(&self) -> String {
+        format!("local folder {}", self.dir.display())
+    }
+
+    fn fetch_album<'a>(&'a self, _options: FetchOptions<'a>) -> BoxFuture<'a, Result<Album>> {
+        // Local folders need no network, so they are read the same way offline
+        Box::pin(async move {
+            let source = self.clone();
+            tokio::task::spawn_blocking(move || source.read_album())
+                .await
+                .context("Reading the photo folder panicked")?
+        })
+    }
+}
+
+/// Returns the MIME type for a supported image or video file
+pub fn mime_type_for(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    LOCAL_EXTENSIONS
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, mime_type)| *mime_type)
+}
+
+/// Returns the extension a photo's file is written with, from its MIME type
+pub fn extension_for(mime_type: &str) -> &'static str {
+    match mime_type {
+        "image/png" => "png",
+        "image/heic" => "heic",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "video/mp4" => "mp4",
+        "video/quicktime" => "mov",
+        _ => "jpg",
+    }
+}
+
+/// Returns the source folder as configured, which local photo GUIDs are
+/// derived from
+///
+/// The configured path is used rather than the resolved one, so GUIDs don't
+/// change when the site is checked out somewhere else.
+fn source_root(dir: &Path) -> String {
+    let root = dir.to_string_lossy().replace('\\', "/");
+    match root.trim_end_matches('/') {
+        "" => root,
+        trimmed => trimmed.to_string(),
+    }
+}
+
+/// Builds a photo from a local image and its sidecar
+///
+/// The GUID is derived from the source folder and the image's path inside
+/// it, so it stays stable when the image is re-exported, and images with the
+/// same name in different folders don't collide. The checksum is derived
+/// from the content.
+fn read_local_photo(path: &Path, root: &str, relative: &Path) -> Result<Photo> {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .context("Image has no file name")?;
+    let mime_type = mime_type_for(path).unwrap_or("image/jpeg");
+    let extension = extension_for(mime_type);
+
+    let contents = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let checksum = format!("{:x}", md5::compute(&contents));
+    let key = format!("{root}/{}", relative.to_string_lossy().replace('\\', "/"));
+    let guid = format!("{:x}", md5::compute(key.as_bytes()));
+
+    let sidecar_path = path.with_file_name(format!("{file_name}.yaml"));
+    let sidecar = if sidecar_path.exists() {
+        let yaml = fs::read_to_string(&sidecar_path)
+            .with_context(|| format!("Failed to read sidecar {}", sidecar_path.display()))?;
+        serde_yaml::from_str(&yaml)
+            .with_context(|| format!("Failed to parse sidecar {}", sidecar_path.display()))?
+    } else {
+        Sidecar::default()
+    };
+
+    // Prefer the sidecar date, then the EXIF date, then the file's modification time
+    let created_at = match sidecar.date {
+        Some(date) => date,
+        None => match extract_exif(path).ok().and_then(|exif| exif.date_time) {
+            Some(date) => date,
+            None => fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .map(DateTime::<Utc>::from)
+                .unwrap_or_else(|_| Utc::now()),
+        },
+    };
+
+    // The image header has the real size; the sidecar can override it
+    let (width, height) = match imagesize::size(path) {
+        Ok(size) => (size.width as u32, size.height as u32),
+        Err(e) => {
+            if mime_type.starts_with("image/") {
+                debug!("Could not read the size of {}: {e}", path.display());
+            }
+            (0, 0)
+        }
+    };
+
+    let url = Url::from_file_path(path)
+        .map_err(|_| anyhow::anyhow!("Invalid photo path: {}", path.display()))?
+        .to_string();
+    debug!("Read local photo {file_name} as {guid}");
+
+    Ok(Photo {
+        filename: format!("{guid}.{extension}"),
+        guid,
+        caption: sidecar.caption,
+        created_at,
+        checksum: checksum.clone(),
+        url,
+        width: sidecar.width.unwrap_or(width),
+        height: sidecar.height.unwrap_or(height),
+        mime_type: mime_type.to_string(),
+        contributor: sidecar.contributor,
+        batch_guid: None,
+        batch_created_at: None,
+        asset_checksum: Some(checksum),
+        sources: Vec::new(),
+    })
+}
+
+/// Returns the local file a `file://` photo URL points to
+pub fn local_file_path(url: &str) -> Option<PathBuf> {
+    if !url.starts_with("file://") {
+        return None;
+    }
+    Url::parse(url).ok()?.to_file_path().ok()
+}
+
+/// Creates the photo source for a configured album URL
+pub fn create_photo_source(album_url: &str) -> Result<Box<dyn PhotoSource>> {
+    if album_url.starts_with("http://") || album_url.starts_with("https://") {
+        return Ok(Box::new(ICloudSource::new(album_url.to_string())));
+    }
+
+    let dir = match local_file_path(album_url) {
+        Some(dir) => dir,
+        None if album_url.starts_with("file://") => {
+            return Err(anyhow::anyhow!("Invalid file URL: {album_url}"));
+        }
+        None => PathBuf::from(album_url),
+    };
+    Ok(Box::new(LocalSource::new(dir)))
+}
+
+/// Fetches several sources and merges them into a single album
+///
+/// Each fetch is retried according to the retry policy. The merged album
+/// takes the name of the first source. Fetching fails if any of the sources
+/// cannot be read, since syncing a partial union would delete the photos of
+/// the missing source.
+pub async fn fetch_albums(
+    album_urls: &[String],
+    retry_policy: &RetryPolicy,
+    options: FetchOptions<'_>,
+) -> Result<Album> {
+    let mut merged: Option<Album> = None;
+
+    for album_url in album_urls {
+        let source = create_photo_source(album_url)?;
+        debug!("Fetching {}", source.describe());
+        let mut album = retry_policy
+            .run(&format!("Fetch of {}", source.describe()), || {
+                source.fetch_album(options)
+            })
+            .await
+            .with_context(|| format!("Failed to fetch album {album_url}"))?;
+
+        // Record where each photo came from
+        for photo in album.photos.values_mut() {
+            photo.sources = vec![album_url.clone()];
+        }
+
+        match merged.as_mut() {
+            Some(merged) => merged.merge(album),
+            None => merged = Some(album),
+        }
+    }
+
+    merged.ok_or_else(|| anyhow::anyhow!("No album URLs configured"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_local_source_reads_images_and_sidecars() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let dir = temp_dir.path().join("Scanned Film");
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("roll1-01.jpg"), "IMAGE ONE")?;
+        fs::write(dir.join("roll1-02.PNG"), "IMAGE TWO")?;
+        fs::write(dir.join("notes.txt"), "not a photo")?;
+        fs::write(
+            dir.join("roll1-01.jpg.yaml"),
+            "caption: Lake at dawn\ndate: 1994-06-01T06:00:00Z\ncontributor: Grandpa\nwidth: 3000\nheight: 2000\n",
+        )?;
+
+        let source = create_photo_source(&dir.display().to_string())?;
+        let album = source.fetch_album(FetchOptions::default()).await?;
+
+        assert_eq!(album.name, "Scanned Film");
+        assert_eq!(album.photos.len(), 2);
+
+        let photo = album.photos.values().find(|p| p.caption.is_some()).unwrap();
+        assert_eq!(photo.caption, Some("Lake at dawn".to_string()));
+        assert_eq!(photo.created_at.to_rfc3339(), "1994-06-01T06:00:00+00:00");
+        assert_eq!(photo.contributor, Some("Grandpa".to_string()));
+        assert_eq!((photo.width, photo.height), (3000, 2000));
+        assert!(photo.filename.ends_with(".jpg"));
+        assert_eq!(
+            local_file_path(&photo.url),
+            Some(fs::canonicalize(dir.join("roll1-01.jpg"))?)
+        );
+
+        let png = album.photos.values().find(|p| p.caption.is_none()).unwrap();
+        assert_eq!(png.mime_type, "image/png");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_local_source_guid_is_stable() -> Result<()> {
+        let temp_dir = tempdir()?;
+        fs::write(temp_dir.path().join("a.jpg"), "FIRST EXPORT")?;
+        let url = Url::from_directory_path(temp_dir.path())
+            .unwrap()
+            .to_string();
+
+        let first = fetch_albums(
+            std::slice::from_ref(&url),
+            &RetryPolicy::default(),
+            FetchOptions::default(),
+        )
+        .await?;
+        fs::write(temp_dir.path().join("a.jpg"), "SECOND EXPORT")?;
+        let second = fetch_albums(
+            std::slice::from_ref(&url),
+            &RetryPolicy::default(),
+            FetchOptions::default(),
+        )
+        .await?;
+
+        let first_photo = first.photos.values().next().unwrap();
+        let second_photo = second.photos.values().next().unwrap();
+        assert_eq!(first_photo.guid, second_photo.guid);
+        assert_ne!(first_photo.checksum, second_photo.checksum);
+        assert_eq!(second_photo.sources, vec![url]);
+
+        Ok(())
+    }
+
+    /// Returns the header of a PNG image of the given size, which is all
+    /// that is read to find its dimensions
+    fn png_header(width: u32, height: u32) -> Vec<u8> {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend(width.to_be_bytes());
+        png.extend(height.to_be_bytes());
+        png.extend([8, 6, 0, 0, 0, 0, 0, 0, 0]);
+        png
+    }
+
+    #[tokio::test]
+    async fn test_local_source_reads_image_size() -> Result<()> {
+        let temp_dir = tempdir()?;
+        fs::write(temp_dir.path().join("scan.png"), png_header(4000, 3000))?;
+        fs::write(temp_dir.path().join("cropped.png"), png_header(4000, 3000))?;
+        fs::write(
+            temp_dir.path().join("cropped.png.yaml"),
+            "width: 1200\nheight: 800\n",
+        )?;
+
+        let source = create_photo_source(&temp_dir.path().display().to_string())?;
+        let album = source.fetch_album(FetchOptions::default()).await?;
+
+        let size = |name: &str| {
+            let photo = album
+                .photos
+                .values()
+                .find(|photo| photo.url.ends_with(name))
+                .unwrap();
+            (photo.width, photo.height)
+        };
+        assert_eq!(size("scan.png"), (4000, 3000));
+        // The sidecar overrides the header
+        assert_eq!(size("cropped.png"), (1200, 800));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_local_source_keeps_quicktime_videos() -> Result<()> {
+        let temp_dir = tempdir()?;
+        fs::write(temp_dir.path().join("clip.MOV"), "MOVIE")?;
+
+        let source = create_photo_source(&temp_dir.path().display().to_string())?;
+        let album = source.fetch_album(FetchOptions::default()).await?;
+
+        let video = album.photos.values().next().unwrap();
+        assert_eq!(video.mime_type, "video/quicktime");
+        assert!(video.filename.ends_with(".mov"));
+        assert_eq!((video.width, video.height), (0, 0));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_same_file_names_in_two_sources() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let mut urls = Vec::new();
+        for (folder, contents) in [("dslr", "DSLR"), ("scans", "SCAN")] {
+            let dir = temp_dir.path().join(folder);
+            fs::create_dir_all(&dir)?;
+            fs::write(dir.join("DSC_0001.jpg"), contents)?;
+            urls.push(dir.display().to_string());
+        }
+
+        let album = fetch_albums(&urls, &RetryPolicy::default(), FetchOptions::default()).await?;
+
+        assert_eq!(album.photos.len(), 2);
+        let mut sources: Vec<&String> = album
+            .photos
+            .values()
+            .flat_map(|photo| &photo.sources)
+            .collect();
+        sources.sort();
+        assert_eq!(sources, vec![&urls[0], &urls[1]]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_source_root() {
+        assert_eq!(source_root(Path::new("exports/dslr/")), "exports/dslr");
+        assert_eq!(source_root(Path::new("/")), "/");
+    }
+
+    #[test]
+    fn test_create_photo_source() -> Result<()> {
+        let icloud = create_photo_source("https://www.icloud.com/sharedalbum/#B0abc")?;
+        assert!(icloud.describe().starts_with("iCloud shared album"));
+
+        let local = create_photo_source("exports/dslr")?;
+        assert_eq!(local.describe(), "local folder exports/dslr");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_missing_local_folder_fails() {
+        let result = fetch_albums(
+            &["/nonexistent/photos".to_string()],
+            &RetryPolicy::default(),
+            FetchOptions::default(),
+        )
+        .await;
+        assert!(result.is_err());
+    }
+}
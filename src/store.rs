@@ -0,0 +1,525 @@
+//! Storage backends for the photo index.
+//!
+//! By default every output keeps its index in the YAML data file that Hugo
+//! reads. Large sites can keep all indexes in one SQLite database instead,
+//! which only writes the rows that changed and can be queried directly. The
+//! YAML data file is then written as an export for Hugo, only when the index
+//! changed.
+//!
+//! Both backends journal changes next to the data file (see the `journal`
+//! module) and run the same schema migrations when loading.
+//!
+//! To find the rows that changed, every save reads all of the output's rows
+//! from each table and compares them with the index. That costs a full read
+//! per save, but only the changed rows are written.
+
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OptionalExtension, Transaction, params};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::index::{PhotoIndex, relative_to};
+
+/// Where an output's photo index is stored
+pub trait IndexStore: Send + Sync {
+    /// Loads the index, replaying changes journaled by an interrupted sync
+    fn load(&self) -> Result<PhotoIndex>;
+
+    /// Saves the index
+    fn save(&self, index: &PhotoIndex) -> Result<()>;
+
+    /// Writes the YAML data file Hugo reads
+    fn export(&self, index: &PhotoIndex) -> Result<()>;
+
+    /// The YAML data file of the output
+    fn data_file(&self) -> &Path;
+
+    /// Short description of where the index lives, for messages
+    fn describe(&self) -> String;
+}
+
+/// Keeps the index in the output's YAML data file
+#[derive(Debug, Clone)]
+pub struct YamlStore {
+    data_file: PathBuf,
+    site_root: PathBuf,
+}
+
+impl YamlStore {
+    /// Creates a store for the given data file of the site at `site_root`
+    pub fn new(data_file: PathBuf, site_root: PathBuf) -> Self {
+        Self {
+            data_file,
+            site_root,
+        }
+    }
+}
+
+impl IndexStore for YamlStore {
+    fn load(&self) -> Result<PhotoIndex> {
+        PhotoIndex::load(&self.data_file, &self.site_root)
+    }
+
+    fn save(&self, index: &PhotoIndex) -> Result<()> {
+        index.save(&self.data_file)
+    }
+
+    fn export(&self, _index: &PhotoIndex) -> Result<()> {
+        // Saving already wrote the data file
+        Ok(())
+    }
+
+    fn data_file(&self) -> &Path {
+        &self.data_file
+    }
+
+    fn describe(&self) -> String {
+        self.data_file.display().to_string()
+    }
+}
+
+/// Keeps the index in an SQLite database shared by all outputs
+///
+/// Each output's rows are keyed by the path of its data file in the site.
+#[derive(Debug)]
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+    database: PathBuf,
+    data_file: PathBuf,
+    site_root: PathBuf,
+    output: String,
+}
+
+/// Tables holding an output's photos, galleries, batches and filtered photos
+/// as JSON rows
+const ROW_TABLES: [&str; 4] = ["photos", "galleries", "batches", "filtered"];
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS indexes (
+    output TEXT PRIMARY KEY,
+    schema_version INTEGER NOT NULL,
+    last_updated TEXT NOT NULL,
+    stale TEXT
+);
+CREATE TABLE IF NOT EXISTS photos (
+    output TEXT NOT NULL,
+    id TEXT NOT NULL,
+    data TEXT NOT NULL,
+    filename TEXT GENERATED ALWAYS AS (json_extract(data, '$.filename')) VIRTUAL,
+    caption TEXT GENERATED ALWAYS AS (json_extract(data, '$.caption')) VIRTUAL,
+    created_at TEXT GENERATED ALWAYS AS (json_extract(data, '$.created_at')) VIRTUAL,
+    checksum TEXT GENERATED ALWAYS AS (json_extract(data, '$.checksum')) VIRTUAL,
+    local_path TEXT GENERATED ALWAYS AS (json_extract(data, '$.local_path')) VIRTUAL,
+    PRIMARY KEY (output, id)
+);
+CREATE TABLE IF NOT EXISTS galleries (
+    output TEXT NOT NULL,
+    id TEXT NOT NULL,
+    data TEXT NOT NULL,
+    PRIMARY KEY (output, id)
+);
+CREATE TABLE IF NOT EXISTS batches (
+    output TEXT NOT NULL,
+    id TEXT NOT NULL,
+    data TEXT NOT NULL,
+    PRIMARY KEY (output, id)
+);
+CREATE TABLE IF NOT EXISTS filtered (
+    output TEXT NOT NULL,
+    id TEXT NOT NULL,
+    data TEXT NOT NULL,
+    PRIMARY KEY (output, id)
+);
+";
+
+impl SqliteStore {
+    /// Opens (or creates) the database and selects the output with the given
+    /// data file of the site at `site_root`
+    pub fn open(database: &Path, data_file: &Path, site_root: &Path) -> Result<Self> {
+        if let Some(parent) = database.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+
+        let connection = Connection::open(database)
+            .with_context(|| format!("Failed to open index database {}", database.display()))?;
+        connection
+            .execute_batch(SCHEMA)
+            .with_context(|| format!("Failed to set up index database {}", database.display()))?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+            database: database.to_path_buf(),
+            data_file: data_file.to_path_buf(),
+            site_root: site_root.to_path_buf(),
+            output: relative_to(data_file, site_root)
+                .to_string_lossy()
+                .to_string(),
+        })
+    }
+
+    /// Reads the rows of one table as a JSON object keyed by ID
+    fn read_rows(&self, connection: &Connection, table: &str) -> Result<serde_json::Value> {
+        let mut statement =
+            connection.prepare(&format!("SELECT id, data FROM {table} WHERE output = ?1"))?;
+        let rows = statement.query_map(params![self.output], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+
+        let mut object = serde_json::Map::new();
+        for row in rows {
+            let (id, data) = row?;
+            let value = serde_json::from_str(&data)
+                .with_context(|| format!("Failed to parse {table} row {id}"))?;
+            object.insert(id, value);
+        }
+        Ok(serde_json::Value::Object(object))
+    }
+
+    /// Brings one table in line with `rows`, only writing what changed
+    fn write_rows<T: Serialize>(
+        &self,
+        transaction: &Transaction,
+        table: &str,
+        rows: impl Iterator<Item = (String, T)>,
+    ) -> Result<()> {
+        let mut existing: HashMap<String, String> = transaction
+            .prepare(&format!("SELECT id, data FROM {table} WHERE output = ?1"))?
+            .query_map(params![self.output], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let mut upsert = transaction.prepare(&format!(
+            "INSERT OR REPLACE INTO {table} (output, id, data) VALUES (?1, ?2, ?3)"
+        ))?;
+        for (id, row) in rows {
+            let data = serde_json::to_string(&row)?;
+            if existing.remove(&id).as_ref() != Some(&data) {
+                upsert.execute(params![self.output, id, data])?;
+            }
+        }
+
+        let mut delete = transaction.prepare(&format!(
+            "DELETE FROM {table} WHERE output = ?1 AND id = ?2"
+        ))?;
+        for id in existing.into_keys() {
+            delete.execute(params![self.output, id])?;
+        }
+
+        Ok(())
+    }
+}
+
+impl IndexStore for SqliteStore {
+    fn load(&self) -> Result<PhotoIndex> {
+        let connection = self.connection.lock().unwrap();
+
+        let header: Option<(u32, String, Option<String>)> = connection
+            .query_row(
+                "SELECT schema_version, last_updated, stale FROM indexes WHERE output = ?1",
+                params![self.output],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?;
+
+        // The first time an output uses the database, its data file is imported
+        let Some((schema_version, last_updated, stale)) = header else {
+            log::info!(
+                "Importing {} into {}",
+                self.data_file.display(),
+                self.database.display()
+            );
+            return PhotoIndex::load(&self.data_file, &self.site_root);
+        };
+
+        let stale = match stale {
+            Some(stale) => serde_json::from_str(&stale)?,
+            None => serde_json::Value::Null,
+        };
+        let mut document = serde_json::json!({
+            "schema_version": schema_version,
+            "last_updated": last_updated,
+            "stale": stale,
+        });
+        for table in ROW_TABLES {
+            document[table] = self.read_rows(&connection, table)?;
+        }
+
+        let mut index = PhotoIndex::from_value(serde_yaml::to_value(document)?, &self.site_root)
+            .with_context(|| format!("Failed to load {} from the database", self.output))?;
+        index.replay_journal(&self.data_file)?;
+        Ok(index)
+    }
+
+    fn save(&self, index: &PhotoIndex) -> Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+
+        let stale = index
+            .stale
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
+        transaction.execute(
+            "INSERT OR REPLACE INTO indexes (output, schema_version, last_updated, stale)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                self.output,
+                index.schema_version,
+                serde_json::to_value(index.last_updated)?
+                    .as_str()
+                    .unwrap_or_default(),
+                stale
+            ],
+        )?;
+        self.write_rows(
+            &transaction,
+            "photos",
+            index.photos.iter().map(|(k, v)| (k.clone(), v)),
+        )?;
+        self.write_rows(
+            &transaction,
+            "galleries",
+            index.galleries.iter().map(|(k, v)| (k.clone(), v)),
+        )?;
+        self.write_rows(
+            &transaction,
+            "batches",
+            index.batches.iter().map(|(k, v)| (k.clone(), v)),
+        )?;
+        self.write_rows(
+            &transaction,
+            "filtered",
+            index.filtered.iter().map(|(k, v)| (k.clone(), v)),
+        )?;
+
+        transaction
+            .commit()
+            .with_context(|| format!("Failed to save {} to the database", self.output))?;
+
+        index.checkpoint(&self.data_file)
+    }
+
+    fn export(&self, index: &PhotoIndex) -> Result<()> {
+        index.save(&self.data_file)
+    }
+
+    fn data_file(&self) -> &Path {
+        &self.data_file
+    }
+
+    fn describe(&self) -> String {
+        format!("{} (in {})", self.output, self.database.display())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::{Batch, FilteredPhoto, IndexedPhoto};
+    use chrono::Utc;
+    use tempfile::tempdir;
+
+    fn create_test_photo(guid: &str) -> IndexedPhoto {
+        IndexedPhoto::new(
+            guid.to_string(),
+            format!("{guid}.jpg"),
+            Some(format!("Caption for {guid}")),
+            Utc::now(),
+            format!("checksum_{guid}"),
+            format!("https://example.com/{guid}.jpg"),
+            800,
+            600,
+            PathBuf::from(format!("content/photostream/{guid}/original.jpg")),
+            Path::new(""),
+        )
+    }
+
+    #[test]
+    fn test_yaml_round_trip() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let data_file = temp_dir.path().join("data/index.yaml");
+        let store = YamlStore::new(data_file.clone(), temp_dir.path().to_path_buf());
+        assert_eq!(store.describe(), data_file.display().to_string());
+
+        let mut index = PhotoIndex::new();
+        index.add_or_update_photo(create_test_photo("photo1"));
+        store.save(&index)?;
+        assert!(data_file.exists());
+        assert!(store.load()?.get_photo("photo1").is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_first_load_imports_data_file() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let database = temp_dir.path().join("index.db");
+        let data_file = temp_dir.path().join("data/photos/index.yaml");
+        let mut index = PhotoIndex::new();
+        index.add_or_update_photo(create_test_photo("photo1"));
+        index.save(&data_file)?;
+
+        let store = SqliteStore::open(&database, &data_file, temp_dir.path())?;
+        assert_eq!(
+            store.describe(),
+            format!("data/photos/index.yaml (in {})", database.display())
+        );
+        assert_eq!(store.load()?.photo_count(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sqlite_round_trip() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let database = temp_dir.path().join("index.db");
+        let data_file = temp_dir.path().join("data/photos/index.yaml");
+        let store = SqliteStore::open(&database, &data_file, temp_dir.path())?;
+
+        let mut index = PhotoIndex::new();
+        index.add_or_update_photo(create_test_photo("photo1"));
+        store.save(&index)?;
+
+        index.add_or_update_photo(create_test_photo("photo2"));
+        index.remove_photo("photo1");
+        index.add_or_update_batch(Batch::new(
+            "batch1".to_string(),
+            "batch-1".to_string(),
+            Utc::now(),
+        ));
+        index.filtered.insert(
+            "photo3".to_string(),
+            FilteredPhoto {
+                checksum: "checksum_photo3".to_string(),
+                has_gps: false,
+            },
+        );
+        index.mark_stale("Album not found".to_string());
+        store.save(&index)?;
+
+        let loaded = SqliteStore::open(&database, &data_file, temp_dir.path())?.load()?;
+        assert_eq!(loaded.photo_count(), 1);
+        assert!(loaded.get_photo("photo2").is_some());
+        assert_eq!(loaded.batch_count(), 1);
+        assert_eq!(loaded.filtered, index.filtered);
+        assert_eq!(loaded.stale, index.stale);
+        assert_eq!(loaded.last_updated, index.last_updated);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_outputs_share_database() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let database = temp_dir.path().join("index.db");
+        let store = SqliteStore::open(
+            &database,
+            &temp_dir.path().join("photos.yaml"),
+            temp_dir.path(),
+        )?;
+        let mut index = PhotoIndex::new();
+        index.add_or_update_photo(create_test_photo("photo1"));
+        store.save(&index)?;
+
+        // Another output in the same database doesn't see these rows
+        let other = SqliteStore::open(
+            &database,
+            &temp_dir.path().join("other.yaml"),
+            temp_dir.path(),
+        )?;
+        assert_eq!(other.load()?.photo_count(), 0);
+        other.save(&PhotoIndex::new())?;
+        assert_eq!(store.load()?.photo_count(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_photos_can_be_queried() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let store = SqliteStore::open(
+            &temp_dir.path().join("index.db"),
+            &temp_dir.path().join("index.yaml"),
+            temp_dir.path(),
+        )?;
+        let mut index = PhotoIndex::new();
+        index.add_or_update_photo(create_test_photo("photo2"));
+        store.save(&index)?;
+
+        let caption: String = store.connection.lock().unwrap().query_row(
+            "SELECT caption FROM photos WHERE id = 'photo2'",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(caption, "Caption for photo2");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_data_file_is_only_written_by_export() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let data_file = temp_dir.path().join("data/index.yaml");
+        let store = SqliteStore::open(
+            &temp_dir.path().join("index.db"),
+            &data_file,
+            temp_dir.path(),
+        )?;
+        let mut index = PhotoIndex::new();
+        index.add_or_update_photo(create_test_photo("photo1"));
+
+        store.save(&index)?;
+        assert!(!data_file.exists());
+        store.export(&index)?;
+        assert!(
+            PhotoIndex::load(&data_file, temp_dir.path())?
+                .get_photo("photo1")
+                .is_some()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_damaged_row_fails_to_load() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let store = SqliteStore::open(
+            &temp_dir.path().join("index.db"),
+            &temp_dir.path().join("index.yaml"),
+            temp_dir.path(),
+        )?;
+        let mut index = PhotoIndex::new();
+        index.add_or_update_photo(create_test_photo("photo1"));
+        store.save(&index)?;
+        store.connection.lock().unwrap().execute(
+            "UPDATE photos SET data = '{\"guid\": 1}' WHERE id = 'photo1'",
+            [],
+        )?;
+
+        let err = store.load().unwrap_err();
+        assert!(format!("{err:#}").contains("from the database"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_unusable_database_fails() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let database = temp_dir.path().join("index.db");
+        std::fs::create_dir(&database)?;
+
+        let err = SqliteStore::open(
+            &database,
+            &temp_dir.path().join("index.yaml"),
+            temp_dir.path(),
+        )
+        .unwrap_err();
+        assert!(format!("{err:#}").contains("index database"));
+
+        Ok(())
+    }
+}
@@ -11,19 +11,73 @@
 //! `SyncResult` enum tracks the status of each photo's synchronization.
 
 use anyhow::{Context, Result};
-use futures::future::join_all;
 use log::{debug, info, warn};
-use reqwest::Client;
 use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use tokio::fs as tokio_fs;
-use tokio::task::{self, JoinSet};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 
+use crate::config::DownloadConfig;
+use crate::download::Downloader;
 use crate::exif::extract_exif;
+use crate::frontmatter::{quote, write_page};
 use crate::geocode::create_geocoding_service;
+use crate::hashtags;
 use crate::icloud::{Album, Photo};
-use crate::index::{IndexedPhoto, PhotoIndex};
+use crate::index::{Approval, IndexedPhoto, PhotoIndex};
+use crate::overrides::{Overrides, PhotoView};
+use crate::retry::RetryPolicy;
+use crate::shutdown::Shutdown;
+use crate::store::{IndexStore, YamlStore};
+
+/// Frontmatter keys of a photo's page that syncs own; other keys are kept
+const PAGE_KEYS: &[&str] = &[
+    "title",
+    "date",
+    "guid",
+    "original_filename",
+    "width",
+    "height",
+    "mime_type",
+    "contributor",
+    "camera_make",
+    "camera_model",
+    "exif_date",
+    "original_latitude",
+    "original_longitude",
+    "latitude",
+    "longitude",
+    "iso",
+    "exposure_time",
+    "f_number",
+    "focal_length",
+    "location",
+    "city",
+    "state",
+    "country",
+    "alt",
+];
+
+/// Adds the frontmatter fields that come from a photo's overrides and
+/// approval
+///
+/// Tags, written to `taxonomy`, and `draft` are only written for some
+/// photos, so they're passed to `write_page` as optional keys: the page
+/// records when a sync wrote them, and they can still be set by hand
+/// otherwise.
+fn push_override_fields(view: &PhotoView, taxonomy: &str, frontmatter: &mut String) {
+    if let Some(ref alt) = view.alt {
+        frontmatter.push_str(&format!("alt: {}\n", quote(alt)));
+    }
+    if let Some(ref tags) = view.tags {
+        hashtags::push_terms(frontmatter, taxonomy, tags);
+    }
+    if view.hidden {
+        frontmatter.push_str("draft: true\n");
+    }
+}
 
 /// Format a photo title using date, location, and camera information
 pub fn format_photo_title(photo: &IndexedPhoto) -> String {
@@ -61,13 +115,25 @@ pub fn format_photo_title(photo: &IndexedPhoto) -> String {
 
 /// Responsible for syncing photos from iCloud to the local filesystem
 pub struct Syncer {
-    /// HTTP client for downloading photos
-    client: Client,
+    /// Downloads photos, retrying transient failures
+    downloader: Downloader,
     /// Base directory for storing photos
     content_dir: PathBuf,
-    /// Path to the index file
+    /// Where the photo index is saved
     #[allow(dead_code)]
-    index_path: PathBuf,
+    store: Arc<dyn IndexStore>,
+    /// Re-render pages from files on disk without downloading or deleting
+    offline: bool,
+    /// Number of photos processed at the same time
+    concurrency: usize,
+    /// Stops new photos from being started when the sync is interrupted
+    shutdown: Shutdown,
+    /// Per-photo overrides applied when pages are rendered
+    overrides: Arc<Overrides>,
+    /// New photos are drafts until they're approved
+    require_approval: bool,
+    /// Directory of the Hugo site, which indexed photo paths are relative to
+    site_root: PathBuf,
 }
 
 /// Result of a photo sync operation
@@ -77,6 +143,8 @@ pub enum SyncResult {
     Added(#[allow(dead_code)] String),
     /// Photo was updated (already existed but changed)
     Updated(#[allow(dead_code)] String),
+    /// Only the photo's metadata changed, so its page was re-rendered without downloading
+    MetadataUpdated(#[allow(dead_code)] String),
     /// Photo was already up to date (no changes)
     Unchanged(#[allow(dead_code)] String),
     /// Photo was deleted (no longer in remote album)
@@ -87,62 +155,20 @@ pub enum SyncResult {
 
 /// Helper struct for task-local operations
 struct TaskSyncer {
-    client: Client,
+    downloader: Downloader,
     content_dir: PathBuf,
+    site_root: PathBuf,
+    overrides: Arc<Overrides>,
 }
 
 impl TaskSyncer {
-    /// Downloads a photo from its URL (task-local version)
-    async fn download_photo(&self, photo: &Photo, path: &Path) -> Result<()> {
-        // For tests, create a placeholder file instead of actually downloading
-        if cfg!(test) {
-            // Create a placeholder image for tests only
-            tokio_fs::write(path, "PLACEHOLDER IMAGE CONTENT")
-                .await
-                .with_context(|| {
-                    format!("Failed to write test placeholder to {}", path.display())
-                })?;
-            return Ok(());
-        }
-
-        // Check for test URLs explicitly - looking for exact test domains rather than a substring
-        if photo.url.starts_with("https://test.example/")
-            || photo.url.starts_with("https://example.com/")
-            || photo.url.starts_with("http://example.com/")
-        {
-            // Create a placeholder for test URLs
-            tokio_fs::write(path, "PLACEHOLDER TEST URL IMAGE CONTENT")
-                .await
-                .with_context(|| {
-                    format!("Failed to write test URL placeholder to {}", path.display())
-                })?;
-            return Ok(());
-        }
-
-        // Otherwise, download the actual image
-        let response = self
-            .client
-            .get(&photo.url)
-            .send()
-            .await
-            .with_context(|| format!("Failed to GET photo from {}", photo.url))?;
-
-        let bytes = response
-            .bytes()
-            .await
-            .context("Failed to read photo bytes")?;
-
-        tokio_fs::write(path, bytes)
-            .await
-            .with_context(|| format!("Failed to write photo to {}", path.display()))?;
-
-        Ok(())
-    }
-
     /// Creates an index.md file with frontmatter including EXIF data (task-local version)
     async fn create_index_md_with_exif(&self, photo: &IndexedPhoto, path: &Path) -> Result<()> {
-        // Generate the photo title using date, location, and camera info
-        let title = format_photo_title(photo);
+        // Apply the photo's overrides; the title is made from the date,
+        // location and camera info unless it's overridden
+        let view = self.overrides.apply(photo);
+        let photo = view.photo.as_ref();
+        let title = &view.title;
 
         // Build the frontmatter with EXIF data if available
         let mut frontmatter = format!(
@@ -155,22 +181,26 @@ width: {}
 height: {}
 mime_type: {}
 ",
-            title,
+            quote(title),
             photo.created_at.format("%Y-%m-%dT%H:%M:%S%z"),
             photo.guid,
-            photo.filename,
+            quote(&photo.filename),
             photo.width,
             photo.height,
             photo.mime_type,
         );
 
+        if let Some(ref contributor) = photo.contributor {
+            frontmatter.push_str(&format!("contributor: {}\n", quote(contributor)));
+        }
+
         // Add EXIF data if available
         if let Some(ref make) = photo.camera_make {
-            frontmatter.push_str(&format!("camera_make: {make}\n"));
+            frontmatter.push_str(&format!("camera_make: {}\n", quote(make)));
         }
 
         if let Some(ref model) = photo.camera_model {
-            frontmatter.push_str(&format!("camera_model: {model}\n"));
+            frontmatter.push_str(&format!("camera_model: {}\n", quote(model)));
         }
 
         if let Some(exif_dt) = photo.exif_date_time {
@@ -216,26 +246,32 @@ mime_type: {}
 
         // Add location data if available
         if let Some(ref location) = photo.location {
-            frontmatter.push_str(&format!("location: {}\n", location.formatted_address));
+            frontmatter.push_str(&format!(
+                "location: {}\n",
+                quote(&location.formatted_address)
+            ));
 
             if let Some(ref city) = location.city {
-                frontmatter.push_str(&format!("city: {city}\n"));
+                frontmatter.push_str(&format!("city: {}\n", quote(city)));
             }
 
             if let Some(ref state) = location.state {
-                frontmatter.push_str(&format!("state: {state}\n"));
+                frontmatter.push_str(&format!("state: {}\n", quote(state)));
             }
 
             if let Some(ref country) = location.country {
-                frontmatter.push_str(&format!("country: {country}\n"));
+                frontmatter.push_str(&format!("country: {}\n", quote(country)));
             }
         }
 
+        let taxonomy = self.overrides.taxonomy().unwrap_or("tags");
+        push_override_fields(&view, taxonomy, &mut frontmatter);
+
         // Close frontmatter and add content
         frontmatter.push_str("---\n\n");
         frontmatter.push_str(&photo.caption.clone().unwrap_or_default());
 
-        tokio_fs::write(path, frontmatter)
+        write_page(path, &frontmatter, PAGE_KEYS, &[taxonomy, "draft"])
             .await
             .with_context(|| format!("Failed to write index.md to {}", path.display()))
     }
@@ -245,16 +281,76 @@ impl Syncer {
     /// Creates a new syncer
     pub fn new(content_dir: PathBuf, index_path: PathBuf) -> Self {
         Self {
-            client: Client::new(),
+            downloader: Downloader::default(),
             content_dir,
-            index_path,
+            store: Arc::new(YamlStore::new(index_path, PathBuf::new())),
+            offline: false,
+            concurrency: DownloadConfig::default().concurrency(),
+            shutdown: Shutdown::default(),
+            overrides: Arc::default(),
+            require_approval: false,
+            site_root: PathBuf::new(),
         }
     }
 
+    /// Sets the retry policy used for downloads
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.downloader = self.downloader.with_retry_policy(retry_policy);
+        self
+    }
+
+    /// Sets how many photos are processed at once and the bandwidth cap
+    pub fn with_download_config(mut self, config: &DownloadConfig) -> Self {
+        self.concurrency = config.concurrency();
+        self.downloader = self
+            .downloader
+            .with_bandwidth_limit(config.max_bytes_per_second);
+        self
+    }
+
+    /// Sets whether to re-render every page from the files already on disk
+    /// instead of downloading anything or deleting photos
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self.downloader = self.downloader.with_offline(offline);
+        self
+    }
+
+    /// Sets the flag that tells the syncer to stop starting new photos
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Sets where the photo index is saved, instead of the YAML index file
+    pub fn with_index_store(mut self, store: Arc<dyn IndexStore>) -> Self {
+        self.store = store;
+        self
+    }
+
+    /// Sets the per-photo overrides applied when pages are rendered
+    pub fn with_overrides(mut self, overrides: Arc<Overrides>) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// Sets whether new photos are drafts until they're approved
+    pub fn with_require_approval(mut self, require_approval: bool) -> Self {
+        self.require_approval = require_approval;
+        self
+    }
+
+    /// Sets the directory of the Hugo site, which indexed photo paths are
+    /// stored relative to
+    pub fn with_site_root(mut self, site_root: PathBuf) -> Self {
+        self.site_root = site_root;
+        self
+    }
+
     /// Saves the photo index
     #[allow(dead_code)]
     pub fn save_index(&self, index: &PhotoIndex) -> Result<()> {
-        index.save(&self.index_path)
+        self.store.save(index)
     }
 
     /// Syncs photos from the remote album to the local filesystem,
@@ -272,12 +368,16 @@ impl Syncer {
         // Keep track of remote photo IDs
         let remote_guids: HashSet<&String> = album.photos.keys().collect();
 
-        // Find photos to delete (in index but not in remote album)
+        // Find photos to delete (in index but not in remote album). Offline
+        // syncs work from a saved album, so they never delete anything, and
+        // rejected photos stay in the index so they're never added again.
         let photos_to_delete: Vec<_> = index
             .photos
-            .keys()
-            .filter(|guid| !remote_guids.contains(guid))
-            .cloned()
+            .values()
+            .filter(|photo| {
+                !self.offline && !remote_guids.contains(&photo.guid) && !photo.is_rejected()
+            })
+            .map(|photo| photo.guid.clone())
             .collect();
 
         // Delete photos concurrently
@@ -300,6 +400,9 @@ impl Syncer {
     }
 
     /// Process deletions concurrently
+    ///
+    /// Each deletion is recorded in the index as soon as it finishes, so an
+    /// interrupted sync doesn't lose track of what was already removed.
     async fn process_deletions(
         &self,
         guids_to_delete: &[String],
@@ -309,57 +412,49 @@ impl Syncer {
             return Ok(Vec::new());
         }
 
-        let concurrent_limit = 10; // Limit concurrent operations
+        let semaphore = Arc::new(Semaphore::new(self.concurrency));
         let mut tasks = JoinSet::new();
-        let results = Arc::new(Mutex::new(Vec::new()));
 
         // Collect photos to delete before starting tasks
         let guids_to_delete = guids_to_delete.to_vec(); // Clone to own the data
 
-        // Process deletions in batches
+        // Every deletion gets a task, the semaphore limits how many run at once
         for guid in guids_to_delete {
             let content_dir = self.content_dir.clone();
-            let results_clone = Arc::clone(&results);
+            let semaphore = Arc::clone(&semaphore);
+            let shutdown = self.shutdown.clone();
 
             tasks.spawn(async move {
-                let result = Self::delete_photo_task(&guid, &content_dir).await;
-
-                // Store the result
-                let sync_result = match result {
-                    Ok(_) => SyncResult::Deleted(guid.clone()),
-                    Err(e) => {
-                        SyncResult::Failed(guid.clone(), format!("Failed to delete photo: {e}"))
-                    }
-                };
+                let _permit = semaphore
+                    .acquire_owned()
+                    .await
+                    .expect("Semaphore is never closed");
+
+                // Don't start anything new once a shutdown was requested
+                if shutdown.is_requested() {
+                    return None;
+                }
 
-                let mut results_guard = results_clone.lock().unwrap();
-                results_guard.push((guid, sync_result));
+                let result = Self::delete_photo_task(&guid, &content_dir).await;
+                Some(match result {
+                    Ok(_) => SyncResult::Deleted(guid),
+                    Err(e) => SyncResult::Failed(guid, format!("Failed to delete photo: {e}")),
+                })
             });
-
-            // Limit concurrent tasks
-            if tasks.len() >= concurrent_limit {
-                tasks.join_next().await;
-            }
-        }
-
-        // Wait for all remaining tasks to complete
-        while let Some(res) = tasks.join_next().await {
-            res?; // Propagate any panics
         }
 
-        // Get all results
-        let task_results = Arc::try_unwrap(results)
-            .expect("All tasks should be completed, but reference still exists")
-            .into_inner()
-            .expect("Failed to get inner mutex value");
-
         let mut final_results = Vec::new();
 
-        // Now update the index with the results of all tasks
-        for (guid, result) in task_results {
+        // Update the index as each task completes
+        while let Some(res) = tasks.join_next().await {
+            // Propagate any panics
+            let Some(result) = res? else {
+                continue;
+            };
+
             // Remove from index only after successful deletion
-            if let SyncResult::Deleted(_) = &result {
-                index.remove_photo(&guid);
+            if let SyncResult::Deleted(guid) = &result {
+                index.remove_photo(guid);
             }
             final_results.push(result);
         }
@@ -394,117 +489,163 @@ impl Syncer {
             return Ok(Vec::new());
         }
 
-        let concurrent_limit = 8; // Limit concurrent operations
-        let mut futures = Vec::new();
-        let results = Arc::new(Mutex::new(Vec::new()));
+        let semaphore = Arc::new(Semaphore::new(self.concurrency));
+        let mut tasks = JoinSet::new();
 
         // First, check which photos are unchanged to avoid processing them
         let mut unchanged_photos = Vec::new();
+        let mut metadata_updates = Vec::new();
         let mut photos_to_process = Vec::new();
 
         for (guid, photo) in &album.photos {
-            // Check if the photo exists and has the same checksum
-            if let Some(existing) = index.get_photo(guid) {
-                if existing.checksum == photo.checksum {
+            // Rejected photos are never downloaded again
+            if index.get_photo(guid).is_some_and(IndexedPhoto::is_rejected) {
+                unchanged_photos.push(SyncResult::Unchanged(guid.clone()));
+                continue;
+            }
+
+            // Check if the photo exists and has the same checksum. Offline,
+            // every photo is processed again from the file on disk.
+            if !self.offline
+                && let Some(existing) = index.get_photo(guid)
+                && existing.checksum == photo.checksum
+            {
+                if existing.metadata_differs(photo) {
+                    // Content is the same but the caption, date, etc. changed
+                    metadata_updates.push(photo);
+                } else {
                     // This photo is unchanged, don't need to process it
                     unchanged_photos.push(SyncResult::Unchanged(guid.clone()));
-                    continue;
                 }
+                continue;
             }
 
-            // This photo needs processing (new or updated)
-            photos_to_process.push(photo.clone());
+            // This photo needs processing (new or updated); it keeps its
+            // approval state, and new photos wait for approval if required
+            let approval = match index.get_photo(guid) {
+                Some(existing) => existing.approval,
+                None => self.require_approval.then_some(Approval::Pending),
+            };
+            photos_to_process.push((photo.clone(), approval));
         }
 
-        // Create tasks for each photo that needs processing
-        for photo in photos_to_process {
-            let guid = photo.guid.clone();
+        // Create tasks for each photo that needs processing; the semaphore
+        // limits how many of them run at once
+        for (photo, approval) in photos_to_process {
             let content_dir = self.content_dir.clone();
-            let client = self.client.clone();
-            let results_clone = Arc::clone(&results);
+            let site_root = self.site_root.clone();
+            let downloader = self.downloader.clone();
+            let overrides = Arc::clone(&self.overrides);
+            let semaphore = Arc::clone(&semaphore);
+            let shutdown = self.shutdown.clone();
+
+            tasks.spawn(async move {
+                let _permit = semaphore
+                    .acquire_owned()
+                    .await
+                    .expect("Semaphore is never closed");
+
+                // Don't start anything new once a shutdown was requested
+                if shutdown.is_requested() {
+                    return None;
+                }
 
-            let future = task::spawn(async move {
                 // Create a task-local syncer for this photo
                 let task_syncer = TaskSyncer {
-                    client,
-                    content_dir: content_dir.clone(),
+                    downloader,
+                    content_dir,
+                    site_root,
+                    overrides,
                 };
 
                 // Sync photo in the task
-                let result = Self::sync_photo_task_v2(&photo, &task_syncer).await;
-
-                // Store both the photo and the result so we can update the index later
-                let mut results_guard = results_clone.lock().unwrap();
-                match result {
-                    Ok((indexed_photo, status)) => {
-                        results_guard.push((Ok(indexed_photo), status));
-                    }
-                    Err(e) => {
-                        let error = format!("Failed to sync photo: {e}");
-                        results_guard.push((Err(error), guid));
-                    }
-                }
+                let result = Self::sync_photo_task_v2(&photo, approval, &task_syncer)
+                    .await
+                    .map(|(indexed_photo, _)| indexed_photo)
+                    .map_err(|e| format!("Failed to sync photo: {e}"));
+                Some((photo.guid, result))
             });
-
-            futures.push(future);
-
-            // Process in batches to limit concurrency
-            if futures.len() >= concurrent_limit {
-                let batch = futures.split_off(futures.len() - concurrent_limit);
-                join_all(batch).await;
-            }
         }
 
-        // Wait for remaining tasks to complete
-        join_all(futures).await;
-
-        // Get all results and update the index
-        let task_results = Arc::try_unwrap(results)
-            .expect("All tasks should be completed, but reference still exists")
-            .into_inner()
-            .expect("Failed to get inner mutex value");
-
         let mut final_results = Vec::new();
 
         // Add all the unchanged photos to the results
         final_results.extend(unchanged_photos);
 
-        // Update the index with successful results and collect final results
-        for (indexed_photo_result, status_or_guid) in task_results {
-            match indexed_photo_result {
-                Ok(indexed_photo) => {
-                    // Get the photo GUID before we add it to the index
-                    let guid = indexed_photo.guid.clone();
+        // Re-render pages whose metadata changed, without downloading anything
+        for photo in metadata_updates {
+            if self.shutdown.is_requested() {
+                break;
+            }
+            final_results.push(self.update_photo_metadata(photo, index).await);
+        }
+
+        // Update the index as each task completes, so every finished photo is
+        // journaled right away
+        while let Some(res) = tasks.join_next().await {
+            // Propagate any panics
+            let Some((guid, result)) = res? else {
+                continue;
+            };
 
+            match result {
+                Ok(indexed_photo) => {
                     // Determine if this is a new photo or an update
                     let is_new = !index.photos.contains_key(&guid);
 
                     // Add or update the index
                     index.add_or_update_photo(indexed_photo);
 
-                    // Create the appropriate result
-                    let result = if is_new {
+                    final_results.push(if is_new {
                         SyncResult::Added(guid)
                     } else {
                         SyncResult::Updated(guid)
-                    };
-
-                    final_results.push(result);
-                }
-                Err(error) => {
-                    // Handle errors
-                    final_results.push(SyncResult::Failed(status_or_guid, error));
+                    });
                 }
+                Err(error) => final_results.push(SyncResult::Failed(guid, error)),
             }
         }
 
         Ok(final_results)
     }
 
+    /// Applies a metadata-only change to an indexed photo and re-renders its index.md
+    async fn update_photo_metadata(&self, photo: &Photo, index: &mut PhotoIndex) -> SyncResult {
+        let Some(mut indexed_photo) = index.get_photo(&photo.guid).cloned() else {
+            return SyncResult::Failed(
+                photo.guid.clone(),
+                "Photo is missing from the index".to_string(),
+            );
+        };
+        indexed_photo.update_metadata(photo);
+
+        let photo_dir = self.content_dir.join(&photo.guid);
+        let result = async {
+            tokio_fs::create_dir_all(&photo_dir)
+                .await
+                .with_context(|| format!("Failed to create directory for photo {}", photo.guid))?;
+            self.create_index_md_with_exif(&indexed_photo, &photo_dir.join("index.md"))
+                .await
+        }
+        .await;
+
+        match result {
+            Ok(()) => {
+                index.add_or_update_photo(indexed_photo);
+                SyncResult::MetadataUpdated(photo.guid.clone())
+            }
+            Err(e) => SyncResult::Failed(
+                photo.guid.clone(),
+                format!("Failed to update photo metadata: {e}"),
+            ),
+        }
+    }
+
     /// Complete rewrite of sync_photo_task that avoids mutation of the index
     /// Returns both the IndexedPhoto and whether it's new/updated/unchanged
     async fn sync_photo_task_v2(
         photo: &Photo,
+        approval: Option<Approval>,
         task_syncer: &TaskSyncer,
     ) -> Result<(IndexedPhoto, String)> {
         // Create directory for this photo
@@ -516,7 +657,8 @@ impl Syncer {
         // Download the image
         let image_path = photo_dir.join("original.jpg");
         task_syncer
-            .download_photo(photo, &image_path)
+            .downloader
+            .download(photo, &image_path)
             .await
             .with_context(|| format!("Failed to download photo {}", photo.guid))?;
 
@@ -531,7 +673,15 @@ impl Syncer {
             photo.width,
             photo.height,
             image_path.clone(),
+            &task_syncer.site_root,
         );
+        indexed_photo.contributor = photo.contributor.clone();
+        indexed_photo.sources = photo.sources.clone();
+        indexed_photo.asset_checksum = photo.asset_checksum.clone();
+        indexed_photo.approval = approval;
+        if let Err(e) = indexed_photo.record_file(&task_syncer.site_root) {
+            warn!("Failed to hash photo {}: {e:#}", photo.guid);
+        }
 
         // Extract EXIF data if possible
         if image_path.exists() {
@@ -584,13 +734,17 @@ impl Syncer {
     async fn sync_photo_task(
         photo: &Photo,
         content_dir: &Path,
-        client: Client,
+        site_root: &Path,
+        downloader: Downloader,
+        overrides: Arc<Overrides>,
         index_arc: &Arc<Mutex<&mut PhotoIndex>>,
     ) -> Result<SyncResult> {
-        // Create a task-local syncer with the client
+        // Create a task-local syncer with the downloader
         let task_syncer = TaskSyncer {
-            client,
+            downloader,
             content_dir: content_dir.to_path_buf(),
+            site_root: site_root.to_path_buf(),
+            overrides,
         };
 
         // Check if this is a new photo or an update by examining the index
@@ -600,10 +754,10 @@ impl Syncer {
         };
 
         // If the photo exists and checksums match, no need to update
-        if let Some(existing) = &existing {
-            if existing.checksum == photo.checksum {
-                return Ok(SyncResult::Unchanged(photo.guid.clone()));
-            }
+        if let Some(existing) = &existing
+            && existing.checksum == photo.checksum
+        {
+            return Ok(SyncResult::Unchanged(photo.guid.clone()));
         }
 
         // Create directory for this photo
@@ -615,7 +769,8 @@ impl Syncer {
         // Download the image
         let image_path = photo_dir.join("original.jpg");
         task_syncer
-            .download_photo(photo, &image_path)
+            .downloader
+            .download(photo, &image_path)
             .await
             .with_context(|| format!("Failed to download photo {}", photo.guid))?;
 
@@ -630,7 +785,11 @@ impl Syncer {
             photo.width,
             photo.height,
             image_path.clone(),
+            site_root,
         );
+        indexed_photo.contributor = photo.contributor.clone();
+        indexed_photo.sources = photo.sources.clone();
+        indexed_photo.asset_checksum = photo.asset_checksum.clone();
 
         // Extract EXIF data if possible
         if image_path.exists() {
@@ -718,10 +877,10 @@ impl Syncer {
         let existing = index.get_photo(&photo.guid);
 
         // If the photo exists and checksums match, no need to update
-        if let Some(existing) = existing {
-            if existing.checksum == photo.checksum {
-                return Ok(SyncResult::Unchanged(photo.guid.clone()));
-            }
+        if let Some(existing) = existing
+            && existing.checksum == photo.checksum
+        {
+            return Ok(SyncResult::Unchanged(photo.guid.clone()));
         }
 
         // Create directory for this photo
@@ -732,7 +891,8 @@ impl Syncer {
 
         // Download the image
         let image_path = photo_dir.join("original.jpg");
-        self.download_photo(photo, &image_path)
+        self.downloader
+            .download(photo, &image_path)
             .await
             .with_context(|| format!("Failed to download photo {}", photo.guid))?;
 
@@ -747,7 +907,11 @@ impl Syncer {
             photo.width,
             photo.height,
             image_path.clone(),
+            &self.site_root,
         );
+        indexed_photo.contributor = photo.contributor.clone();
+        indexed_photo.sources = photo.sources.clone();
+        indexed_photo.asset_checksum = photo.asset_checksum.clone();
 
         // Extract EXIF data if possible
         if image_path.exists() {
@@ -797,59 +961,23 @@ impl Syncer {
         Ok(result)
     }
 
-    /// Downloads a photo from its URL
-    #[allow(dead_code)]
-    async fn download_photo(&self, photo: &Photo, path: &Path) -> Result<()> {
-        // For tests, create a placeholder file instead of actually downloading
-        if cfg!(test) {
-            // Create a placeholder image for tests only
-            tokio_fs::write(path, "PLACEHOLDER IMAGE CONTENT")
-                .await
-                .with_context(|| {
-                    format!("Failed to write test placeholder to {}", path.display())
-                })?;
-            return Ok(());
-        }
-
-        // Check for test URLs explicitly - looking for exact test domains rather than a substring
-        if photo.url.starts_with("https://example.com/")
-            || photo.url.starts_with("http://example.com/")
-            || photo.url.starts_with("https://test.example/")
-        {
-            // Create a placeholder for test URLs
-            tokio_fs::write(path, "PLACEHOLDER TEST URL IMAGE CONTENT")
-                .await
-                .with_context(|| {
-                    format!("Failed to write test URL placeholder to {}", path.display())
-                })?;
-            return Ok(());
-        }
-
-        // Otherwise, download the actual image
-        let response = self
-            .client
-            .get(&photo.url)
-            .send()
-            .await
-            .with_context(|| format!("Failed to GET photo from {}", photo.url))?;
-
-        let bytes = response
-            .bytes()
+    /// Writes the index.md of an indexed photo's page bundle
+    pub async fn render_page(&self, photo: &IndexedPhoto) -> Result<()> {
+        let photo_dir = self.content_dir.join(&photo.guid);
+        tokio_fs::create_dir_all(&photo_dir)
             .await
-            .context("Failed to read photo bytes")?;
-
-        tokio_fs::write(path, bytes)
+            .with_context(|| format!("Failed to create directory for photo {}", photo.guid))?;
+        self.create_index_md_with_exif(photo, &photo_dir.join("index.md"))
             .await
-            .with_context(|| format!("Failed to write photo to {}", path.display()))?;
-
-        Ok(())
     }
 
     /// Creates an index.md file with frontmatter including EXIF data
-    #[allow(dead_code)]
     async fn create_index_md_with_exif(&self, photo: &IndexedPhoto, path: &Path) -> Result<()> {
-        // Generate the photo title using date, location, and camera info
-        let title = format_photo_title(photo);
+        // Apply the photo's overrides; the title is made from the date,
+        // location and camera info unless it's overridden
+        let view = self.overrides.apply(photo);
+        let photo = view.photo.as_ref();
+        let title = &view.title;
 
         // Build the frontmatter with EXIF data if available
         let mut frontmatter = format!(
@@ -862,22 +990,26 @@ width: {}
 height: {}
 mime_type: {}
 ",
-            title,
+            quote(title),
             photo.created_at.format("%Y-%m-%dT%H:%M:%S%z"),
             photo.guid,
-            photo.filename,
+            quote(&photo.filename),
             photo.width,
             photo.height,
             photo.mime_type,
         );
 
+        if let Some(ref contributor) = photo.contributor {
+            frontmatter.push_str(&format!("contributor: {}\n", quote(contributor)));
+        }
+
         // Add EXIF data if available
         if let Some(ref make) = photo.camera_make {
-            frontmatter.push_str(&format!("camera_make: {make}\n"));
+            frontmatter.push_str(&format!("camera_make: {}\n", quote(make)));
         }
 
         if let Some(ref model) = photo.camera_model {
-            frontmatter.push_str(&format!("camera_model: {model}\n"));
+            frontmatter.push_str(&format!("camera_model: {}\n", quote(model)));
         }
 
         if let Some(exif_dt) = photo.exif_date_time {
@@ -923,26 +1055,32 @@ mime_type: {}
 
         // Add location data if available
         if let Some(ref location) = photo.location {
-            frontmatter.push_str(&format!("location: {}\n", location.formatted_address));
+            frontmatter.push_str(&format!(
+                "location: {}\n",
+                quote(&location.formatted_address)
+            ));
 
             if let Some(ref city) = location.city {
-                frontmatter.push_str(&format!("city: {city}\n"));
+                frontmatter.push_str(&format!("city: {}\n", quote(city)));
             }
 
             if let Some(ref state) = location.state {
-                frontmatter.push_str(&format!("state: {state}\n"));
+                frontmatter.push_str(&format!("state: {}\n", quote(state)));
             }
 
             if let Some(ref country) = location.country {
-                frontmatter.push_str(&format!("country: {country}\n"));
+                frontmatter.push_str(&format!("country: {}\n", quote(country)));
             }
         }
 
+        let taxonomy = self.overrides.taxonomy().unwrap_or("tags");
+        push_override_fields(&view, taxonomy, &mut frontmatter);
+
         // Close frontmatter and add content
         frontmatter.push_str("---\n\n");
         frontmatter.push_str(&photo.caption.clone().unwrap_or_default());
 
-        tokio_fs::write(path, frontmatter)
+        write_page(path, &frontmatter, PAGE_KEYS, &[taxonomy, "draft"])
             .await
             .with_context(|| format!("Failed to write index.md to {}", path.display()))
     }
@@ -967,6 +1105,11 @@ mod tests {
             width: 800,
             height: 600,
             mime_type: "image/jpeg".to_string(),
+            contributor: None,
+            batch_guid: None,
+            batch_created_at: None,
+            asset_checksum: None,
+            sources: Vec::new(),
         }
     }
 
@@ -982,6 +1125,11 @@ mod tests {
             width: 800,
             height: 600,
             mime_type: "image/jpeg".to_string(),
+            contributor: None,
+            batch_guid: None,
+            batch_created_at: None,
+            asset_checksum: None,
+            sources: Vec::new(),
         }
     }
 
@@ -1037,6 +1185,7 @@ mod tests {
             match result {
                 SyncResult::Added(_) => added_count += 1,
                 SyncResult::Updated(_) => updated_count += 1,
+                SyncResult::MetadataUpdated(_) => panic!("Should not have metadata updates"),
                 SyncResult::Unchanged(_) => unchanged_count += 1,
                 SyncResult::Deleted(_) => panic!("Should not have deleted any photos"),
                 SyncResult::Failed(guid, error) => {
@@ -1063,6 +1212,42 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_interrupted_sync_resumes_from_journal() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content");
+        let index_path = temp_dir.path().join("index.yaml");
+        let album = create_test_album();
+
+        // A shutdown requested before the sync starts leaves everything for later
+        let shutdown = Shutdown::new();
+        shutdown.request();
+        let syncer = Syncer::new(content_dir.clone(), index_path.clone()).with_shutdown(shutdown);
+        let mut index = PhotoIndex::new();
+        let results = syncer.sync_photos(&album, &mut index).await?;
+        assert!(results.is_empty());
+        assert_eq!(index.photo_count(), 0);
+
+        // The process dies after syncing, before the index is saved
+        let syncer = Syncer::new(content_dir.clone(), index_path.clone());
+        let mut index = PhotoIndex::new();
+        index.open_journal(&index_path)?;
+        syncer.sync_photos(&album, &mut index).await?;
+        drop(index);
+
+        // The next run finds the finished photos in the journal
+        let mut resumed = PhotoIndex::load(&index_path, Path::new(""))?;
+        assert_eq!(resumed.photo_count(), 2);
+        let results = syncer.sync_photos(&album, &mut resumed).await?;
+        assert!(
+            results
+                .iter()
+                .all(|result| matches!(result, SyncResult::Unchanged(_)))
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_sync_unchanged_photos() -> Result<()> {
         let temp_dir = tempdir()?;
@@ -1089,6 +1274,7 @@ mod tests {
                 photo.width,
                 photo.height,
                 PathBuf::from(format!("/content/{guid}/original.jpg")),
+                Path::new(""),
             );
 
             index.add_or_update_photo(indexed_photo);
@@ -1143,6 +1329,7 @@ mod tests {
                 photo.width,
                 photo.height,
                 PathBuf::from(format!("/content/{guid}/original.jpg")),
+                Path::new(""),
             );
 
             index.add_or_update_photo(indexed_photo);
@@ -1171,6 +1358,69 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_sync_metadata_only_update() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content");
+        let index_path = temp_dir.path().join("index.yaml");
+
+        let syncer = Syncer::new(content_dir.clone(), index_path.clone());
+
+        // Create a test album with two photos
+        let album = create_test_album();
+
+        // Index both photos with matching checksums, but an old caption for photo1
+        let mut index = PhotoIndex::new();
+        for (guid, photo) in &album.photos {
+            let caption = if guid == "photo1" {
+                Some("Old caption".to_string())
+            } else {
+                photo.caption.clone()
+            };
+            let indexed_photo = IndexedPhoto::new(
+                guid.clone(),
+                photo.filename.clone(),
+                caption,
+                photo.created_at,
+                photo.checksum.clone(), // Same checksum!
+                photo.url.clone(),
+                photo.width,
+                photo.height,
+                content_dir.join(guid).join("original.jpg"),
+                Path::new(""),
+            );
+
+            index.add_or_update_photo(indexed_photo);
+        }
+
+        // Sync the photos
+        let results = syncer.sync_photos(&album, &mut index).await?;
+
+        // Only photo1 should be re-rendered, and nothing downloaded
+        assert_eq!(results.len(), 2);
+        assert!(
+            results
+                .iter()
+                .any(|r| matches!(r, SyncResult::MetadataUpdated(guid) if guid == "photo1"))
+        );
+        assert!(
+            results
+                .iter()
+                .any(|r| matches!(r, SyncResult::Unchanged(guid) if guid == "photo2"))
+        );
+        assert!(!content_dir.join("photo1").join("original.jpg").exists());
+
+        // The index and the page should carry the new caption
+        assert_eq!(
+            index.get_photo("photo1").unwrap().caption,
+            Some("Caption for photo1".to_string())
+        );
+        let index_md = fs::read_to_string(content_dir.join("photo1").join("index.md"))?;
+        assert!(index_md.contains("Caption for photo1"));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_sync_delete_photos() -> Result<()> {
         let temp_dir = tempdir()?;
@@ -1202,6 +1452,7 @@ mod tests {
                 photo.width,
                 photo.height,
                 photo_dir.join("original.jpg"),
+                Path::new(""),
             );
 
             index.add_or_update_photo(indexed_photo);
@@ -1245,6 +1496,55 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_removed_overrides_leave_no_stale_keys() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content");
+        let index_path = temp_dir.path().join("index.yaml");
+        let overrides_path = temp_dir.path().join("overrides.yaml");
+        fs::write(
+            &overrides_path,
+            "photo1:\n  title: 'Beach: \"the\" day'\n  tags: [summer]\n  hidden: true\n",
+        )?;
+        let overrides = Arc::new(Overrides::load(&overrides_path)?);
+
+        let syncer = Syncer::new(content_dir.clone(), index_path.clone()).with_overrides(overrides);
+        let mut index = PhotoIndex::new();
+        syncer.sync_photos(&create_test_album(), &mut index).await?;
+
+        let page_path = content_dir.join("photo1").join("index.md");
+        let page = fs::read_to_string(&page_path)?;
+        let frontmatter = crate::frontmatter::parse(&page)?.unwrap();
+        assert_eq!(
+            crate::frontmatter::string(&frontmatter, "title").as_deref(),
+            Some("Beach: \"the\" day")
+        );
+        assert!(page.contains("tags:\n  - \"summer\"\n"));
+        assert!(page.contains("draft: true\n"));
+
+        // Tags and draft added by hand to another page are left alone
+        let other_path = content_dir.join("photo2").join("index.md");
+        let other = fs::read_to_string(&other_path)?.replacen(
+            "---\n",
+            "---\ndraft: true\ntags:\n  - mine\n",
+            1,
+        );
+        fs::write(&other_path, &other)?;
+
+        // Once the overrides are gone, so are the keys they wrote
+        let syncer = Syncer::new(content_dir.clone(), index_path.clone());
+        for guid in ["photo1", "photo2"] {
+            syncer.render_page(index.get_photo(guid).unwrap()).await?;
+        }
+        let page = fs::read_to_string(&page_path)?;
+        assert!(!page.contains("tags:"));
+        assert!(!page.contains("draft:"));
+        assert!(!page.contains("Beach"));
+        assert_eq!(fs::read_to_string(&other_path)?, other);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_photo_title_formatting() -> Result<()> {
         let temp_dir = tempdir()?;
@@ -0,0 +1,116 @@
+//! Bandwidth limiting for icloud2hugo.
+//!
+//! A sync downloads many photos at once, which can saturate a shared home
+//! connection. When a bytes-per-second cap is configured, every download of an
+//! output draws from one shared `Throttle`, a token bucket that refills at the
+//! configured rate. Downloads that run out of tokens wait until enough have
+//! been refilled, so the combined rate stays under the cap.
+
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+/// A token bucket limiting the combined rate of all downloads sharing it
+#[derive(Debug)]
+pub struct Throttle {
+    /// Maximum sustained rate in bytes per second
+    bytes_per_second: u64,
+    /// Bytes that may be consumed right now (negative while in debt) and when
+    /// the bucket was last refilled
+    state: Mutex<(f64, Instant)>,
+}
+
+impl Throttle {
+    /// Creates a throttle for the given rate, starting with one second's worth of tokens
+    pub fn new(bytes_per_second: u64) -> Self {
+        let bytes_per_second = bytes_per_second.max(1);
+        Self {
+            bytes_per_second,
+            state: Mutex::new((bytes_per_second as f64, Instant::now())),
+        }
+    }
+
+    /// Accounts for `bytes` received, waiting until the rate allows them
+    pub async fn consume(&self, bytes: usize) {
+        let wait = {
+            let mut state = self.state.lock().await;
+            let (tokens, last_refill) = &mut *state;
+            let rate = self.bytes_per_second as f64;
+
+            // Refill for the time that passed, holding at most one second's worth
+            let now = Instant::now();
+            *tokens = (*tokens + now.duration_since(*last_refill).as_secs_f64() * rate).min(rate);
+            *last_refill = now;
+
+            *tokens -= bytes as f64;
+            if *tokens < 0.0 {
+                Duration::from_secs_f64(-*tokens / rate)
+            } else {
+                Duration::ZERO
+            }
+        };
+
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_first_second_is_available_at_once() {
+        let throttle = Throttle::new(10_000);
+        let start = Instant::now();
+
+        throttle.consume(10_000).await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn test_throttle_limits_rate() {
+        let throttle = Throttle::new(10_000);
+        throttle.consume(10_000).await;
+        let start = Instant::now();
+
+        // Everything after the first second's worth arrives at the configured rate
+        throttle.consume(1_000).await;
+        throttle.consume(1_000).await;
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed >= Duration::from_millis(190) && elapsed < Duration::from_millis(1000),
+            "took {elapsed:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_oversized_chunk_waits_for_its_debt() {
+        let throttle = Throttle::new(10_000);
+        let start = Instant::now();
+
+        throttle.consume(12_000).await;
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed >= Duration::from_millis(190) && elapsed < Duration::from_millis(1000),
+            "took {elapsed:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refills_while_idle() {
+        let throttle = Throttle::new(10_000);
+        throttle.consume(10_000).await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let start = Instant::now();
+        throttle.consume(1_000).await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_zero_rate_is_raised_to_one() {
+        assert_eq!(Throttle::new(0).bytes_per_second, 1);
+    }
+}
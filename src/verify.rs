@@ -0,0 +1,851 @@
+//! Checking an output's page bundles against its index, and repairing them.
+//!
+//! Bundles drift from the index when files are deleted or edited by hand, or
+//! when a sync dies at the wrong moment. `verify_output` reports every media
+//! file the index expects that is missing, has the wrong size or a different
+//! content hash, every page that is missing, and every file or directory in
+//! the output that the index doesn't know about.
+//!
+//! `repair` downloads missing and damaged media again, re-renders missing
+//! pages and, for each orphan, removes it, adopts it into the index or leaves
+//! it alone, as decided by the caller.
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::batch::BatchSyncer;
+use crate::config::OutputType;
+use crate::download::Downloader;
+use crate::exif::extract_exif;
+use crate::gallery::GallerySyncer;
+use crate::index::{IndexedPhoto, PhotoIndex, content_hash};
+use crate::sync::Syncer;
+
+/// File name of a Hugo page inside its bundle
+const PAGE_FILE: &str = "index.md";
+
+/// File name of the media in a photostream bundle
+const PHOTOSTREAM_MEDIA_FILE: &str = "original.jpg";
+
+/// A page generated from the index
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Page {
+    /// The page bundle of a photostream photo
+    Photo(String),
+    /// The page of a gallery
+    Gallery(String),
+    /// The page of an upload batch
+    Batch(String),
+}
+
+impl fmt::Display for Page {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Page::Photo(guid) => write!(f, "Page of photo {guid}"),
+            Page::Gallery(id) => write!(f, "Page of gallery {id}"),
+            Page::Batch(id) => write!(f, "Page of batch {id}"),
+        }
+    }
+}
+
+/// A difference between the index and what's on disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// A photo's media file is missing
+    MissingMedia { guid: String, path: PathBuf },
+    /// A photo's media file doesn't have the size it was downloaded with
+    SizeMismatch {
+        guid: String,
+        path: PathBuf,
+        expected: u64,
+        found: u64,
+    },
+    /// A photo's media file doesn't have the content it was downloaded with
+    HashMismatch { guid: String, path: PathBuf },
+    /// A page is missing
+    MissingPage { page: Page, path: PathBuf },
+    /// A file or directory in the output that the index doesn't know about
+    Orphan { path: PathBuf },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::MissingMedia { guid, path } => {
+                write!(f, "Photo {guid} is missing {}", path.display())
+            }
+            Issue::SizeMismatch {
+                guid,
+                path,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Photo {guid} has {found} bytes in {}, expected {expected}",
+                path.display()
+            ),
+            Issue::HashMismatch { guid, path } => write!(
+                f,
+                "Photo {guid} has different content in {}",
+                path.display()
+            ),
+            Issue::MissingPage { path, .. } => write!(f, "Page {} is missing", path.display()),
+            Issue::Orphan { path } => write!(f, "{} isn't in the index", path.display()),
+        }
+    }
+}
+
+/// Checks an output's content directory against its index
+///
+/// `site_root` is the directory of the Hugo site, which photo paths in the
+/// index are relative to.
+pub fn verify_output(
+    output_type: &OutputType,
+    content_dir: &Path,
+    site_root: &Path,
+    index: &PhotoIndex,
+) -> Result<Vec<Issue>> {
+    let mut issues = Vec::new();
+
+    match output_type {
+        OutputType::Photostream => {
+            // Rejected photos have no files left
+            for photo in index.photos.values().filter(|photo| !photo.is_rejected()) {
+                check_media(photo, site_root, &mut issues);
+                let page = content_dir.join(&photo.guid).join(PAGE_FILE);
+                if !page.exists() {
+                    issues.push(Issue::MissingPage {
+                        page: Page::Photo(photo.guid.clone()),
+                        path: page,
+                    });
+                }
+            }
+
+            let known: HashSet<&str> = index.photos.keys().map(String::as_str).collect();
+            for (name, path) in entries(content_dir)? {
+                if path.is_dir() && !known.contains(name.as_str()) {
+                    issues.push(Issue::Orphan { path });
+                }
+            }
+        }
+        OutputType::Gallery => {
+            let mut media = HashSet::new();
+            for gallery in index.galleries.values() {
+                for photo in index.get_gallery_photos(&gallery.id) {
+                    check_media(photo, site_root, &mut issues);
+                    media.insert(file_name(&photo.local_path));
+                }
+
+                let page = content_dir.join(PAGE_FILE);
+                if !page.exists() {
+                    issues.push(Issue::MissingPage {
+                        page: Page::Gallery(gallery.id.clone()),
+                        path: page,
+                    });
+                }
+            }
+
+            for (name, path) in entries(content_dir)? {
+                if path.is_file() && !is_page(&name) && !media.contains(&name) {
+                    issues.push(Issue::Orphan { path });
+                }
+            }
+        }
+        OutputType::Batches => {
+            for batch in index.batches.values() {
+                let batch_dir = content_dir.join(&batch.slug);
+                let mut media = HashSet::new();
+                for photo in index.get_batch_photos(&batch.id) {
+                    check_media(photo, site_root, &mut issues);
+                    media.insert(file_name(&photo.local_path));
+                }
+
+                let page = batch_dir.join(PAGE_FILE);
+                if !page.exists() {
+                    issues.push(Issue::MissingPage {
+                        page: Page::Batch(batch.id.clone()),
+                        path: page,
+                    });
+                }
+
+                for (name, path) in entries(&batch_dir)? {
+                    if path.is_file() && !is_page(&name) && !media.contains(&name) {
+                        issues.push(Issue::Orphan { path });
+                    }
+                }
+            }
+
+            let slugs: HashSet<&str> = index.batches.values().map(|b| b.slug.as_str()).collect();
+            for (name, path) in entries(content_dir)? {
+                if path.is_dir() && !slugs.contains(name.as_str()) {
+                    issues.push(Issue::Orphan { path });
+                }
+            }
+        }
+    }
+
+    Ok(issues)
+}
+
+/// Checks that a photo's media file is there, with the size and content it
+/// was downloaded with
+fn check_media(photo: &IndexedPhoto, site_root: &Path, issues: &mut Vec<Issue>) {
+    // Rejected photos have no files left
+    if photo.is_rejected() {
+        return;
+    }
+
+    let path = &photo.file_path(site_root);
+    let Ok(contents) = fs::read(path) else {
+        issues.push(Issue::MissingMedia {
+            guid: photo.guid.clone(),
+            path: path.clone(),
+        });
+        return;
+    };
+
+    let found = contents.len() as u64;
+    if let Some(expected) = photo.file_size
+        && expected != found
+    {
+        issues.push(Issue::SizeMismatch {
+            guid: photo.guid.clone(),
+            path: path.clone(),
+            expected,
+            found,
+        });
+    } else if let Some(expected) = &photo.content_hash
+        && *expected != content_hash(&contents)
+    {
+        issues.push(Issue::HashMismatch {
+            guid: photo.guid.clone(),
+            path: path.clone(),
+        });
+    }
+}
+
+/// Lists the visible entries of a directory, sorted by name
+fn entries(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !name.starts_with('.') {
+            entries.push((name, entry.path()));
+        }
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+/// Whether a file is a Hugo page rather than media
+fn is_page(name: &str) -> bool {
+    name == PAGE_FILE || name == "_index.md"
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// What to do with a file or directory the index doesn't know about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OrphanAction {
+    /// Leave it alone
+    Keep,
+    /// Delete it
+    Remove,
+    /// Add it to the index
+    Adopt,
+}
+
+/// How an issue was dealt with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The media file was downloaded again
+    Downloaded,
+    /// The page was rendered again
+    Rendered,
+    /// The orphan was deleted
+    Removed,
+    /// The orphan was added to the index
+    Adopted,
+    /// The orphan was left alone
+    Kept,
+    /// The issue couldn't be repaired
+    Failed(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Downloaded => write!(f, "downloaded again"),
+            Outcome::Rendered => write!(f, "rendered again"),
+            Outcome::Removed => write!(f, "removed"),
+            Outcome::Adopted => write!(f, "adopted"),
+            Outcome::Kept => write!(f, "kept"),
+            Outcome::Failed(error) => write!(f, "failed: {error}"),
+        }
+    }
+}
+
+/// Renders an output's pages with the syncer of its type
+pub enum Renderer {
+    Photostream(Syncer),
+    Gallery(GallerySyncer),
+    Batches(BatchSyncer),
+}
+
+impl Renderer {
+    /// Writes a page from the index
+    pub async fn render(&self, index: &PhotoIndex, page: &Page) -> Result<()> {
+        match (self, page) {
+            (Renderer::Photostream(syncer), Page::Photo(guid)) => {
+                let photo = index
+                    .get_photo(guid)
+                    .with_context(|| format!("Photo {guid} is not in the index"))?;
+                syncer.render_page(photo).await
+            }
+            (Renderer::Gallery(syncer), Page::Gallery(id)) => {
+                syncer.render_gallery(index, id).await
+            }
+            (Renderer::Batches(syncer), Page::Batch(id)) => syncer.render_batch(index, id).await,
+            (_, page) => bail!("{page:?} doesn't belong to this output"),
+        }
+    }
+}
+
+/// Repairs the issues found by `verify_output`
+///
+/// Media is repaired first, then orphans, then pages, so pages that list
+/// adopted photos are rendered with them. `decide` is asked what to do with
+/// each orphan.
+pub async fn repair(
+    issues: Vec<Issue>,
+    index: &mut PhotoIndex,
+    content_dir: &Path,
+    site_root: &Path,
+    downloader: &Downloader,
+    renderer: &Renderer,
+    mut decide: impl FnMut(&Path) -> OrphanAction,
+) -> Vec<(Issue, Outcome)> {
+    let mut outcomes = Vec::new();
+    let mut pages = BTreeSet::new();
+    let (pages_missing, rest): (Vec<Issue>, Vec<Issue>) = issues
+        .into_iter()
+        .partition(|issue| matches!(issue, Issue::MissingPage { .. }));
+
+    for issue in rest {
+        let outcome = match &issue {
+            Issue::MissingMedia { guid, .. }
+            | Issue::SizeMismatch { guid, .. }
+            | Issue::HashMismatch { guid, .. } => {
+                match download_again(index, guid, site_root, downloader).await {
+                    Ok(()) => Outcome::Downloaded,
+                    Err(e) => Outcome::Failed(format!("{e:#}")),
+                }
+            }
+            Issue::Orphan { path } => match decide(path) {
+                OrphanAction::Keep => Outcome::Kept,
+                OrphanAction::Remove => match remove(path) {
+                    Ok(()) => Outcome::Removed,
+                    Err(e) => Outcome::Failed(format!("{e:#}")),
+                },
+                OrphanAction::Adopt => match adopt(index, content_dir, site_root, renderer, path) {
+                    Ok(page) => {
+                        pages.extend(page);
+                        Outcome::Adopted
+                    }
+                    Err(e) => Outcome::Failed(format!("{e:#}")),
+                },
+            },
+            Issue::MissingPage { .. } => unreachable!("pages are repaired last"),
+        };
+        outcomes.push((issue, outcome));
+    }
+
+    for issue in pages_missing {
+        if let Issue::MissingPage { page, .. } = &issue {
+            pages.remove(page);
+            let outcome = match renderer.render(index, page).await {
+                Ok(()) => Outcome::Rendered,
+                Err(e) => Outcome::Failed(format!("{e:#}")),
+            };
+            outcomes.push((issue, outcome));
+        }
+    }
+
+    // Pages that list adopted photos, but weren't missing
+    for page in pages {
+        if let Err(e) = renderer.render(index, &page).await {
+            log::warn!("Failed to render {page:?}: {e:#}");
+        }
+    }
+
+    outcomes
+}
+
+/// Downloads a photo's media again and records its new size and hash
+async fn download_again(
+    index: &mut PhotoIndex,
+    guid: &str,
+    site_root: &Path,
+    downloader: &Downloader,
+) -> Result<()> {
+    let mut photo = index
+        .get_photo(guid)
+        .cloned()
+        .with_context(|| format!("Photo {guid} is not in the index"))?;
+
+    let path = photo.file_path(site_root);
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    downloader.download(&photo.to_photo(), &path).await?;
+    photo.record_file(site_root)?;
+
+    index.add_or_update_photo(photo);
+    Ok(())
+}
+
+/// Deletes an orphaned file or directory
+fn remove(path: &Path) -> Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+    .with_context(|| format!("Failed to remove {}", path.display()))
+}
+
+/// Adds an orphan to the index, returning the page that now lists it
+///
+/// Adopted photos have no checksum, so the next sync downloads them again if
+/// they are in the album, and removes them like any other photo if not.
+fn adopt(
+    index: &mut PhotoIndex,
+    content_dir: &Path,
+    site_root: &Path,
+    renderer: &Renderer,
+    path: &Path,
+) -> Result<Option<Page>> {
+    match renderer {
+        Renderer::Photostream(_) => {
+            let media = path.join(PHOTOSTREAM_MEDIA_FILE);
+            if !media.is_file() {
+                bail!("{} has no {PHOTOSTREAM_MEDIA_FILE}", path.display());
+            }
+            let guid = file_name(path);
+            index.add_or_update_photo(adopted_photo(guid.clone(), &media, site_root)?);
+            Ok((!path.join(PAGE_FILE).exists()).then_some(Page::Photo(guid)))
+        }
+        Renderer::Gallery(_) => {
+            let gallery_id = index
+                .galleries
+                .keys()
+                .next()
+                .cloned()
+                .context("There is no gallery to add the photo to")?;
+            let photo = adopted_photo(file_stem(path), path, site_root)?;
+            let guid = photo.guid.clone();
+            index.add_or_update_photo(photo);
+            index.update_gallery(&gallery_id, |gallery| gallery.add_photo(guid));
+            Ok(Some(Page::Gallery(gallery_id)))
+        }
+        Renderer::Batches(_) => {
+            let slug = path
+                .parent()
+                .filter(|parent| *parent != content_dir && path.is_file())
+                .map(file_name)
+                .context("Only photos inside a batch can be adopted")?;
+            let batch_id = index
+                .batches
+                .values()
+                .find(|batch| batch.slug == slug)
+                .map(|batch| batch.id.clone())
+                .with_context(|| format!("There is no batch {slug}"))?;
+            let photo = adopted_photo(file_stem(path), path, site_root)?;
+            let guid = photo.guid.clone();
+            index.add_or_update_photo(photo);
+            index.update_batch(&batch_id, |batch| batch.add_photo(guid));
+            Ok(Some(Page::Batch(batch_id)))
+        }
+    }
+}
+
+/// Builds an index entry for a media file that was found on disk
+fn adopted_photo(guid: String, path: &Path, site_root: &Path) -> Result<IndexedPhoto> {
+    let modified = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let mut photo = IndexedPhoto::new(
+        guid,
+        file_name(path),
+        None,
+        DateTime::<Utc>::from(modified),
+        String::new(),
+        String::new(),
+        0,
+        0,
+        path.to_path_buf(),
+        site_root,
+    );
+    if let Ok(exif) = extract_exif(path) {
+        photo.update_exif(&exif);
+        if let Some(taken_at) = photo.exif_date_time {
+            photo.created_at = taken_at;
+        }
+    }
+    photo.record_file(site_root)?;
+
+    Ok(photo)
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::{Approval, Batch, Gallery};
+    use tempfile::tempdir;
+
+    fn create_photo(content_dir: &Path, guid: &str) -> Result<IndexedPhoto> {
+        let media = content_dir.join(guid).join(PHOTOSTREAM_MEDIA_FILE);
+        fs::create_dir_all(media.parent().unwrap())?;
+        fs::write(&media, format!("IMAGE {guid}"))?;
+        fs::write(content_dir.join(guid).join(PAGE_FILE), "---\n---\n")?;
+
+        let mut photo = IndexedPhoto::new(
+            guid.to_string(),
+            format!("{guid}.jpg"),
+            None,
+            Utc::now(),
+            format!("checksum_{guid}"),
+            format!("https://example.com/{guid}.jpg"),
+            800,
+            600,
+            media,
+            Path::new(""),
+        );
+        photo.record_file(Path::new(""))?;
+        Ok(photo)
+    }
+
+    /// Creates a photo stored as `<dir>/<guid>.jpg`, as galleries and batches
+    /// store them
+    fn create_file_photo(dir: &Path, guid: &str) -> Result<IndexedPhoto> {
+        let media = dir.join(format!("{guid}.jpg"));
+        fs::create_dir_all(dir)?;
+        fs::write(&media, format!("IMAGE {guid}"))?;
+
+        let mut photo = IndexedPhoto::new(
+            guid.to_string(),
+            format!("{guid}.jpg"),
+            None,
+            Utc::now(),
+            format!("checksum_{guid}"),
+            format!("https://example.com/{guid}.jpg"),
+            800,
+            600,
+            media,
+            Path::new(""),
+        );
+        photo.record_file(Path::new(""))?;
+        Ok(photo)
+    }
+
+    fn photostream(content_dir: &Path, guids: &[&str]) -> Result<PhotoIndex> {
+        let mut index = PhotoIndex::new();
+        for guid in guids {
+            index.add_or_update_photo(create_photo(content_dir, guid)?);
+        }
+        Ok(index)
+    }
+
+    fn renderer(temp_dir: &Path, content_dir: &Path) -> Renderer {
+        Renderer::Photostream(Syncer::new(
+            content_dir.to_path_buf(),
+            temp_dir.join("index.yaml"),
+        ))
+    }
+
+    fn verify_photostream(content_dir: &Path, index: &PhotoIndex) -> Result<Vec<Issue>> {
+        verify_output(&OutputType::Photostream, content_dir, Path::new(""), index)
+    }
+
+    #[test]
+    fn test_verify_intact_photostream() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/photostream");
+        let index = photostream(&content_dir, &["photo1", "photo2"])?;
+
+        assert!(verify_photostream(&content_dir, &index)?.is_empty());
+        // An output that was never synced has nothing to check
+        assert!(
+            verify_photostream(&temp_dir.path().join("missing"), &PhotoIndex::new())?.is_empty()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_media() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/photostream");
+        let index = photostream(&content_dir, &["missing", "damaged", "edited"])?;
+        fs::remove_file(content_dir.join("missing").join(PHOTOSTREAM_MEDIA_FILE))?;
+        fs::write(content_dir.join("damaged").join(PHOTOSTREAM_MEDIA_FILE), "")?;
+        fs::write(
+            content_dir.join("edited").join(PHOTOSTREAM_MEDIA_FILE),
+            "IMAGE xxxxxx",
+        )?;
+
+        let issues = verify_photostream(&content_dir, &index)?;
+        assert_eq!(issues.len(), 3);
+        assert!(
+            matches!(&issues[0], Issue::SizeMismatch { guid, found: 0, .. } if guid == "damaged")
+        );
+        assert!(matches!(&issues[1], Issue::HashMismatch { guid, .. } if guid == "edited"));
+        assert!(matches!(&issues[2], Issue::MissingMedia { guid, .. } if guid == "missing"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_skips_rejected_photos() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/photostream");
+        let mut index = photostream(&content_dir, &["rejected"])?;
+        index.photos.get_mut("rejected").unwrap().approval = Some(Approval::Rejected);
+        fs::remove_dir_all(content_dir.join("rejected"))?;
+
+        assert!(verify_photostream(&content_dir, &index)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_pages_and_orphans() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/photostream");
+        let index = photostream(&content_dir, &["no_page"])?;
+        fs::remove_file(content_dir.join("no_page").join(PAGE_FILE))?;
+        fs::create_dir_all(content_dir.join("stray"))?;
+        fs::create_dir_all(content_dir.join(".hidden"))?;
+        fs::write(content_dir.join("_index.md"), "---\n---\n")?;
+
+        assert_eq!(
+            verify_photostream(&content_dir, &index)?,
+            vec![
+                Issue::MissingPage {
+                    page: Page::Photo("no_page".to_string()),
+                    path: content_dir.join("no_page").join(PAGE_FILE),
+                },
+                Issue::Orphan {
+                    path: content_dir.join("stray")
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_gallery() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/gallery");
+        let mut index = PhotoIndex::new();
+        let mut gallery = Gallery::new(
+            "summer".to_string(),
+            "Summer".to_string(),
+            "summer".to_string(),
+            None,
+        );
+        index.add_or_update_photo(create_file_photo(&content_dir, "photo1")?);
+        gallery.add_photo("photo1".to_string());
+        index.add_or_update_gallery(gallery);
+        fs::write(content_dir.join("stray.jpg"), "IMAGE")?;
+
+        assert_eq!(
+            verify_output(&OutputType::Gallery, &content_dir, Path::new(""), &index)?,
+            vec![
+                Issue::MissingPage {
+                    page: Page::Gallery("summer".to_string()),
+                    path: content_dir.join(PAGE_FILE),
+                },
+                Issue::Orphan {
+                    path: content_dir.join("stray.jpg")
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_batches() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/batches");
+        let mut index = PhotoIndex::new();
+        let mut batch = Batch::new("b1".to_string(), "batch-1".to_string(), Utc::now());
+        index.add_or_update_photo(create_file_photo(&content_dir.join("batch-1"), "photo1")?);
+        batch.add_photo("photo1".to_string());
+        index.add_or_update_batch(batch);
+        fs::write(content_dir.join("batch-1").join(PAGE_FILE), "---\n---\n")?;
+        fs::write(content_dir.join("batch-1/stray.jpg"), "IMAGE")?;
+        fs::create_dir_all(content_dir.join("old-batch"))?;
+
+        assert_eq!(
+            verify_output(&OutputType::Batches, &content_dir, Path::new(""), &index)?,
+            vec![
+                Issue::Orphan {
+                    path: content_dir.join("batch-1/stray.jpg")
+                },
+                Issue::Orphan {
+                    path: content_dir.join("old-batch")
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_repair_media_and_pages() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/photostream");
+        let mut index = photostream(&content_dir, &["missing", "damaged", "no_page"])?;
+        fs::remove_file(content_dir.join("missing").join(PHOTOSTREAM_MEDIA_FILE))?;
+        fs::write(content_dir.join("damaged").join(PHOTOSTREAM_MEDIA_FILE), "")?;
+        fs::remove_file(content_dir.join("no_page").join(PAGE_FILE))?;
+
+        let issues = verify_photostream(&content_dir, &index)?;
+        let outcomes = repair(
+            issues,
+            &mut index,
+            &content_dir,
+            temp_dir.path(),
+            &Downloader::default(),
+            &renderer(temp_dir.path(), &content_dir),
+            |_| OrphanAction::Keep,
+        )
+        .await;
+        let outcomes: Vec<Outcome> = outcomes.into_iter().map(|(_, outcome)| outcome).collect();
+        assert_eq!(
+            outcomes,
+            vec![Outcome::Downloaded, Outcome::Downloaded, Outcome::Rendered]
+        );
+        assert!(verify_photostream(&content_dir, &index)?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_repair_orphans() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/photostream");
+        let mut index = PhotoIndex::new();
+        for dir in ["stray", "kept", "handmade"] {
+            fs::create_dir_all(content_dir.join(dir))?;
+        }
+        fs::write(
+            content_dir.join("handmade").join(PHOTOSTREAM_MEDIA_FILE),
+            "IMAGE",
+        )?;
+
+        let issues = verify_photostream(&content_dir, &index)?;
+        let outcomes = repair(
+            issues,
+            &mut index,
+            &content_dir,
+            temp_dir.path(),
+            &Downloader::default(),
+            &renderer(temp_dir.path(), &content_dir),
+            |path| match file_name(path).as_str() {
+                "handmade" => OrphanAction::Adopt,
+                "kept" => OrphanAction::Keep,
+                _ => OrphanAction::Remove,
+            },
+        )
+        .await;
+        let outcomes: Vec<Outcome> = outcomes.into_iter().map(|(_, outcome)| outcome).collect();
+        assert_eq!(
+            outcomes,
+            vec![Outcome::Adopted, Outcome::Kept, Outcome::Removed]
+        );
+
+        assert!(!content_dir.join("stray").exists());
+        assert!(content_dir.join("kept").exists());
+        // The adopted photo gets a page
+        assert!(index.get_photo("handmade").is_some());
+        assert!(content_dir.join("handmade").join(PAGE_FILE).exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_adopt_without_media_fails() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/photostream");
+        fs::create_dir_all(content_dir.join("empty"))?;
+        let mut index = PhotoIndex::new();
+
+        let issues = verify_photostream(&content_dir, &index)?;
+        let outcomes = repair(
+            issues,
+            &mut index,
+            &content_dir,
+            temp_dir.path(),
+            &Downloader::default(),
+            &renderer(temp_dir.path(), &content_dir),
+            |_| OrphanAction::Adopt,
+        )
+        .await;
+        assert!(matches!(
+            &outcomes[..],
+            [(_, Outcome::Failed(error))] if error.ends_with("has no original.jpg")
+        ));
+        assert_eq!(index.photo_count(), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_render_page_of_other_output_fails() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let content_dir = temp_dir.path().join("content/photostream");
+        let renderer = renderer(temp_dir.path(), &content_dir);
+        let index = PhotoIndex::new();
+
+        let err = renderer
+            .render(&index, &Page::Batch("b1".to_string()))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("doesn't belong to this output"));
+        let err = renderer
+            .render(&index, &Page::Photo("unknown".to_string()))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("is not in the index"));
+
+        Ok(())
+    }
+}
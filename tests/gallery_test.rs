@@ -25,6 +25,11 @@ fn create_test_photo(guid: &str) -> Photo {
         width: 800,
         height: 600,
         mime_type: "image/jpeg".to_string(),
+        contributor: None,
+        batch_guid: None,
+        batch_created_at: None,
+        asset_checksum: None,
+        sources: Vec::new(),
     }
 }
 